@@ -0,0 +1,85 @@
+//! Decoding a large synthetic save, shaped like the game's: lots of small
+//! classes and structs sharing field names, a few strings needing percent
+//! escapes, and references back to earlier objects
+//!
+//! Run with `cargo bench --bench decode`.
+
+use std::borrow::Cow;
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use mm2_tool::haxe::{from_str, Serializer, StreamDecoder, Unserializer, Value};
+use vecmap::VecMap as Map;
+
+const ITEMS: usize = 20_000;
+
+fn item(i: usize) -> Value<'static> {
+    let name = if i.is_multiple_of(10) {
+        format!("item {i} (100%)")
+    } else {
+        format!("item_{i}")
+    };
+    let fields: Map<Cow<str>, Value> = [
+        ("id".into(), Value::Int(i as i32)),
+        ("name".into(), Value::String(name.into())),
+        ("weight".into(), Value::Int((i * 7 % 1000) as i32)),
+        ("equipped".into(), Value::Bool(i.is_multiple_of(3))),
+        (
+            "stats".into(),
+            Value::Struct {
+                fields: [
+                    ("hp".into(), Value::Int(100)),
+                    ("mp".into(), Value::Int(i as i32 % 50)),
+                    ("tags".into(), Value::Array(vec![Value::Null; i % 4])),
+                ]
+                .into(),
+            },
+        ),
+    ]
+    .into();
+    Value::Class {
+        name: "game.Item".into(),
+        fields,
+    }
+}
+
+fn synthetic_save() -> String {
+    let items: Vec<_> = (0..ITEMS).map(item).collect();
+    // the same items again, which the object cache turns into references
+    let favourites = items.iter().step_by(5).cloned().collect();
+    let save = Value::Struct {
+        fields: [
+            ("inventory".into(), Value::Array(items)),
+            ("favourites".into(), Value::Array(favourites)),
+        ]
+        .into(),
+    };
+    Serializer::new().use_cache(true).encode(&[save])
+}
+
+fn decode(c: &mut Criterion) {
+    let save = synthetic_save();
+
+    let mut group = c.benchmark_group("decode");
+    group.throughput(Throughput::Bytes(save.len() as u64));
+    group.sample_size(20);
+    group.bench_function("values", |b| b.iter(|| from_str(&save).unwrap()));
+    group.bench_function("keep_references", |b| {
+        let unserializer = Unserializer::new().keep_references(true);
+        b.iter(|| unserializer.decode(&save).unwrap())
+    });
+    group.bench_function("lossless", |b| {
+        b.iter(|| Unserializer::new().decode_lossless(&save).unwrap())
+    });
+    group.bench_function("stream", |b| {
+        b.iter(|| {
+            StreamDecoder::new(save.as_bytes())
+                .next_value()
+                .unwrap()
+                .unwrap()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, decode);
+criterion_main!(benches);
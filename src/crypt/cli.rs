@@ -32,6 +32,7 @@ pub enum Command {
         file: PathBuf,
     },
     Decrypt {
+        /// File to write, or `-` to write standard output
         #[arg(short, long)]
         output: PathBuf,
 
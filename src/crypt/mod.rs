@@ -16,7 +16,11 @@ pub fn run(Cli::Crypt { key, command }: Cli) {
             let data = std::fs::read(file).unwrap();
             let data =
                 xxtea::decrypt_with_padding(data, &key).unwrap_or_else(|err| panic!("{err:?}"));
-            std::fs::write(output, data).unwrap();
+            if output.as_os_str() == "-" {
+                std::io::Write::write_all(&mut std::io::stdout().lock(), &data).unwrap();
+            } else {
+                std::fs::write(output, data).unwrap();
+            }
         }
     }
 }
@@ -2,6 +2,8 @@ use clap::{Subcommand, ValueEnum};
 
 use std::path::PathBuf;
 
+use super::{FloatFormat, Path};
+
 #[derive(Subcommand)]
 pub enum Cli {
     /// Haxe serialization and deserialization
@@ -20,6 +22,14 @@ pub enum Command {
         #[arg(short, long, value_enum, default_value_t = FileFormat::Auto)]
         format: FileFormat,
 
+        /// Write references for objects equal to ones already written
+        #[arg(long)]
+        use_cache: bool,
+
+        /// How floats are written
+        #[arg(long, value_enum, default_value_t = FloatFormat::Shortest)]
+        float_format: FloatFormat,
+
         file: PathBuf,
     },
 
@@ -30,8 +40,43 @@ pub enum Command {
         #[arg(short, long, value_enum, default_value_t = FileFormat::Auto)]
         format: FileFormat,
 
+        /// Keep object references instead of copying what they point to
+        #[arg(long)]
+        keep_references: bool,
+
+        /// File to decode, or `-` to read standard input
         file: PathBuf,
     },
+
+    /// Print the values at a path, like `0.player.inventory[*].id`
+    Query {
+        #[arg(short, long, value_enum, default_value_t = FileFormat::Auto)]
+        format: FileFormat,
+
+        /// Keep object references instead of copying what they point to
+        #[arg(long)]
+        keep_references: bool,
+
+        /// File to query, or `-` to read standard input
+        file: PathBuf,
+
+        /// Where the values are, starting with the index of a top level
+        /// value
+        path: Path,
+    },
+
+    /// Apply a json patch to a file, keeping everything it doesn't change
+    /// written the same way
+    #[cfg(feature = "export-json")]
+    Patch {
+        #[arg(short, long)]
+        output: PathBuf,
+
+        file: PathBuf,
+
+        /// Json list of operations, see `haxe::Patch`
+        patch: PathBuf,
+    },
 }
 
 #[derive(Debug, Clone, Default, ValueEnum)]
@@ -40,6 +85,17 @@ pub enum FileFormat {
     Auto,
 
     Debug,
+    /// Text notation that can be edited and encoded again, see `haxe::to_text`
+    Text,
     #[cfg(feature = "export-json")]
     Json,
+    /// Json with plain objects, numbers and arrays, see `haxe::to_plain_json`
+    #[cfg(feature = "export-json")]
+    JsonPlain,
+    /// Yaml shaped like the json, with enums as single key maps
+    #[cfg(feature = "export-yaml")]
+    Yaml,
+    /// Toml shaped like the json, with the values in a `values` array
+    #[cfg(feature = "export-toml")]
+    Toml,
 }
@@ -0,0 +1,108 @@
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    fmt,
+    sync::Arc,
+};
+
+use vecmap::VecMap as Map;
+
+use super::value::Value;
+
+/// Names the values a custom class wrote in its `hxSerialize`, so it decodes
+/// to a [`Value::CustomFields`] instead of a [`Value::Custom`]
+///
+/// Codecs are registered by class name on the
+/// [`Unserializer`](super::Unserializer),
+/// [`StreamDecoder`](super::StreamDecoder),
+/// [`typed::Deserializer`](super::typed::Deserializer) and
+/// [`Serializer`](super::Serializer). Classes without one use
+/// [`FieldArrays`].
+pub trait CustomCodec: fmt::Debug + Send + Sync {
+    /// The fields of a class from the values it wrote, or the values back if
+    /// they aren't what this codec expects
+    #[allow(clippy::type_complexity)]
+    fn decode<'a>(
+        &self,
+        raw: Vec<Value<'a>>,
+    ) -> Result<Map<Cow<'a, str>, Value<'a>>, Vec<Value<'a>>>;
+
+    /// The values `hxSerialize` writes for `fields`
+    fn encode<'v>(&self, fields: &'v Map<Cow<'v, str>, Value<'v>>) -> Vec<Encoded<'v>>;
+}
+
+pub(super) type Codecs = HashMap<String, Arc<dyn CustomCodec>>;
+
+pub(super) fn codec<'c>(codecs: &'c Codecs, name: &str) -> &'c dyn CustomCodec {
+    codecs.get(name).map_or(&FieldArrays, |codec| &**codec)
+}
+
+pub(super) fn decode<'a>(codecs: &Codecs, name: Cow<'a, str>, raw: Vec<Value<'a>>) -> Value<'a> {
+    match codec(codecs, &name).decode(raw) {
+        Ok(fields) => Value::CustomFields { name, fields },
+        Err(raw) => Value::Custom { name, raw },
+    }
+}
+
+/// A value written by [`CustomCodec::encode`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Encoded<'v> {
+    /// One of the class's fields
+    Value(&'v Value<'v>),
+    Null,
+    Bool(bool),
+    Int(i32),
+    Float(f64),
+    String(&'v str),
+    /// An array built in `hxSerialize`, which takes up a slot in the object
+    /// cache like any other
+    Array(Vec<Encoded<'v>>),
+}
+
+impl Encoded<'_> {
+    pub(super) fn is_null(&self) -> bool {
+        matches!(self, Self::Null | Self::Value(Value::Null))
+    }
+}
+
+/// How the game's classes write themselves: an array of field names followed
+/// by an array of their values
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FieldArrays;
+
+impl CustomCodec for FieldArrays {
+    fn decode<'a>(
+        &self,
+        raw: Vec<Value<'a>>,
+    ) -> Result<Map<Cow<'a, str>, Value<'a>>, Vec<Value<'a>>> {
+        let [Value::Array(names), Value::Array(values)] = raw.as_slice() else {
+            return Err(raw);
+        };
+        let mut unique = HashSet::new();
+        let names_ok = names.iter().all(|name| match name {
+            Value::String(name) => unique.insert(name),
+            _ => false,
+        });
+        if !names_ok || names.len() != values.len() {
+            return Err(raw);
+        }
+
+        let mut raw = raw.into_iter();
+        let (Some(Value::Array(names)), Some(Value::Array(values))) = (raw.next(), raw.next())
+        else {
+            unreachable!("checked above");
+        };
+        let names = names.into_iter().map(|name| match name {
+            Value::String(name) => name,
+            _ => unreachable!("checked above"),
+        });
+        Ok(names.zip(values).collect())
+    }
+
+    fn encode<'v>(&self, fields: &'v Map<Cow<'v, str>, Value<'v>>) -> Vec<Encoded<'v>> {
+        vec![
+            Encoded::Array(fields.keys().map(|k| Encoded::String(k)).collect()),
+            Encoded::Array(fields.values().map(Encoded::Value).collect()),
+        ]
+    }
+}
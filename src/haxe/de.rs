@@ -1,337 +1,1019 @@
-use std::sync::RwLock;
-use std::{borrow::Cow, rc::Rc};
+use std::{borrow::Cow, collections::VecDeque, sync::Arc};
 
 use base64::{engine::general_purpose::STANDARD, Engine as _};
-use vecmap::VecMap as Map;
 use winnow::{
     ascii::{dec_int, dec_uint, float},
-    combinator::{alt, peek, repeat},
-    error::ContextError,
-    token::{any, take},
-    Parser, Stateful,
+    combinator::{alt, peek},
+    error::{ErrMode, ErrorKind, ParserError},
+    stream::{Location, Stream, StreamIsPartial},
+    token::{any, take, take_while},
+    Located, Parser, Partial, Stateful,
 };
 
-use super::value::{float::Float, Value};
+use super::{
+    custom::{self, CustomCodec},
+    object_cache::{Cached, ObjectCache},
+    ser::DEFAULT_ENCODE_SET,
+    spelling::{self, Spelling, StringToken, Token},
+    value::{
+        date::{self, HaxeDate},
+        float::Float,
+        Value,
+    },
+    visitor::ValueBuilder,
+    Visitor,
+};
+
+// bytes of input on each side of an error in its snippet
+const CONTEXT: usize = 16;
+
+/// Why decoding failed, along with the byte offset into the input where it
+/// happened and a snippet of the surrounding input.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    #[error("invalid syntax at byte {offset} (near {snippet:?})")]
+    Syntax { offset: usize, snippet: String },
+
+    #[error("unknown tag {tag:?} at byte {offset} (near {snippet:?})")]
+    UnknownTag {
+        tag: char,
+        offset: usize,
+        snippet: String,
+    },
+
+    #[error("string cache reference R{index} at byte {offset} is out of range (near {snippet:?})")]
+    BadStringCacheRef {
+        index: usize,
+        offset: usize,
+        snippet: String,
+    },
+
+    #[error("object cache reference r{index} at byte {offset} is out of range (near {snippet:?})")]
+    BadObjectCacheRef {
+        index: usize,
+        offset: usize,
+        snippet: String,
+    },
+
+    #[error("invalid base64 in bytes at byte {offset} (near {snippet:?})")]
+    InvalidBase64 { offset: usize, snippet: String },
+
+    #[error("string at byte {offset} isn't valid utf-8 once decoded (near {snippet:?})")]
+    InvalidUtf8 { offset: usize, snippet: String },
+
+    #[error("invalid date at byte {offset} (near {snippet:?})")]
+    InvalidDate { offset: usize, snippet: String },
+
+    #[error(
+        "run of nulls at byte {offset} goes over {MAX_NULLS} nulls in total (near {snippet:?})"
+    )]
+    TooManyNulls { offset: usize, snippet: String },
+
+    #[error("value at byte {offset} is nested over {MAX_DEPTH} deep (near {snippet:?})")]
+    TooDeep { offset: usize, snippet: String },
+
+    #[error(
+        "object reference at byte {offset} goes over {MAX_COPIES} copied values in total (near {snippet:?})"
+    )]
+    TooManyCopies { offset: usize, snippet: String },
+}
+
+/// How many nulls `u<count>` runs can add up to in one input, since a few
+/// bytes could otherwise ask for any amount of memory
+pub const MAX_NULLS: usize = 1 << 20;
+
+/// How deeply values can be nested, since every level takes up some of the
+/// stack
+pub const MAX_DEPTH: usize = 128;
+
+/// How many values copying referenced objects can add up to in one input,
+/// since references to objects full of references multiply with every level
+pub const MAX_COPIES: usize = 1 << 20;
+
+impl DecodeError {
+    /// Byte offset into the input where the error happened
+    pub fn offset(&self) -> usize {
+        match self {
+            Self::Syntax { offset, .. }
+            | Self::UnknownTag { offset, .. }
+            | Self::BadStringCacheRef { offset, .. }
+            | Self::BadObjectCacheRef { offset, .. }
+            | Self::InvalidBase64 { offset, .. }
+            | Self::InvalidUtf8 { offset, .. }
+            | Self::InvalidDate { offset, .. }
+            | Self::TooManyNulls { offset, .. }
+            | Self::TooDeep { offset, .. }
+            | Self::TooManyCopies { offset, .. } => *offset,
+        }
+    }
+
+    /// The input surrounding [`DecodeError::offset`]
+    pub fn snippet(&self) -> &str {
+        match self {
+            Self::Syntax { snippet, .. }
+            | Self::UnknownTag { snippet, .. }
+            | Self::BadStringCacheRef { snippet, .. }
+            | Self::BadObjectCacheRef { snippet, .. }
+            | Self::InvalidBase64 { snippet, .. }
+            | Self::InvalidUtf8 { snippet, .. }
+            | Self::InvalidDate { snippet, .. }
+            | Self::TooManyNulls { snippet, .. }
+            | Self::TooDeep { snippet, .. }
+            | Self::TooManyCopies { snippet, .. } => snippet,
+        }
+    }
+
+    fn snippet_mut(&mut self) -> &mut String {
+        match self {
+            Self::Syntax { snippet, .. }
+            | Self::UnknownTag { snippet, .. }
+            | Self::BadStringCacheRef { snippet, .. }
+            | Self::BadObjectCacheRef { snippet, .. }
+            | Self::InvalidBase64 { snippet, .. }
+            | Self::InvalidUtf8 { snippet, .. }
+            | Self::InvalidDate { snippet, .. }
+            | Self::TooManyNulls { snippet, .. }
+            | Self::TooDeep { snippet, .. }
+            | Self::TooManyCopies { snippet, .. } => snippet,
+        }
+    }
+
+    // errors are created deep in the parser without access to the whole input,
+    // so the snippet is only filled in once parsing has stopped
+    pub(crate) fn with_snippet(mut self, input: &str) -> Self {
+        let offset = self.offset().min(input.len());
+        let mut start = offset.saturating_sub(CONTEXT);
+        while !input.is_char_boundary(start) {
+            start -= 1;
+        }
+        let mut end = (offset + CONTEXT).min(input.len());
+        while !input.is_char_boundary(end) {
+            end += 1;
+        }
+
+        *self.snippet_mut() = input[start..end].to_owned();
+        self
+    }
+
+    // the same for when only the input around the error is at hand, `bytes`
+    // being the input from byte `start` on
+    pub(crate) fn with_snippet_from(mut self, bytes: &[u8], start: usize) -> Self {
+        let offset = self.offset().clamp(start, start + bytes.len()) - start;
+        let mut bytes = &bytes[offset.saturating_sub(CONTEXT)..(offset + CONTEXT).min(bytes.len())];
+        // characters cut in half at either end are left out
+        while let [byte, rest @ ..] = bytes {
+            if byte & 0xc0 != 0x80 {
+                break;
+            }
+            bytes = rest;
+        }
+        if let Err(err) = std::str::from_utf8(bytes) {
+            if err.error_len().is_none() {
+                bytes = &bytes[..err.valid_up_to()];
+            }
+        }
+
+        *self.snippet_mut() = String::from_utf8_lossy(bytes).into_owned();
+        self
+    }
+}
+
+impl<'a, 's> ParserError<Input<'a, 's>> for DecodeError {
+    fn from_error_kind(input: &Input<'a, 's>, _kind: ErrorKind) -> Self {
+        Self::Syntax {
+            offset: input.state.base + input.location(),
+            snippet: String::new(),
+        }
+    }
+
+    fn append(
+        self,
+        _input: &Input<'a, 's>,
+        _token_start: &<Input<'a, 's> as Stream>::Checkpoint,
+        _kind: ErrorKind,
+    ) -> Self {
+        self
+    }
+}
+
+pub(super) type PResult<O> = winnow::PResult<O, DecodeError>;
 
 #[derive(Debug, Default)]
-struct ParserState<'a> {
+pub(super) struct ParserState<'a> {
+    keep_references: bool,
+    // references to an object they're inside of stay references instead of
+    // becoming nulls, so lossless decoding can write them back
+    keep_cycles: bool,
+    pub(super) source: &'a str,
+    // where `source` starts in the input, when only the end of it is kept
+    base: usize,
+    // the string cache from before `source`, when the input is decoded in
+    // parts
+    earlier_strings: &'a [String],
     string_cache: Vec<Cow<'a, str>>,
-    object_cache: Vec<Value<'a>>,
+    pub(super) object_cache: ObjectCache,
+    // objects are cached without where they start, their input isn't kept
+    drop_objects: bool,
+    // nulls from `u<count>` runs so far, see `MAX_NULLS`
+    nulls: usize,
+    // how many values are being parsed inside of each other, see
+    // `MAX_DEPTH`
+    depth: usize,
+    // where each kept reference is, only when streaming
+    ref_offsets: Option<Vec<usize>>,
+    // the spellings of the values being parsed, only when decoding
+    // losslessly
+    spellings: Option<Vec<Spelling>>,
+}
+
+impl<'a> ParserState<'a> {
+    pub(super) fn new(source: &'a str) -> Self {
+        Self {
+            source,
+            ..Self::default()
+        }
+    }
+
+    /// Count a value at `offset` being parsed inside of the ones before it,
+    /// see [`MAX_DEPTH`], and as a copy when it's for a reference
+    pub(super) fn enter(&mut self, offset: usize) -> Result<(), DecodeError> {
+        if self.depth >= MAX_DEPTH {
+            return Err(DecodeError::TooDeep {
+                offset,
+                snippet: String::new(),
+            });
+        }
+        self.depth += 1;
+
+        if self.object_cache.is_replaying() {
+            self.object_cache.count_copy()?;
+        }
+        Ok(())
+    }
+
+    pub(super) fn leave(&mut self) {
+        self.depth -= 1;
+    }
+
+    // `source` from byte `offset` on, which ends the input unless it's
+    // `partial`
+    pub(super) fn input_at(&self, offset: usize, partial: bool) -> Partial<Located<&'a str>> {
+        let mut input = Partial::new(Located::new(self.source));
+        if !partial {
+            let _ = input.complete();
+        }
+        input.next_slice(offset);
+        input
+    }
+}
+
+// the parser is single threaded, so its state is borrowed for the whole parse
+pub(super) type Input<'a, 's> = Stateful<Partial<Located<&'a str>>, &'s mut ParserState<'a>>;
+
+/// Decoding options, named after haxe's `Unserializer`
+#[derive(Debug, Clone, Default)]
+pub struct Unserializer {
+    keep_references: bool,
+    custom_codecs: custom::Codecs,
+}
+
+impl Unserializer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decode object cache references (`r<n>`) as [`Value::Ref`] instead of
+    /// copying the referenced value, so aliasing survives re-encoding
+    #[must_use]
+    pub fn keep_references(mut self, keep_references: bool) -> Self {
+        self.keep_references = keep_references;
+        self
+    }
+
+    /// Decode custom classes named `name` with `codec` instead of
+    /// [`FieldArrays`](super::FieldArrays)
+    #[must_use]
+    pub fn custom_codec(
+        mut self,
+        name: impl Into<String>,
+        codec: impl CustomCodec + 'static,
+    ) -> Self {
+        self.custom_codecs.insert(name.into(), Arc::new(codec));
+        self
+    }
+
+    /// Decode `input`, with references copied unless they're kept
+    ///
+    /// Haxe gives a reference to an object it's still decoding, such as an
+    /// array containing itself, the object as it is so far. A value can't
+    /// contain itself, so such references decode as nulls.
+    pub fn decode<'a>(&self, input: &'a str) -> Result<Vec<Value<'a>>, DecodeError> {
+        self.decode_spelled(input, false).map(|(values, _)| values)
+    }
+
+    /// Decode `input` along with how every value in it was written, so
+    /// [`Serializer::encode_lossless`](super::Serializer::encode_lossless)
+    /// can write it back byte for byte
+    ///
+    /// References to an object they're inside of are kept as
+    /// [`Value::Ref`] even when other references are copied.
+    pub fn decode_lossless<'a>(
+        &self,
+        input: &'a str,
+    ) -> Result<(Vec<Value<'a>>, Spelling), DecodeError> {
+        self.decode_spelled(input, true)
+            .map(|(values, spelling)| (values, spelling.unwrap_or_default()))
+    }
+
+    fn decode_spelled<'a>(
+        &self,
+        input: &'a str,
+        lossless: bool,
+    ) -> Result<(Vec<Value<'a>>, Option<Spelling>), DecodeError> {
+        let mut state = ParserState {
+            keep_references: self.keep_references,
+            keep_cycles: lossless,
+            // the root spelling collects one child per top level value
+            spellings: lossless.then(|| vec![Spelling::default()]),
+            ..ParserState::new(input)
+        };
+        let mut data = Input {
+            input: state.input_at(0, false),
+            state: &mut state,
+        };
+
+        let mut builder = ValueBuilder::new(&self.custom_codecs);
+        let mut values = Vec::new();
+        while !data.is_empty() {
+            parse_object(&mut data, &mut builder).map_err(|err| finish_error(err, input))?;
+            values.extend(builder.finished());
+        }
+
+        let spelling = state.spellings.and_then(|mut spellings| spellings.pop());
+        Ok((values, spelling))
+    }
+
+    /// Call `visitor` for every part of `input` as it's parsed, without
+    /// building any values
+    pub fn visit<'a>(
+        &self,
+        input: &'a str,
+        visitor: &mut (impl Visitor<'a> + ?Sized),
+    ) -> Result<(), DecodeError> {
+        // there are no values to decode again for references
+        let mut state = ParserState {
+            keep_references: true,
+            ..ParserState::new(input)
+        };
+        let mut data = Input {
+            input: state.input_at(0, false),
+            state: &mut state,
+        };
+
+        while !data.is_empty() {
+            parse_object(&mut data, visitor).map_err(|err| finish_error(err, input))?;
+        }
+
+        Ok(())
+    }
 }
 
-type Input<'st> = Stateful<&'st str, Rc<RwLock<ParserState<'st>>>>;
+pub fn from_str(input: &str) -> Result<Vec<Value<'_>>, DecodeError> {
+    Unserializer::new().decode(input)
+}
+
+/// Like [`from_str`], but the values don't borrow from `input`
+pub fn from_str_owned(input: &str) -> Result<Vec<Value<'static>>, DecodeError> {
+    from_str(input).map(|values| values.into_iter().map(Value::into_owned).collect())
+}
 
-pub fn from_str(input: &str) -> Result<Vec<Value>, ContextError> {
-    repeat(0.., parse_object)
-        .parse(Input {
-            input,
-            state: Rc::default(),
+pub fn visit<'a>(
+    input: &'a str,
+    visitor: &mut (impl Visitor<'a> + ?Sized),
+) -> Result<(), DecodeError> {
+    Unserializer::new().visit(input, visitor)
+}
+
+/// What a [`StreamDecoder`](super::StreamDecoder) has kept of its input: the
+/// text from byte `base` on, and the caches of everything before
+pub(super) struct Streamed<'a, 'c> {
+    pub(super) text: &'a str,
+    pub(super) base: usize,
+    pub(super) strings: &'a [String],
+    pub(super) object_cache: &'c mut ObjectCache,
+    pub(super) nulls: &'c mut usize,
+    // where each reference kept as an event is
+    pub(super) ref_offsets: &'c mut VecDeque<usize>,
+}
+
+impl<'a> Streamed<'a, '_> {
+    fn state(&mut self, keep_references: bool) -> ParserState<'a> {
+        ParserState {
+            keep_references,
+            base: self.base,
+            earlier_strings: self.strings,
+            object_cache: std::mem::take(self.object_cache),
+            // only objects that references are copied from need their input
+            drop_objects: keep_references,
+            nulls: *self.nulls,
+            ref_offsets: keep_references.then(Vec::new),
+            ..ParserState::new(self.text)
+        }
+    }
+
+    /// Parse the top level value at byte `offset` of the text, returning
+    /// where it ends and the strings it added to the cache, or `None` when
+    /// the text ends first and `more` of it may follow
+    pub(super) fn parse(
+        mut self,
+        offset: usize,
+        more: bool,
+        keep_references: bool,
+        visitor: &mut (impl Visitor<'a> + ?Sized),
+    ) -> Result<Option<(usize, Vec<String>)>, DecodeError> {
+        let checkpoint = self.object_cache.checkpoint();
+        let mut state = self.state(keep_references);
+        let mut data = Input {
+            input: state.input_at(offset, more),
+            state: &mut state,
+        };
+
+        let result = parse_object(&mut data, visitor);
+        let end = data.location();
+        *self.object_cache = std::mem::take(&mut state.object_cache);
+        // the caches are left as they were to parse the value again
+        if let Err(ErrMode::Incomplete(_)) = result {
+            self.object_cache.reset(checkpoint);
+            return Ok(None);
+        }
+
+        self.ref_offsets
+            .extend(state.ref_offsets.unwrap_or_default());
+        match result {
+            Ok(()) => {
+                *self.nulls = state.nulls;
+                let strings = state.string_cache.into_iter().map(Cow::into_owned);
+                Ok(Some((end, strings.collect())))
+            }
+            Err(ErrMode::Backtrack(err) | ErrMode::Cut(err)) => Err(err),
+            Err(ErrMode::Incomplete(_)) => unreachable!("returned above"),
+        }
+    }
+
+    /// Decode the object at byte `start` of the input again for a reference
+    /// at byte `offset`
+    pub(super) fn replay(
+        mut self,
+        start: usize,
+        offset: usize,
+        visitor: &mut (impl Visitor<'a> + ?Sized),
+    ) -> Result<(), DecodeError> {
+        let mut state = self.state(false);
+        let mut data = Input {
+            input: state.input_at(0, false),
+            state: &mut state,
+        };
+
+        let result = replay_object(&mut data, start, offset, visitor);
+        *self.object_cache = std::mem::take(&mut state.object_cache);
+        *self.nulls = state.nulls;
+        result.map_err(|err| match err {
+            ErrMode::Backtrack(err) | ErrMode::Cut(err) => err,
+            ErrMode::Incomplete(_) => unreachable!("replayed objects are complete"),
         })
-        .map_err(winnow::error::ParseError::into_inner)
+    }
+}
+
+pub(super) fn finish_error(err: ErrMode<DecodeError>, input: &str) -> DecodeError {
+    match err {
+        ErrMode::Backtrack(err) | ErrMode::Cut(err) => err.with_snippet(input),
+        ErrMode::Incomplete(_) => unreachable!("input is never partial"),
+    }
+}
+
+// where parsing is in the whole input
+fn position(data: &Input<'_, '_>) -> usize {
+    data.state.base + data.location()
+}
+
+pub(super) fn reserve_object_cache(data: &mut Input<'_, '_>, start: usize) -> Option<usize> {
+    if data.state.drop_objects {
+        data.state.object_cache.reserve_dropped()
+    } else {
+        data.state.object_cache.reserve(start)
+    }
+}
+
+pub(super) fn fill_object_cache(data: &mut Input<'_, '_>, index: Option<usize>) {
+    let end = position(data);
+    data.state.object_cache.fill(index, end);
+}
+
+pub(super) fn push_object_cache(data: &mut Input<'_, '_>, start: usize) {
+    let index = reserve_object_cache(data, start);
+    fill_object_cache(data, index);
+}
+
+fn cut<O>(err: DecodeError) -> PResult<O> {
+    Err(ErrMode::Cut(err))
+}
+
+pub(super) fn parse_object<'a, V: Visitor<'a> + ?Sized>(
+    data: &mut Input<'a, '_>,
+    visitor: &mut V,
+) -> PResult<()> {
+    let offset = position(data);
+    data.state.enter(offset).map_err(ErrMode::Cut)?;
+    let result = parse_spelled(data, visitor);
+    data.state.leave();
+    result
+}
+
+fn parse_spelled<'a, V: Visitor<'a> + ?Sized>(
+    data: &mut Input<'a, '_>,
+    visitor: &mut V,
+) -> PResult<()> {
+    if data.state.spellings.is_none() {
+        return parse_tagged(data, visitor);
+    }
+
+    let start = data.location();
+    with_spellings(data, |spellings| spellings.push(Spelling::default()));
+    parse_tagged(data, visitor)?;
+    let end = data.location();
+
+    let state = &mut *data.state;
+    let raw = &state.source[start..end];
+    let spellings = state.spellings.as_mut().expect("checked above");
+    let mut spelling = spellings.pop().expect("pushed above");
+    spelling.token = spelling::token(raw, state.keep_references);
+    spellings
+        .last_mut()
+        .expect("the root is never popped")
+        .children
+        .push(spelling);
+    Ok(())
 }
 
-fn parse_object<'a>(data: &mut Input<'a>) -> winnow::PResult<Value<'a>> {
-    Ok(match peek(any).parse_next(data)? {
+// runs `f` on the stack of spellings being recorded, if any
+fn with_spellings(data: &mut Input<'_, '_>, f: impl FnOnce(&mut Vec<Spelling>)) {
+    if let Some(spellings) = &mut data.state.spellings {
+        f(spellings);
+    }
+}
+
+fn record_string(data: &mut Input<'_, '_>, token: StringToken) {
+    with_spellings(data, |spellings| {
+        if let Some(spelling) = spellings.last_mut() {
+            spelling.strings.push(token);
+        }
+    });
+}
+
+// one value, without counting it as nested
+pub(super) fn parse_tagged<'a, V: Visitor<'a> + ?Sized>(
+    data: &mut Input<'a, '_>,
+    visitor: &mut V,
+) -> PResult<()> {
+    // each kind of value has its own function, so values nested inside of
+    // each other only take up the stack of the kinds they are
+    match peek(any).parse_next(data)? {
+        'a' | 'l' | 'b' | 'q' | 'M' | 'o' | 'c' | 'C' => parse_container(data, visitor),
+        'w' | 'j' | 'x' => parse_wrapper(data, visitor),
+        'r' => parse_object_cache_reference(data, visitor),
+        _ => parse_scalar(data, visitor),
+    }
+}
+
+fn parse_scalar<'a, V: Visitor<'a> + ?Sized>(
+    data: &mut Input<'a, '_>,
+    visitor: &mut V,
+) -> PResult<()> {
+    let offset = position(data);
+    match peek(any).parse_next(data)? {
         'n' => {
-            data.input = &data[1..];
-            return Ok(Value::Null);
+            any.parse_next(data)?;
+            visitor.visit_null();
         }
         'z' => {
-            data.input = &data[1..];
-            Value::Int(0)
+            any.parse_next(data)?;
+            visitor.visit_int(0);
+        }
+        'i' => {
+            'i'.parse_next(data)?;
+            visitor.visit_int(dec_int.parse_next(data)?);
+        }
+        'd' => {
+            'd'.parse_next(data)?;
+            visitor.visit_float(Float::new(float.parse_next(data)?));
         }
-        'i' => parse_int(data)?,
-        'd' => parse_float(data)?,
         'k' => {
-            data.input = &data[1..];
-            Value::Float(Float::Nan)
+            any.parse_next(data)?;
+            visitor.visit_float(Float::Nan);
         }
         'm' => {
-            data.input = &data[1..];
-            Value::Float(Float::NegativeInfinity)
+            any.parse_next(data)?;
+            visitor.visit_float(Float::NegativeInfinity);
         }
         'p' => {
-            data.input = &data[1..];
-            Value::Float(Float::PositiveInfinity)
+            any.parse_next(data)?;
+            visitor.visit_float(Float::PositiveInfinity);
         }
         't' => {
-            data.input = &data[1..];
-            Value::Bool(true)
+            any.parse_next(data)?;
+            visitor.visit_bool(true);
         }
         'f' => {
-            data.input = &data[1..];
-            Value::Bool(false)
-        }
-        'y' => Value::String(parse_string_literal(data)?),
-        'l' => parse_list(data)?,
-        'a' => parse_array(data)?,
-        'v' => parse_date(data)?,
-        'b' => parse_string_map(data)?,
-        'q' => parse_int_map(data)?,
-        'M' => parse_object_map(data)?,
-        's' => parse_bytes(data)?,
-        'x' => parse_exception(data)?,
-        'o' => parse_struct(data)?,
-        'c' => parse_class(data)?,
-        'w' => parse_enum(data)?,
-        'j' => todo!("https://github.com/HaxeFoundation/haxe/blob/dc1a43dc52f98b9c480f68264885c6155e570f3e/std/haxe/Unserializer.hx#L325"),
-        'R' => Value::String(parse_string_cache_reference(data)?),
-        'r' => parse_int_cache_reference(data)?,
-        'C' => parse_custom(data)?,
-        unknown => todo!("{unknown:?}"),
-    })
+            any.parse_next(data)?;
+            visitor.visit_bool(false);
+        }
+        'y' => visitor.visit_string(parse_string_literal(data)?),
+        'R' => {
+            let (index, s) = parse_string_cache_entry(data)?;
+            visitor.string_ref(index, s);
+        }
+        'v' => {
+            'v'.parse_next(data)?;
+            let date = parse_date_body(data)?;
+            push_object_cache(data, offset);
+            visitor.visit_date(date);
+        }
+        's' => visitor.visit_bytes(parse_bytes(data)?),
+        tag => {
+            return cut(DecodeError::UnknownTag {
+                tag,
+                offset,
+                snippet: String::new(),
+            })
+        }
+    }
+
+    Ok(())
 }
 
-fn parse_int<'a>(data: &mut Input<'a>) -> winnow::PResult<Value<'a>> {
-    'i'.parse_next(data)?;
-    Ok(Value::Int(dec_int.parse_next(data)?))
+fn parse_container<'a, V: Visitor<'a> + ?Sized>(
+    data: &mut Input<'a, '_>,
+    visitor: &mut V,
+) -> PResult<()> {
+    let offset = position(data);
+    match peek(any).parse_next(data)? {
+        'a' => {
+            let cache_index = reserve_object_cache(data, offset);
+            'a'.parse_next(data)?;
+            visitor.begin_array();
+            parse_array_items(data, visitor)?;
+            visitor.end_array();
+            fill_object_cache(data, cache_index);
+        }
+        'l' => {
+            let cache_index = reserve_object_cache(data, offset);
+            'l'.parse_next(data)?;
+            visitor.begin_list();
+            while data.bytes().next() != Some(b'h') {
+                parse_object(data, visitor)?;
+            }
+            'h'.parse_next(data)?;
+            visitor.end_list();
+            fill_object_cache(data, cache_index);
+        }
+        'b' => {
+            let cache_index = reserve_object_cache(data, offset);
+            'b'.parse_next(data)?;
+            visitor.begin_string_map();
+            parse_fields(data, visitor, b'h')?;
+            visitor.end_string_map();
+            fill_object_cache(data, cache_index);
+        }
+        'q' => {
+            let cache_index = reserve_object_cache(data, offset);
+            'q'.parse_next(data)?;
+            visitor.begin_int_map();
+            while data.bytes().next() != Some(b'h') {
+                ':'.parse_next(data)?;
+                visitor.int_key(dec_int.parse_next(data)?);
+                parse_object(data, visitor)?;
+            }
+            'h'.parse_next(data)?;
+            visitor.end_int_map();
+            fill_object_cache(data, cache_index);
+        }
+        'M' => {
+            let cache_index = reserve_object_cache(data, offset);
+            'M'.parse_next(data)?;
+            visitor.begin_object_map();
+            while data.bytes().next() != Some(b'h') {
+                parse_object(data, visitor)?;
+                parse_object(data, visitor)?;
+            }
+            'h'.parse_next(data)?;
+            visitor.end_object_map();
+            fill_object_cache(data, cache_index);
+        }
+        'o' => {
+            let cache_index = reserve_object_cache(data, offset);
+            'o'.parse_next(data)?;
+            visitor.begin_struct();
+            parse_fields(data, visitor, b'g')?;
+            visitor.end_struct();
+            fill_object_cache(data, cache_index);
+        }
+        'c' => {
+            let cache_index = reserve_object_cache(data, offset);
+            'c'.parse_next(data)?;
+            visitor.begin_class(parse_string(data)?);
+            parse_fields(data, visitor, b'g')?;
+            visitor.end_class();
+            fill_object_cache(data, cache_index);
+        }
+        'C' => {
+            let cache_index = reserve_object_cache(data, offset);
+            'C'.parse_next(data)?;
+            visitor.begin_custom(parse_string(data)?);
+            // whatever `hxSerialize` wrote, which is always more serialized
+            // values in the files seen so far
+            while data.bytes().next() != Some(b'g') {
+                parse_object(data, visitor)?;
+            }
+            'g'.parse_next(data)?;
+            visitor.end_custom();
+            fill_object_cache(data, cache_index);
+        }
+        _ => unreachable!("picked by `parse_tagged`"),
+    }
+
+    Ok(())
 }
 
-fn parse_float<'a>(data: &mut Input<'a>) -> winnow::PResult<Value<'a>> {
-    'd'.parse_next(data)?;
-    float.map(Float::new).map(Value::Float).parse_next(data)
+// enums and exceptions
+fn parse_wrapper<'a, V: Visitor<'a> + ?Sized>(
+    data: &mut Input<'a, '_>,
+    visitor: &mut V,
+) -> PResult<()> {
+    let offset = position(data);
+    match peek(any).parse_next(data)? {
+        'w' => {
+            'w'.parse_next(data)?;
+            let name = parse_string(data)?;
+            let constructor = parse_string(data)?;
+            ':'.parse_next(data)?;
+            let len: usize = dec_uint.parse_next(data)?;
+            visitor.begin_enum(name, constructor, len);
+            for _ in 0..len {
+                parse_object(data, visitor)?;
+            }
+            push_object_cache(data, offset);
+            visitor.end_enum();
+        }
+        'j' => {
+            'j'.parse_next(data)?;
+            let name = parse_string(data)?;
+            ':'.parse_next(data)?;
+            let index: usize = dec_uint.parse_next(data)?;
+            ':'.parse_next(data)?;
+            let len: usize = dec_uint.parse_next(data)?;
+            visitor.begin_enum_index(name, index, len);
+            for _ in 0..len {
+                parse_object(data, visitor)?;
+            }
+            push_object_cache(data, offset);
+            visitor.end_enum();
+        }
+        'x' => {
+            'x'.parse_next(data)?;
+            visitor.begin_exception();
+            parse_object(data, visitor)?;
+            visitor.end_exception();
+        }
+        _ => unreachable!("picked by `parse_tagged`"),
+    }
+
+    Ok(())
 }
 
-fn parse_string<'a>(data: &mut Input<'a>) -> winnow::PResult<Cow<'a, str>> {
+pub(super) fn parse_string<'a>(data: &mut Input<'a, '_>) -> PResult<Cow<'a, str>> {
     alt((parse_string_literal, parse_string_cache_reference)).parse_next(data)
 }
 
-fn parse_string_literal<'a>(data: &mut Input<'a>) -> winnow::PResult<Cow<'a, str>> {
+fn parse_string_literal<'a>(data: &mut Input<'a, '_>) -> PResult<Cow<'a, str>> {
     'y'.parse_next(data)?;
     let len: usize = dec_uint.parse_next(data)?;
     ':'.parse_next(data)?;
-    let s = take(len).parse_next(data)?;
-    let s = percent_encoding::percent_decode_str(s)
-        .decode_utf8()
-        .unwrap();
-    data.state.write().unwrap().string_cache.push(s.clone());
-    Ok(s)
-}
-
-fn parse_list<'a>(data: &mut Input<'a>) -> winnow::PResult<Value<'a>> {
-    'l'.parse_next(data)?;
-    let mut items = Vec::new();
-    while data.bytes().next() != Some(b'h') {
-        let item = parse_object(data)?;
-        items.push(item);
+    let offset = position(data);
+    let raw: &'a str = take(len).parse_next(data)?;
+    let s = if raw.contains('%') {
+        match percent_encoding::percent_decode_str(raw).decode_utf8() {
+            Ok(s) => s,
+            Err(_) => {
+                return cut(DecodeError::InvalidUtf8 {
+                    offset,
+                    snippet: String::new(),
+                })
+            }
+        }
+    } else {
+        Cow::Borrowed(raw)
+    };
+    let state = &mut *data.state;
+    if let Some(spelling) = state.spellings.as_mut().and_then(|s| s.last_mut()) {
+        // only keep the literal when the serializer wouldn't write it the same
+        let encoded: Cow<'_, str> =
+            percent_encoding::percent_encode(s.as_bytes(), DEFAULT_ENCODE_SET).into();
+        let raw = (encoded != raw).then(|| raw.into());
+        spelling.strings.push(StringToken::Literal(raw));
     }
-    'h'.parse_next(data)?;
-
-    let obj = Value::List(items);
-    data.state.write().unwrap().object_cache.push(obj.clone());
-    Ok(obj)
+    if !state.object_cache.is_replaying() {
+        state.string_cache.push(s.clone());
+    }
+    Ok(s)
 }
 
-fn parse_array<'a>(data: &mut Input<'a>) -> winnow::PResult<Value<'a>> {
-    'a'.parse_next(data)?;
-    let mut items = Vec::new();
+// the items of an array up to and including its `h`
+fn parse_array_items<'a, V: Visitor<'a> + ?Sized>(
+    data: &mut Input<'a, '_>,
+    visitor: &mut V,
+) -> PResult<()> {
     while data.bytes().next() != Some(b'h') {
         if data.bytes().next() == Some(b'u') {
-            'u'.parse_next(data)?;
-            let count: usize = dec_uint.parse_next(data)?;
-            for _ in 0..count {
-                items.push(Value::Null);
-            }
+            let count = parse_null_run(data)?;
+            visitor.null_run(count);
+            with_spellings(data, |spellings| {
+                let children = &mut spellings.last_mut().expect("inside a value").children;
+                children.extend((0..count).map(|i| Spelling {
+                    token: if i == 0 {
+                        Token::Nulls(count)
+                    } else {
+                        Token::InNulls
+                    },
+                    ..Spelling::default()
+                }));
+            });
         } else {
-            let item = parse_object(data)?;
-            items.push(item);
+            let lone_null = data.bytes().next() == Some(b'n');
+            parse_object(data, visitor)?;
+            if lone_null {
+                with_spellings(data, |spellings| {
+                    let children = &mut spellings.last_mut().expect("inside a value").children;
+                    if let Some(child) = children.last_mut() {
+                        child.token = Token::Null;
+                    }
+                });
+            }
         }
     }
     'h'.parse_next(data)?;
-    let obj = Value::Array(items);
-    data.state.write().unwrap().object_cache.push(obj.clone());
-    Ok(obj)
+    Ok(())
 }
 
-fn parse_date<'a>(data: &mut Input<'a>) -> winnow::PResult<Value<'a>> {
-    'v'.parse_next(data)?;
-
-    // let year = dec_uint.parse_next(data)?;
-    // '-'.parse_next(data)?;
-    // let month = dec_uint.parse_next(data)?;
-    // '-'.parse_next(data)?;
-    // let day = dec_uint.parse_next(data)?;
-    // ' '.parse_next(data)?;
-    // let hour = dec_uint.parse_next(data)?;
-    // ':'.parse_next(data)?;
-    // let minute = dec_uint.parse_next(data)?;
-    // ':'.parse_next(data)?;
-    // let second = dec_uint.parse_next(data)?;
-
-    let date_str = take(19_usize).parse_next(data)?;
-    Ok(Value::Date(date_str.into()))
-}
-
-fn parse_string_map<'a>(data: &mut Input<'a>) -> winnow::PResult<Value<'a>> {
-    'b'.parse_next(data)?;
-    let mut map = Map::new();
-    while data.bytes().next() != Some(b'h') {
-        let key = parse_string(data)?;
-        let value = parse_object(data)?;
-        map.insert(key, value);
+// the count of a `u<count>` run, which is checked against `MAX_NULLS`
+pub(super) fn parse_null_run(data: &mut Input<'_, '_>) -> PResult<usize> {
+    let offset = position(data);
+    'u'.parse_next(data)?;
+    let count: usize = dec_uint.parse_next(data)?;
+    data.state.nulls = data.state.nulls.saturating_add(count);
+    if data.state.nulls > MAX_NULLS {
+        return cut(DecodeError::TooManyNulls {
+            offset,
+            snippet: String::new(),
+        });
     }
-    'h'.parse_next(data)?;
-    let obj = Value::StringMap(map);
-    data.state.write().unwrap().object_cache.push(obj.clone());
-    Ok(obj)
+    Ok(count)
 }
 
-fn parse_int_map<'a>(data: &mut Input<'a>) -> winnow::PResult<Value<'a>> {
-    'q'.parse_next(data)?;
-    let mut map = Map::new();
-    while data.bytes().next() != Some(b'h') {
-        ':'.parse_next(data)?;
-        let key = dec_int.parse_next(data)?;
-        let value = parse_object(data)?;
-        map.insert(key, value);
+pub(super) fn parse_date_body(data: &mut Input<'_, '_>) -> PResult<HaxeDate> {
+    let offset = position(data);
+    let head: Vec<u8> = data.bytes().take(5).collect();
+    let date = if date::is_local_form(&head) {
+        take(19_usize).parse_next(data)?
+    } else {
+        take_while(1.., |c: char| {
+            c.is_ascii() && date::is_timestamp_byte(c as u8)
+        })
+        .parse_next(data)?
+    };
+
+    match date.parse() {
+        Ok(date) => Ok(date),
+        Err(_) => cut(DecodeError::InvalidDate {
+            offset,
+            snippet: String::new(),
+        }),
     }
-    'h'.parse_next(data)?;
-    let obj = Value::IntMap(map);
-    data.state.write().unwrap().object_cache.push(obj.clone());
-    Ok(obj)
 }
 
-fn parse_object_map<'a>(data: &mut Input<'a>) -> winnow::PResult<Value<'a>> {
-    'M'.parse_next(data)?;
-    let mut map = Map::new();
-    while data.bytes().next() != Some(b'h') {
-        let key = parse_object(data)?;
-        let value = parse_object(data)?;
-        map.insert(key, value);
+// the keys and values of a struct, class or string map up to and including
+// `end`
+fn parse_fields<'a, V: Visitor<'a> + ?Sized>(
+    data: &mut Input<'a, '_>,
+    visitor: &mut V,
+    end: u8,
+) -> PResult<()> {
+    while data.bytes().next() != Some(end) {
+        visitor.field_name(parse_string(data)?);
+        parse_object(data, visitor)?;
     }
-    'h'.parse_next(data)?;
-    Ok(Value::ObjectMap(map))
+    any.parse_next(data)?;
+    Ok(())
 }
 
-fn parse_bytes<'a>(data: &mut Input<'a>) -> winnow::PResult<Value<'a>> {
+pub(super) fn parse_bytes(data: &mut Input<'_, '_>) -> PResult<Vec<u8>> {
+    let start = position(data);
     's'.parse_next(data)?;
     let len: usize = dec_uint.parse_next(data)?;
     ':'.parse_next(data)?;
+    let offset = position(data);
     let bytes = take(len).parse_next(data)?;
-    let bytes = STANDARD.decode(bytes).unwrap();
-    let obj = Value::Bytes(bytes);
-    data.state.write().unwrap().object_cache.push(obj.clone());
-    Ok(obj)
+    let Ok(bytes) = STANDARD.decode(bytes) else {
+        return cut(DecodeError::InvalidBase64 {
+            offset,
+            snippet: String::new(),
+        });
+    };
+    push_object_cache(data, start);
+    Ok(bytes)
 }
 
-fn parse_exception<'a>(_data: &mut Input<'a>) -> winnow::PResult<Value<'a>> {
-    todo!()
-    // 'x'.parse_next(data)?;
-    // let exception_str = take_while(|c: char| c.is_alphanumeric()).parse_next(data)?;
-    // Ok(Object::Exception(exception_str))
+fn parse_string_cache_reference<'a>(data: &mut Input<'a, '_>) -> PResult<Cow<'a, str>> {
+    parse_string_cache_entry(data).map(|(_, s)| s)
 }
 
-fn parse_struct<'a>(data: &mut Input<'a>) -> winnow::PResult<Value<'a>> {
-    'o'.parse_next(data)?;
-    let mut fields = Map::new();
-    while data.bytes().next() != Some(b'g') {
-        let key = parse_string(data)?;
-        let value = parse_object(data)?;
-        fields.insert(key, value);
-    }
-    'g'.parse_next(data)?;
-
-    let obj = Value::Struct { fields };
-    data.state.write().unwrap().object_cache.push(obj.clone());
-    Ok(obj)
-}
-
-fn parse_class<'a>(data: &mut Input<'a>) -> winnow::PResult<Value<'a>> {
-    'c'.parse_next(data)?;
-    let name = parse_string(data)?;
-    let mut fields = Map::new();
-    while data.bytes().next() != Some(b'g') {
-        let key = parse_string(data)?;
-        let value = parse_object(data)?;
-        fields.insert(key, value);
+fn parse_string_cache_entry<'a>(data: &mut Input<'a, '_>) -> PResult<(usize, Cow<'a, str>)> {
+    let offset = position(data);
+    'R'.parse_next(data)?;
+    let index: usize = dec_uint.parse_next(data)?;
+    let state = &*data.state;
+    let s = match index.checked_sub(state.earlier_strings.len()) {
+        Some(index) => state.string_cache.get(index).cloned(),
+        None => Some(Cow::Borrowed(state.earlier_strings[index].as_str())),
+    };
+    match s {
+        Some(s) => {
+            record_string(data, StringToken::Ref(index));
+            Ok((index, s))
+        }
+        None => cut(DecodeError::BadStringCacheRef {
+            index,
+            offset,
+            snippet: String::new(),
+        }),
     }
-    'g'.parse_next(data)?;
-
-    let obj = Value::Class { name, fields };
-    data.state.write().unwrap().object_cache.push(obj.clone());
-    Ok(obj)
 }
 
-fn parse_enum<'a>(data: &mut Input<'a>) -> winnow::PResult<Value<'a>> {
-    'w'.parse_next(data)?;
-    let name = parse_string(data)?;
-    let constructor = parse_string(data)?;
-    ':'.parse_next(data)?;
-    let mut fields = Vec::new();
-    let count: usize = dec_uint.parse_next(data)?;
-    for _ in 0..count {
-        let field = parse_object(data)?;
-        fields.push(field);
+fn parse_object_cache_reference<'a, V: Visitor<'a> + ?Sized>(
+    data: &mut Input<'a, '_>,
+    visitor: &mut V,
+) -> PResult<()> {
+    let offset = position(data);
+    match parse_object_cache_entry(data)? {
+        (index, _) if data.state.keep_references => {
+            if let Some(offsets) = &mut data.state.ref_offsets {
+                offsets.push(offset);
+            }
+            visitor.object_ref(index);
+        }
+        (_, Cached::Object { start }) => replay_object(data, start, offset, visitor)?,
+        // haxe gives back the object as far as it's decoded, so the
+        // reference is a real cycle, which only a kept reference can hold
+        (index, Cached::Unfinished) if data.state.keep_cycles => visitor.object_ref(index),
+        (_, Cached::Unfinished) => visitor.visit_null(),
+        (_, Cached::Dropped) => unreachable!("only kept references point at dropped objects"),
     }
-
-    let obj = Value::Enum {
-        name,
-        constructor,
-        fields,
-    };
-
-    data.state.write().unwrap().object_cache.push(obj.clone());
-    Ok(obj)
+    Ok(())
 }
 
-fn parse_string_cache_reference<'a>(data: &mut Input<'a>) -> winnow::PResult<Cow<'a, str>> {
-    'R'.parse_next(data)?;
+// `r<index>` and what it points at
+pub(super) fn parse_object_cache_entry(data: &mut Input<'_, '_>) -> PResult<(usize, Cached)> {
+    let offset = position(data);
+    'r'.parse_next(data)?;
     let index: usize = dec_uint.parse_next(data)?;
-    let string_cache = &data.state.read().unwrap().string_cache;
 
-    // TODO: maybe the strings should be under an Rc?
-    Ok(string_cache.get(index).unwrap().clone())
+    match data.state.object_cache.get(index, offset) {
+        // there's nothing to copy from objects whose input wasn't kept
+        Some(Cached::Dropped) if !data.state.keep_references => {}
+        Some(cached) => return Ok((index, cached)),
+        None => {}
+    }
+    cut(DecodeError::BadObjectCacheRef {
+        index,
+        offset,
+        snippet: String::new(),
+    })
 }
 
-fn parse_int_cache_reference<'a>(data: &mut Input<'a>) -> winnow::PResult<Value<'a>> {
-    'r'.parse_next(data)?;
-    let index: usize = dec_uint.parse_next(data)?;
-    let object_cache = &data.state.read().unwrap().object_cache;
-
-    Ok(object_cache.get(index).unwrap().clone())
-}
-
-fn parse_custom<'a>(data: &mut Input<'a>) -> winnow::PResult<Value<'a>> {
-    'C'.parse_next(data)?;
-    let name = parse_string.parse_next(data)?;
-    // technically after the class there is arbitrary data, but from testing
-    // the data I care about custom sections just contains more serialized haxe
-    // data.
-    //
-    // That data is always two arrays one with strings that are field
-    // name, and another with the same number of elements of the last array
-    // with each fields value. If this ever caues a panic on deserialization
-    // I'll have to rethink this xd
-    let fields = {
-        let fields = parse_array.parse_next(data)?;
-        let Value::Array(fields) = fields else {
-            return Err(winnow::error::ErrMode::Cut(ContextError::new()));
-        };
-        fields
-            .into_iter()
-            .map(|obj| {
-                let Value::String(s) = obj else {
-                    return Err(winnow::error::ErrMode::Cut(ContextError::new()));
-                };
-
-                winnow::PResult::Ok(s)
-            })
-            .collect::<winnow::PResult<Vec<_>>>()?
-    };
-    let values = {
-        let values = parse_array.parse_next(data)?;
-        let Value::Array(values) = values else {
-            return Err(winnow::error::ErrMode::Cut(ContextError::new()));
-        };
-        values
-    };
-    'g'.parse_next(data)?;
-
-    assert_eq!(fields.len(), values.len());
-    let obj = Value::Custom {
-        name,
-        fields: fields.into_iter().zip(values).collect(),
-    };
-    data.state.write().unwrap().object_cache.push(obj.clone());
-    Ok(obj)
+// decodes the object at `start` again for the reference at `offset`, with
+// the caches as they were left
+fn replay_object<'a, V: Visitor<'a> + ?Sized>(
+    data: &mut Input<'a, '_>,
+    start: usize,
+    offset: usize,
+    visitor: &mut V,
+) -> PResult<()> {
+    let input = data.state.input_at(start - data.state.base, false);
+    let resume = std::mem::replace(&mut data.input, input);
+    let spellings = data.state.spellings.take();
+    data.state.object_cache.begin_replay(offset);
+    let result = parse_object(data, visitor);
+    data.state.object_cache.end_replay();
+    data.state.spellings = spellings;
+    data.input = resume;
+    result
 }
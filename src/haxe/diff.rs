@@ -0,0 +1,251 @@
+use std::{borrow::Cow, fmt, hash::Hash};
+
+use vecmap::VecMap as Map;
+
+use super::{value::path::Segments, Segment, Value};
+
+/// How one value differs from another, see [`diff`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Diff<'v, 'a> {
+    Same,
+
+    /// A different scalar, or a different kind of value altogether: another
+    /// variant, class name or enum constructor
+    Replaced {
+        old: &'v Value<'a>,
+        new: &'v Value<'a>,
+    },
+
+    /// The same kind of container with changes to its entries, in the order
+    /// they're stored
+    Entries(Vec<Entry<'v, 'a>>),
+}
+
+/// A changed entry of a container, keyed the same way as a
+/// [`Path`](super::Path)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry<'v, 'a> {
+    pub key: Segment,
+    pub change: Change<'v, 'a>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change<'v, 'a> {
+    Added(&'v Value<'a>),
+    Removed(&'v Value<'a>),
+    /// Never [`Diff::Same`]
+    Changed(Diff<'v, 'a>),
+}
+
+/// The changes that turn `old` into `new`
+///
+/// Structs, classes, maps and enums with the same constructor are compared
+/// entry by entry. Arrays and lists are compared item by item after skipping
+/// the items they start and end with in common, so inserting or removing
+/// items only shows up once. Their removed and changed items are keyed by
+/// their index in `old`, added ones by their index in `new`.
+pub fn diff<'v, 'a>(old: &'v Value<'a>, new: &'v Value<'a>) -> Diff<'v, 'a> {
+    if old == new {
+        return Diff::Same;
+    }
+
+    let entries = match (old, new) {
+        (Value::Array(old), Value::Array(new)) | (Value::List(old), Value::List(new)) => {
+            diff_items(old, new)
+        }
+        (Value::StringMap(old), Value::StringMap(new))
+        | (Value::Struct { fields: old }, Value::Struct { fields: new }) => diff_fields(old, new),
+        (
+            Value::Class { name, fields: old },
+            Value::Class {
+                name: new_name,
+                fields: new,
+            },
+        )
+        | (
+            Value::CustomFields { name, fields: old },
+            Value::CustomFields {
+                name: new_name,
+                fields: new,
+            },
+        ) if name == new_name => diff_fields(old, new),
+        (Value::IntMap(old), Value::IntMap(new)) => {
+            diff_maps(old, new, |key| Segment::Int((*key).into()))
+        }
+        (Value::ObjectMap(old), Value::ObjectMap(new)) => {
+            diff_maps(old, new, |key| Segment::Object(key.to_owned()))
+        }
+        (
+            Value::Enum {
+                name,
+                constructor,
+                fields: old,
+            },
+            Value::Enum {
+                name: new_name,
+                constructor: new_constructor,
+                fields: new,
+            },
+        ) if (name, constructor) == (new_name, new_constructor) => diff_items(old, new),
+        (
+            Value::EnumIndex {
+                name,
+                index,
+                fields: old,
+            },
+            Value::EnumIndex {
+                name: new_name,
+                index: new_index,
+                fields: new,
+            },
+        ) if (name, index) == (new_name, new_index) => diff_items(old, new),
+        (
+            Value::Custom { name, raw: old },
+            Value::Custom {
+                name: new_name,
+                raw: new,
+            },
+        ) if name == new_name => diff_items(old, new),
+        _ => return Diff::Replaced { old, new },
+    };
+    Diff::Entries(entries)
+}
+
+fn diff_items<'v, 'a>(old: &'v [Value<'a>], new: &'v [Value<'a>]) -> Vec<Entry<'v, 'a>> {
+    let prefix = old
+        .iter()
+        .zip(new)
+        .take_while(|(old, new)| old == new)
+        .count();
+    let (old_rest, new_rest) = (&old[prefix..], &new[prefix..]);
+    let suffix = old_rest
+        .iter()
+        .rev()
+        .zip(new_rest.iter().rev())
+        .take_while(|(old, new)| old == new)
+        .count();
+    let old_rest = &old_rest[..old_rest.len() - suffix];
+    let new_rest = &new_rest[..new_rest.len() - suffix];
+
+    let key = |index: usize| Segment::Int((prefix + index) as i64);
+    let mut entries = Vec::new();
+    for (index, (old, new)) in old_rest.iter().zip(new_rest).enumerate() {
+        if let Some(change) = changed(old, new) {
+            entries.push(Entry {
+                key: key(index),
+                change,
+            });
+        }
+    }
+    let common = old_rest.len().min(new_rest.len());
+    entries.extend(
+        old_rest
+            .iter()
+            .enumerate()
+            .skip(common)
+            .map(|(index, old)| Entry {
+                key: key(index),
+                change: Change::Removed(old),
+            }),
+    );
+    entries.extend(
+        new_rest
+            .iter()
+            .enumerate()
+            .skip(common)
+            .map(|(index, new)| Entry {
+                key: key(index),
+                change: Change::Added(new),
+            }),
+    );
+    entries
+}
+
+fn diff_fields<'v, 'a>(
+    old: &'v Map<Cow<'a, str>, Value<'a>>,
+    new: &'v Map<Cow<'a, str>, Value<'a>>,
+) -> Vec<Entry<'v, 'a>> {
+    diff_maps(old, new, |key| Segment::Name(key.as_ref().to_owned()))
+}
+
+// entries are listed in the order of `old`, with added ones after
+fn diff_maps<'v, 'a, K: Eq + Hash>(
+    old: &'v Map<K, Value<'a>>,
+    new: &'v Map<K, Value<'a>>,
+    key: impl Fn(&K) -> Segment,
+) -> Vec<Entry<'v, 'a>> {
+    let mut entries = Vec::new();
+    for (k, old) in old {
+        let change = match new.get(k) {
+            Some(new) => changed(old, new),
+            None => Some(Change::Removed(old)),
+        };
+        if let Some(change) = change {
+            entries.push(Entry {
+                key: key(k),
+                change,
+            });
+        }
+    }
+    entries.extend(
+        new.iter()
+            .filter(|(k, _)| !old.contains_key(*k))
+            .map(|(k, new)| Entry {
+                key: key(k),
+                change: Change::Added(new),
+            }),
+    );
+    entries
+}
+
+fn changed<'v, 'a>(old: &'v Value<'a>, new: &'v Value<'a>) -> Option<Change<'v, 'a>> {
+    match diff(old, new) {
+        Diff::Same => None,
+        diff => Some(Change::Changed(diff)),
+    }
+}
+
+impl Diff<'_, '_> {
+    pub fn is_same(&self) -> bool {
+        matches!(self, Diff::Same)
+    }
+}
+
+/// One line per change: `~ path: old -> new`, `+ path: new` or `- path: old`
+impl fmt::Display for Diff<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_diff(f, &mut Vec::new(), self)
+    }
+}
+
+fn write_diff(f: &mut fmt::Formatter<'_>, path: &mut Vec<Segment>, diff: &Diff) -> fmt::Result {
+    match diff {
+        Diff::Same => Ok(()),
+        Diff::Replaced { old, new } => write_line(f, '~', path, format_args!("{old:?} -> {new:?}")),
+        Diff::Entries(entries) => {
+            for Entry { key, change } in entries {
+                path.push(key.clone());
+                match change {
+                    Change::Added(new) => write_line(f, '+', path, format_args!("{new:?}"))?,
+                    Change::Removed(old) => write_line(f, '-', path, format_args!("{old:?}"))?,
+                    Change::Changed(diff) => write_diff(f, path, diff)?,
+                }
+                path.pop();
+            }
+            Ok(())
+        }
+    }
+}
+
+fn write_line(
+    f: &mut fmt::Formatter<'_>,
+    sign: char,
+    path: &[Segment],
+    value: fmt::Arguments,
+) -> fmt::Result {
+    if path.is_empty() {
+        writeln!(f, "{sign} {value}")
+    } else {
+        writeln!(f, "{sign} {}: {value}", Segments(path))
+    }
+}
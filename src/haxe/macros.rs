@@ -0,0 +1,141 @@
+/// Build a [`Value`](crate::haxe::Value) from something that reads like the
+/// haxe it stands for
+///
+/// ```
+/// use mm2_tool::haxe;
+///
+/// let item = haxe!({ path: "a.png", type: "IMAGE", sizes: [1, null, -2] });
+/// let point = haxe!(class game.Point { x: 1, y: 2.5 });
+/// let color = haxe!(enum Color.Rgb(255, 0, item));
+/// let empty = haxe!(class "" {});
+/// # let _ = (point, color, empty);
+/// ```
+///
+/// - `null` is [`Value::Null`](crate::haxe::Value::Null)
+/// - `[a, b]` is an array
+/// - `{ name: a, "other name": b }` is a struct
+/// - `class pkg.Name { name: a }` is a class, its name can be a string
+///   literal too
+/// - `enum pkg.Name.Constructor(a, b)` is an enum, with fields in parentheses
+///   if it has any, or `enum "Name"."Constructor"` to name it with string
+///   literals
+///
+/// Anything else is an expression converted with `Value::from`, so it can be
+/// a number, bool, string, another value, or a `Vec` or map of them.
+#[macro_export]
+macro_rules! haxe {
+    ($($value:tt)+) => {
+        $crate::haxe_internal!(@value $($value)+)
+    };
+}
+
+// munches lists of values and fields a token at a time, since a value can be
+// any number of tokens up to the next comma
+#[macro_export]
+#[doc(hidden)]
+macro_rules! haxe_internal {
+    (@value null) => {
+        $crate::haxe::Value::Null
+    };
+
+    (@value [$($items:tt)*]) => {
+        $crate::haxe::Value::Array($crate::haxe_internal!(@items [] () $($items)*))
+    };
+
+    (@value {$($fields:tt)*}) => {
+        $crate::haxe::Value::Struct {
+            fields: $crate::haxe_internal!(@fields [] $($fields)*),
+        }
+    };
+
+    (@value class $name:literal {$($fields:tt)*}) => {
+        $crate::haxe::Value::Class {
+            name: ::std::borrow::Cow::Borrowed($name),
+            fields: $crate::haxe_internal!(@fields [] $($fields)*),
+        }
+    };
+
+    (@value class $first:ident $(. $rest:ident)* {$($fields:tt)*}) => {
+        $crate::haxe::Value::Class {
+            name: ::std::borrow::Cow::Borrowed($crate::haxe_internal!(@name $first $($rest)*)),
+            fields: $crate::haxe_internal!(@fields [] $($fields)*),
+        }
+    };
+
+    (@value enum $name:literal . $constructor:literal $(($($items:tt)*))?) => {
+        $crate::haxe::Value::Enum {
+            name: ::std::borrow::Cow::Borrowed($name),
+            constructor: ::std::borrow::Cow::Borrowed($constructor),
+            fields: $crate::haxe_internal!(@items [] () $($($items)*)?),
+        }
+    };
+
+    (@value enum $first:ident $(. $rest:ident)+ $(($($items:tt)*))?) => {{
+        // the last part of the path is the constructor
+        let (name, constructor) = $crate::haxe_internal!(@name $first $($rest)+)
+            .rsplit_once('.')
+            .unwrap();
+        $crate::haxe::Value::Enum {
+            name: ::std::borrow::Cow::Borrowed(name),
+            constructor: ::std::borrow::Cow::Borrowed(constructor),
+            fields: $crate::haxe_internal!(@items [] () $($($items)*)?),
+        }
+    }};
+
+    (@value $other:expr) => {
+        $crate::haxe::Value::from($other)
+    };
+
+    // a dotted path of identifiers as a `&'static str`
+    (@name $first:ident $($rest:ident)*) => {
+        concat!(stringify!($first) $(, ".", stringify!($rest))*)
+    };
+
+    // items: [done] (tokens of the current item) rest
+    (@items [$($done:expr,)*] ()) => {
+        ::std::vec![$($done,)*]
+    };
+
+    (@items [$($done:expr,)*] ($($item:tt)+)) => {
+        ::std::vec![$($done,)* $crate::haxe_internal!(@value $($item)+),]
+    };
+
+    (@items [$($done:expr,)*] ($($item:tt)+) , $($rest:tt)*) => {
+        $crate::haxe_internal!(@items [$($done,)* $crate::haxe_internal!(@value $($item)+),] () $($rest)*)
+    };
+
+    (@items [$($done:expr,)*] ($($item:tt)*) $next:tt $($rest:tt)*) => {
+        $crate::haxe_internal!(@items [$($done,)*] ($($item)* $next) $($rest)*)
+    };
+
+    // fields: [done] rest, then [done] (key) (tokens of the current value) rest
+    (@fields []) => {
+        ::std::default::Default::default()
+    };
+
+    (@fields [$(($key:expr, $value:expr),)*]) => {
+        [$((::std::borrow::Cow::Borrowed($key), $value)),*]
+            .into_iter()
+            .collect()
+    };
+
+    (@fields [$($done:tt)*] $key:ident : $($rest:tt)*) => {
+        $crate::haxe_internal!(@field [$($done)*] (stringify!($key)) () $($rest)*)
+    };
+
+    (@fields [$($done:tt)*] $key:literal : $($rest:tt)*) => {
+        $crate::haxe_internal!(@field [$($done)*] ($key) () $($rest)*)
+    };
+
+    (@field [$($done:tt)*] ($key:expr) ($($value:tt)+)) => {
+        $crate::haxe_internal!(@fields [$($done)* ($key, $crate::haxe_internal!(@value $($value)+)),])
+    };
+
+    (@field [$($done:tt)*] ($key:expr) ($($value:tt)+) , $($rest:tt)*) => {
+        $crate::haxe_internal!(@fields [$($done)* ($key, $crate::haxe_internal!(@value $($value)+)),] $($rest)*)
+    };
+
+    (@field [$($done:tt)*] ($key:expr) ($($value:tt)*) $next:tt $($rest:tt)*) => {
+        $crate::haxe_internal!(@field [$($done)*] ($key) ($($value)* $next) $($rest)*)
+    };
+}
@@ -1,14 +1,45 @@
 pub mod cli;
+mod custom;
 mod de;
+mod diff;
+mod macros;
+mod object_cache;
+mod patch;
+#[cfg(feature = "export-json")]
+mod plain_json;
 mod ser;
+mod spelling;
+pub mod stream;
+mod text;
+pub mod typed;
 mod value;
+mod visitor;
 
 #[cfg(test)]
 mod tests;
 
-pub use de::from_str;
-pub use ser::to_string;
-pub use value::Value;
+pub use custom::{CustomCodec, Encoded, FieldArrays};
+pub use de::{
+    from_str, from_str_owned, visit, DecodeError, Unserializer, MAX_COPIES, MAX_DEPTH, MAX_NULLS,
+};
+pub use diff::{diff, Change, Diff, Entry};
+pub use patch::{Operation, Patch, PatchError};
+#[cfg(feature = "export-json")]
+pub(crate) use plain_json::values_from_plain_json;
+#[cfg(feature = "export-json")]
+pub use plain_json::{from_plain_json, to_plain_json, PlainJsonError};
+pub use ser::{to_string, FloatFormat, Serializer, DEFAULT_ENCODE_SET, JS_ENCODE_SET};
+pub use spelling::Spelling;
+pub use stream::{Event, StreamDecoder};
+pub use text::{from_text, to_text, TextError};
+pub use typed::{from_str_typed, to_string_typed};
+pub use value::{
+    convert::{TypeError, ValueIndex},
+    date::{DateError, HaxeDate, LocalDate},
+    path::{Path, PathError, Segment},
+    Value,
+};
+pub use visitor::Visitor;
 
 pub use cli::{Cli, Command};
 pub fn run(Cli::Haxe { command }: Cli) {
@@ -21,11 +52,13 @@ pub fn run(Cli::Haxe { command }: Cli) {
             file,
             output,
             format,
+            use_cache,
+            float_format,
         } => {
             let format = FileFormat::guess(format, &file);
             if let FileFormat::Debug = format {
                 eprintln!("Error: a format is required when serializing");
-                return;
+                std::process::exit(1);
             }
 
             let data = std::fs::read(file).unwrap();
@@ -33,20 +66,64 @@ pub fn run(Cli::Haxe { command }: Cli) {
             let value: Vec<Value> = match format {
                 FileFormat::Debug => unreachable!(),
 
+                FileFormat::Text => match from_text(std::str::from_utf8(&data).unwrap()) {
+                    Ok(values) => values,
+                    Err(err) => {
+                        eprintln!("Error: {err}");
+                        std::process::exit(1);
+                    }
+                },
+
                 #[cfg(feature = "export-json")]
                 FileFormat::Json => serde_json::from_slice(&data).unwrap(),
+
+                #[cfg(feature = "export-json")]
+                FileFormat::JsonPlain => {
+                    let json = serde_json::from_slice(&data).unwrap();
+                    match values_from_plain_json(&json) {
+                        Ok(values) => values,
+                        Err(err) => {
+                            eprintln!("Error: {err}");
+                            std::process::exit(1);
+                        }
+                    }
+                }
+
+                #[cfg(feature = "export-yaml")]
+                FileFormat::Yaml => match from_yaml(&data) {
+                    Ok(values) => values,
+                    Err(err) => {
+                        eprintln!("Error: {err}");
+                        std::process::exit(1);
+                    }
+                },
+
+                #[cfg(feature = "export-toml")]
+                FileFormat::Toml => match from_toml(std::str::from_utf8(&data).unwrap()) {
+                    Ok(values) => values,
+                    Err(err) => {
+                        eprintln!("Error: {err}");
+                        std::process::exit(1);
+                    }
+                },
             };
 
-            std::fs::write(output, to_string(&value)).unwrap();
+            let serializer = Serializer::new()
+                .use_cache(use_cache)
+                .float_format(float_format);
+            let output = std::io::BufWriter::new(std::fs::File::create(output).unwrap());
+            serializer.encode_to_writer(&value, output).unwrap();
         }
 
         Command::Decode {
             file,
             output,
             format,
+            keep_references,
         } => {
-            let data = std::fs::read_to_string(file).unwrap();
-            let obj = from_str(&data).unwrap();
+            let Some(obj) = read_values(&file, keep_references) else {
+                std::process::exit(1);
+            };
 
             #[cfg_attr(not(feature = "export-json"), allow(unused_variables))]
             let byte_vec_spot: Vec<u8>;
@@ -58,23 +135,227 @@ pub fn run(Cli::Haxe { command }: Cli) {
                     string_spot.as_bytes()
                 }
 
+                FileFormat::Text => {
+                    string_spot = to_text(&obj);
+                    string_spot.as_bytes()
+                }
+
                 #[cfg(feature = "export-json")]
                 FileFormat::Json => {
                     byte_vec_spot = serde_json::to_vec_pretty(&obj).unwrap();
                     byte_vec_spot.as_slice()
                 }
+
+                #[cfg(feature = "export-json")]
+                FileFormat::JsonPlain => {
+                    let json: Vec<_> = obj.iter().map(to_plain_json).collect();
+                    byte_vec_spot = serde_json::to_vec_pretty(&json).unwrap();
+                    byte_vec_spot.as_slice()
+                }
+
+                #[cfg(feature = "export-yaml")]
+                FileFormat::Yaml => {
+                    string_spot = to_yaml(&obj).unwrap();
+                    string_spot.as_bytes()
+                }
+
+                #[cfg(feature = "export-toml")]
+                FileFormat::Toml => {
+                    string_spot = to_toml(&obj).unwrap();
+                    string_spot.as_bytes()
+                }
             };
 
             std::fs::write(output, bytes).unwrap();
         }
+
+        Command::Query {
+            file,
+            format,
+            keep_references,
+            path,
+        } => {
+            let Some(obj) = read_values(&file, keep_references) else {
+                std::process::exit(1);
+            };
+            // top level values are indexed like an array
+            let obj = Value::Array(obj);
+            let selected = obj.select_all(&path);
+            if selected.is_empty() {
+                // scripts tell a missing path apart by the exit code
+                eprintln!("Error: nothing at {path}");
+                std::process::exit(1);
+            }
+
+            // there's no output file to guess from
+            let format = FileFormat::guess(format, std::path::Path::new(""));
+            for value in selected {
+                match format {
+                    FileFormat::Debug => println!("{value:#?}"),
+                    FileFormat::Text => print!("{}", to_text(std::slice::from_ref(value))),
+
+                    #[cfg(feature = "export-json")]
+                    FileFormat::Json => {
+                        println!("{}", serde_json::to_string_pretty(value).unwrap())
+                    }
+
+                    #[cfg(feature = "export-json")]
+                    FileFormat::JsonPlain => {
+                        let json = to_plain_json(value);
+                        println!("{}", serde_json::to_string_pretty(&json).unwrap())
+                    }
+
+                    #[cfg(feature = "export-yaml")]
+                    FileFormat::Yaml => print!("{}", to_yaml(value).unwrap()),
+
+                    // a toml file is a table, so this is one too
+                    #[cfg(feature = "export-toml")]
+                    FileFormat::Toml => {
+                        print!("{}", to_toml(std::slice::from_ref(value)).unwrap())
+                    }
+                }
+            }
+        }
+
+        #[cfg(feature = "export-json")]
+        Command::Patch {
+            file,
+            patch,
+            output,
+        } => {
+            // a patch that didn't apply fails the command, so scripts notice
+            let Some(patch) = read_patch(&patch) else {
+                std::process::exit(1);
+            };
+            let data = std::fs::read_to_string(file).unwrap();
+            let (mut values, spelling) = match Unserializer::new().decode_lossless(&data) {
+                Ok(decoded) => decoded,
+                Err(err) => {
+                    eprintln!("Error: {err}");
+                    std::process::exit(1);
+                }
+            };
+            if let Err(err) = patch.apply_to_all(&mut values) {
+                eprintln!("Error: {err}");
+                std::process::exit(1);
+            }
+
+            let data = Serializer::new().encode_lossless(&values, &spelling);
+            std::fs::write(output, data).unwrap();
+        }
+    }
+}
+
+// reads a json patch, printing any error
+#[cfg(feature = "export-json")]
+pub(crate) fn read_patch(file: &std::path::Path) -> Option<Patch> {
+    let data = std::fs::read(file).unwrap();
+    match serde_json::from_slice(&data) {
+        Ok(patch) => Some(patch),
+        Err(err) => {
+            eprintln!("Error: invalid patch: {err}");
+            None
+        }
+    }
+}
+
+// enums are written as single key maps like in json, since yaml tags can't be
+// nested and floats are enums inside of `Value::Float`
+#[cfg(feature = "export-yaml")]
+pub(crate) fn to_yaml<T: serde::Serialize>(value: &T) -> Result<String, serde_yaml_ng::Error> {
+    let mut yaml = Vec::new();
+    let mut serializer = serde_yaml_ng::Serializer::new(&mut yaml);
+    serde_yaml_ng::with::singleton_map_recursive::serialize(value, &mut serializer)?;
+    Ok(String::from_utf8(yaml).unwrap())
+}
+
+#[cfg(feature = "export-yaml")]
+pub(crate) fn from_yaml<'de, T: serde::Deserialize<'de>>(
+    yaml: &'de [u8],
+) -> Result<T, serde_yaml_ng::Error> {
+    let deserializer = serde_yaml_ng::Deserializer::from_slice(yaml);
+    serde_yaml_ng::with::singleton_map_recursive::deserialize(deserializer)
+}
+
+// a toml file has to be a table, so the values go in its `values` array the
+// same way a save's do
+#[cfg(feature = "export-toml")]
+pub(crate) fn to_toml(values: &[Value]) -> Result<String, toml::ser::Error> {
+    #[derive(serde::Serialize)]
+    struct Values<'v, 'a> {
+        values: &'v [Value<'a>],
+    }
+
+    to_toml_table(&Values { values })
+}
+
+// toml writes a table's plain keys before its subtables, which would reorder
+// fields and map entries, so everything under the file's own keys is written
+// as inline tables, one array item per line
+#[cfg(feature = "export-toml")]
+pub(crate) fn to_toml_table<T: serde::Serialize>(table: &T) -> Result<String, toml::ser::Error> {
+    use serde::Serialize;
+
+    let mut toml = String::new();
+    for (key, value) in toml::Table::try_from(table)? {
+        toml.push_str(&key);
+        toml.push_str(" = ");
+        match value {
+            toml::Value::Array(items) => {
+                toml.push_str("[\n");
+                for item in items {
+                    toml.push_str("    ");
+                    item.serialize(toml::ser::ValueSerializer::new(&mut toml))?;
+                    toml.push_str(",\n");
+                }
+                toml.push(']');
+            }
+            value => value.serialize(toml::ser::ValueSerializer::new(&mut toml))?,
+        }
+        toml.push('\n');
+    }
+    Ok(toml)
+}
+
+#[cfg(feature = "export-toml")]
+pub(crate) fn from_toml(toml: &str) -> Result<Vec<Value<'static>>, toml::de::Error> {
+    #[derive(serde::Deserialize)]
+    struct Values {
+        values: Vec<Value<'static>>,
+    }
+
+    toml::from_str(toml).map(|Values { values }| values)
+}
+
+// decodes a file, or standard input for `-`, printing any error
+fn read_values(file: &std::path::Path, keep_references: bool) -> Option<Vec<Value<'static>>> {
+    let reader: Box<dyn std::io::BufRead> = if file.as_os_str() == "-" {
+        Box::new(std::io::stdin().lock())
+    } else {
+        Box::new(std::io::BufReader::new(std::fs::File::open(file).unwrap()))
+    };
+    let decoder = StreamDecoder::new(reader).keep_references(keep_references);
+    match decoder.collect::<Result<Vec<_>, _>>() {
+        Ok(values) => Some(values),
+        Err(err) => {
+            eprintln!("Error: {err}");
+            None
+        }
     }
 }
 
 #[derive(Debug, Clone)]
 pub enum FileFormat {
     Debug,
+    Text,
     #[cfg(feature = "export-json")]
     Json,
+    #[cfg(feature = "export-json")]
+    JsonPlain,
+    #[cfg(feature = "export-yaml")]
+    Yaml,
+    #[cfg(feature = "export-toml")]
+    Toml,
 }
 
 impl FileFormat {
@@ -85,11 +366,23 @@ impl FileFormat {
         match (format, extension) {
             #[cfg(feature = "export-json")]
             (cli::FileFormat::Json, _) => FileFormat::Json,
+            #[cfg(feature = "export-json")]
+            (cli::FileFormat::JsonPlain, _) => FileFormat::JsonPlain,
+            #[cfg(feature = "export-yaml")]
+            (cli::FileFormat::Yaml, _) => FileFormat::Yaml,
+            #[cfg(feature = "export-toml")]
+            (cli::FileFormat::Toml, _) => FileFormat::Toml,
             (cli::FileFormat::Debug, _) => FileFormat::Debug,
+            (cli::FileFormat::Text, _) => FileFormat::Text,
 
             #[cfg(feature = "export-json")]
             (Auto, Some("json")) => FileFormat::Json,
+            #[cfg(feature = "export-yaml")]
+            (Auto, Some("yaml" | "yml")) => FileFormat::Yaml,
+            #[cfg(feature = "export-toml")]
+            (Auto, Some("toml")) => FileFormat::Toml,
             (Auto, Some("debug")) => FileFormat::Debug,
+            (Auto, Some("hxt")) => FileFormat::Text,
             (Auto, _) => FileFormat::Debug,
         }
     }
@@ -0,0 +1,151 @@
+//! Haxe's object cache as the decoders keep it
+
+use super::{DecodeError, MAX_COPIES};
+
+/// The objects read so far, by their index in haxe's object cache
+///
+/// References are resolved by decoding the referenced object again from where
+/// it starts in the input, rather than keeping a copy of every object around
+/// in case it's referenced. Haxe caches containers before decoding their
+/// contents, so references inside of them can point back at the container.
+/// Everything else is cached once it's fully decoded.
+#[derive(Debug, Default)]
+pub(super) struct ObjectCache {
+    objects: Vec<CachedObject>,
+    // where the first object with a known start starts
+    first_start: Option<usize>,
+    // while decoding a referenced object again the cache is already filled
+    replaying: usize,
+    // where the outermost reference being decoded again is
+    replay_offset: usize,
+    // values decoded again for references so far, see `MAX_COPIES`
+    copies: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct CachedObject {
+    // where it starts in the input, unless the decoder didn't keep that part
+    start: Option<usize>,
+    // where it ended, references from inside of it are cycles
+    end: Option<usize>,
+}
+
+/// What an object reference points at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Cached {
+    /// A finished object, to be decoded again from `start`
+    Object { start: usize },
+    /// An object the reference is inside of, which haxe gives back as far as
+    /// it's decoded
+    Unfinished,
+    /// A finished object whose input wasn't kept
+    Dropped,
+}
+
+/// The cache as it was, to go back to with [`ObjectCache::reset`]
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Checkpoint {
+    len: usize,
+    first_start: Option<usize>,
+    copies: usize,
+}
+
+impl ObjectCache {
+    pub(super) fn is_replaying(&self) -> bool {
+        self.replaying > 0
+    }
+
+    /// Where the earliest object that can be decoded again starts, input
+    /// before it is never read again
+    pub(super) fn first_start(&self) -> Option<usize> {
+        self.first_start
+    }
+
+    /// Cache the object starting at `start` before its contents are decoded,
+    /// returning its index unless it's being decoded again
+    pub(super) fn reserve(&mut self, start: usize) -> Option<usize> {
+        self.reserve_at(Some(start))
+    }
+
+    /// Like [`ObjectCache::reserve`] for an object whose input isn't kept
+    pub(super) fn reserve_dropped(&mut self) -> Option<usize> {
+        self.reserve_at(None)
+    }
+
+    fn reserve_at(&mut self, start: Option<usize>) -> Option<usize> {
+        if self.is_replaying() {
+            return None;
+        }
+
+        if let Some(start) = start {
+            let first = self.first_start.get_or_insert(start);
+            *first = start.min(*first);
+        }
+        self.objects.push(CachedObject { start, end: None });
+        Some(self.objects.len() - 1)
+    }
+
+    /// Mark a reserved object as finished at `end`
+    pub(super) fn fill(&mut self, index: Option<usize>, end: usize) {
+        if let Some(index) = index {
+            self.objects[index].end = Some(end);
+        }
+    }
+
+    /// Cache an object that's already fully decoded
+    pub(super) fn push(&mut self, start: usize, end: usize) {
+        let index = self.reserve(start);
+        self.fill(index, end);
+    }
+
+    /// What `r<index>` at `offset` points at, `None` when it's out of range
+    pub(super) fn get(&self, index: usize, offset: usize) -> Option<Cached> {
+        let object = self.objects.get(index)?;
+        Some(match (object.start, object.end) {
+            (Some(start), Some(end)) if end <= offset => Cached::Object { start },
+            (None, Some(end)) if end <= offset => Cached::Dropped,
+            _ => Cached::Unfinished,
+        })
+    }
+
+    /// Decode a referenced object again for the reference at `offset`,
+    /// without caching anything in it
+    pub(super) fn begin_replay(&mut self, offset: usize) {
+        if self.replaying == 0 {
+            self.replay_offset = offset;
+        }
+        self.replaying += 1;
+    }
+
+    pub(super) fn end_replay(&mut self) {
+        self.replaying -= 1;
+    }
+
+    /// Count a value decoded again for a reference, failing once there have
+    /// been more than [`MAX_COPIES`]
+    pub(super) fn count_copy(&mut self) -> Result<(), DecodeError> {
+        self.copies += 1;
+        if self.copies > MAX_COPIES {
+            return Err(DecodeError::TooManyCopies {
+                offset: self.replay_offset,
+                snippet: String::new(),
+            });
+        }
+        Ok(())
+    }
+
+    pub(super) fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            len: self.objects.len(),
+            first_start: self.first_start,
+            copies: self.copies,
+        }
+    }
+
+    /// Forget everything cached since `checkpoint`
+    pub(super) fn reset(&mut self, checkpoint: Checkpoint) {
+        self.objects.truncate(checkpoint.len);
+        self.first_start = checkpoint.first_start;
+        self.copies = checkpoint.copies;
+    }
+}
@@ -0,0 +1,278 @@
+use std::{borrow::Cow, mem};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    value::path::{Keys, Segment},
+    Path, Value,
+};
+
+/// Edits to make to a value, in order, like a JSON Patch
+///
+/// Paths start at the value the patch is applied to, so for a file of
+/// several values they start with the index of one. Written as json a patch
+/// is a list of operations:
+///
+/// ```json
+/// [
+///     { "op": "set", "path": "0.player.gold", "value": { "Int": 500 }, "old": { "Int": 20 } },
+///     { "op": "remove", "path": "0.player.debuffs[1]" },
+///     { "op": "insert", "path": "0.player.inventory[0]", "value": { "Int": 7 } },
+///     { "op": "move", "from": "0.player.pet", "path": "0.pets[0]" }
+/// ]
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Patch {
+    pub operations: Vec<Operation>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum Operation {
+    /// Replace the value at `path`, or add a field or map entry there
+    Set {
+        path: Path,
+        value: Value<'static>,
+        /// What has to be at `path` beforehand, if anything
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        old: Option<Value<'static>>,
+    },
+
+    /// Remove the item, field or map entry at `path`
+    Remove {
+        path: Path,
+        /// What has to be at `path` beforehand, if anything
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        old: Option<Value<'static>>,
+    },
+
+    /// Insert an item into an array or list before the index `path` ends
+    /// with, which may be its length
+    Insert { path: Path, value: Value<'static> },
+
+    /// Remove the value at `from`, then insert it at `path` if that's in an
+    /// array or list, or set it there otherwise
+    Move { from: Path, path: Path },
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum PatchError {
+    #[error("operation {index}: nothing at {path}")]
+    NotFound { index: usize, path: Path },
+
+    #[error("operation {index}: expected {expected:?} at {path}, found {found:?}")]
+    Mismatch {
+        index: usize,
+        path: Path,
+        expected: Box<Value<'static>>,
+        found: Box<Value<'static>>,
+    },
+
+    #[error("operation {index}: can't {op} at {path}")]
+    InvalidTarget {
+        index: usize,
+        op: &'static str,
+        path: Path,
+    },
+}
+
+impl From<Vec<Operation>> for Patch {
+    fn from(operations: Vec<Operation>) -> Self {
+        Self { operations }
+    }
+}
+
+impl Patch {
+    /// Apply every operation in order, leaving `value` as it was if any of
+    /// them fail
+    pub fn apply(&self, value: &mut Value<'_>) -> Result<(), PatchError> {
+        let mut patched = value.clone();
+        for (index, operation) in self.operations.iter().enumerate() {
+            operation.apply(&mut patched, index)?;
+        }
+        *value = patched;
+        Ok(())
+    }
+
+    /// [`Patch::apply`] to the top level values of a file, which paths index
+    /// like an array
+    pub fn apply_to_all(&self, values: &mut Vec<Value<'_>>) -> Result<(), PatchError> {
+        let mut root = Value::Array(mem::take(values));
+        let result = self.apply(&mut root);
+        let Value::Array(patched) = root else {
+            unreachable!("operations can't replace the value they're applied to");
+        };
+        *values = patched;
+        result
+    }
+}
+
+impl Operation {
+    fn apply(&self, root: &mut Value<'_>, index: usize) -> Result<(), PatchError> {
+        match self {
+            Operation::Set { path, value, old } => {
+                if old.is_some() {
+                    check_old(root, path, old.as_ref(), index)?;
+                }
+                find_parent(root, path)
+                    .and_then(|(parent, key)| set(parent, key, value.clone()))
+                    .map_err(|err| err.into_error(index, "set", path))
+            }
+            Operation::Remove { path, old } => {
+                check_old(root, path, old.as_ref(), index)?;
+                find_parent(root, path)
+                    .and_then(|(parent, key)| remove(parent, key))
+                    .map(|_| ())
+                    .map_err(|err| err.into_error(index, "remove", path))
+            }
+            Operation::Insert { path, value } => find_parent(root, path)
+                .and_then(|(parent, key)| insert(parent, key, value.clone()))
+                .map_err(|err| err.into_error(index, "insert", path)),
+            Operation::Move { from, path } => {
+                let value = find_parent(root, from)
+                    .and_then(|(parent, key)| remove(parent, key))
+                    .map_err(|err| err.into_error(index, "move", from))?;
+                find_parent(root, path)
+                    .and_then(|(parent, key)| match parent {
+                        Value::Array(_) | Value::List(_) => insert(parent, key, value),
+                        _ => set(parent, key, value),
+                    })
+                    .map_err(|err| err.into_error(index, "move", path))
+            }
+        }
+    }
+}
+
+fn check_old(
+    root: &Value,
+    path: &Path,
+    old: Option<&Value>,
+    index: usize,
+) -> Result<(), PatchError> {
+    let Some(found) = root.get_path(path) else {
+        return Err(PatchError::NotFound {
+            index,
+            path: path.clone(),
+        });
+    };
+    match old {
+        Some(expected) if expected != found => Err(PatchError::Mismatch {
+            index,
+            path: path.clone(),
+            expected: Box::new(expected.to_owned()),
+            found: Box::new(found.to_owned()),
+        }),
+        _ => Ok(()),
+    }
+}
+
+// the value containing what `path` points at, and its key in there
+fn find_parent<'v, 'a, 'p>(
+    root: &'v mut Value<'a>,
+    path: &'p Path,
+) -> Result<(&'v mut Value<'a>, &'p Segment), EntryError> {
+    let Some((key, parents)) = path.segments().split_last() else {
+        return Err(EntryError::Invalid);
+    };
+    if path.segments().contains(&Segment::Wildcard) {
+        return Err(EntryError::Invalid);
+    }
+    let parent = root.get_path_mut_from(parents);
+    Ok((parent.ok_or(EntryError::NotFound)?, key))
+}
+
+enum EntryError {
+    NotFound,
+    Invalid,
+}
+
+impl EntryError {
+    fn into_error(self, index: usize, op: &'static str, path: &Path) -> PatchError {
+        let path = path.clone();
+        match self {
+            Self::NotFound => PatchError::NotFound { index, path },
+            Self::Invalid => PatchError::InvalidTarget { index, op, path },
+        }
+    }
+}
+
+fn items<'v, 'a>(value: &'v mut Value<'a>) -> Option<&'v mut Vec<Value<'a>>> {
+    match value {
+        Value::Array(items)
+        | Value::List(items)
+        | Value::Enum { fields: items, .. }
+        | Value::EnumIndex { fields: items, .. }
+        | Value::Custom { raw: items, .. } => Some(items),
+        _ => None,
+    }
+}
+
+fn set<'a>(parent: &mut Value<'a>, key: &Segment, value: Value<'a>) -> Result<(), EntryError> {
+    let keys = Keys::new(key);
+    if let Some(items) = items(parent) {
+        let index = keys.index.ok_or(EntryError::Invalid)?;
+        let item = items.get_mut(index).ok_or(EntryError::NotFound)?;
+        *item = value;
+        return Ok(());
+    }
+
+    match parent {
+        Value::StringMap(fields)
+        | Value::Struct { fields }
+        | Value::Class { fields, .. }
+        | Value::CustomFields { fields, .. } => {
+            let key = keys.string.ok_or(EntryError::Invalid)?;
+            fields.insert(Cow::Owned(key.to_owned()), value);
+        }
+        Value::IntMap(map) => {
+            map.insert(keys.int.ok_or(EntryError::Invalid)?, value);
+        }
+        Value::ObjectMap(map) => {
+            let mut candidates = keys.objects().peekable();
+            let first = candidates.peek().cloned().ok_or(EntryError::Invalid)?;
+            let key = candidates
+                .find(|key| map.contains_key(key))
+                .unwrap_or(first);
+            map.insert(key, value);
+        }
+        _ => return Err(EntryError::Invalid),
+    }
+    Ok(())
+}
+
+fn remove<'a>(parent: &mut Value<'a>, key: &Segment) -> Result<Value<'a>, EntryError> {
+    let keys = Keys::new(key);
+    if let Some(items) = items(parent) {
+        let index = keys.index.ok_or(EntryError::Invalid)?;
+        if index >= items.len() {
+            return Err(EntryError::NotFound);
+        }
+        return Ok(items.remove(index));
+    }
+
+    let removed = match parent {
+        Value::StringMap(fields)
+        | Value::Struct { fields }
+        | Value::Class { fields, .. }
+        | Value::CustomFields { fields, .. } => {
+            fields.remove(keys.string.ok_or(EntryError::Invalid)?)
+        }
+        Value::IntMap(map) => map.remove(&keys.int.ok_or(EntryError::Invalid)?),
+        Value::ObjectMap(map) => keys.objects().find_map(|key| map.remove(&key)),
+        _ => return Err(EntryError::Invalid),
+    };
+    removed.ok_or(EntryError::NotFound)
+}
+
+fn insert<'a>(parent: &mut Value<'a>, key: &Segment, value: Value<'a>) -> Result<(), EntryError> {
+    let (Value::Array(items) | Value::List(items)) = parent else {
+        return Err(EntryError::Invalid);
+    };
+    let index = Keys::new(key).index.ok_or(EntryError::Invalid)?;
+    if index > items.len() {
+        return Err(EntryError::NotFound);
+    }
+    items.insert(index, value);
+    Ok(())
+}
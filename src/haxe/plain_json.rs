@@ -0,0 +1,399 @@
+//! Json that reads like the data rather than like [`Value`]
+//!
+//! The json [`Value`] serializes to says which variant every value is:
+//! `{"Struct":{"fields":{"a":{"Int":1}}}}`. In plain json structs are
+//! objects, ints and floats are numbers, strings are strings and arrays are
+//! arrays, so that's `{"a":1}`. Everything else is an object with a type
+//! hint, a key starting with `$`:
+//!
+//! - `{"$class": "game.Item", "id": 3}` and `{"$custom": "Point", "x": 1}`
+//!   for classes and custom classes with fields
+//! - `{"$custom": "Point", "$raw": [1, 2]}` for custom classes without
+//! - `{"$enum": "Color.Rgb", "$fields": [0, 0, 255]}`, or `"Mode.2"` for an
+//!   enum written by index, `$fields` is left out if there aren't any
+//! - `{"$float": "nan"}`, `"inf"` or `"-inf"`
+//! - `{"$date": "2024-01-01 10:00:00"}` or milliseconds since the epoch
+//! - `{"$bytes": "base64"}`
+//! - `{"$list": [...]}`, `{"$stringmap": {...}}`, `{"$intmap": {"1": ...}}`
+//!   and `{"$objectmap": [[key, value], ...]}`
+//! - `{"$exception": value}` and `{"$ref": 3}`
+//!
+//! Structs, classes and custom classes with a field starting with `$` keep
+//! their fields in a `$fields` object instead, like `{"$fields": {"$a": 1}}`.
+//! Numbers with a fraction or exponent are floats, as are ints too big for
+//! haxe.
+
+use std::borrow::Cow;
+
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use serde_json::{json, Map as JsonMap, Number, Value as Json};
+use vecmap::VecMap as Map;
+
+use super::value::{
+    date::HaxeDate,
+    float::Float,
+    path::{at, Path, Segment},
+    Value,
+};
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum PlainJsonError {
+    #[error("unknown type hint {hint:?}{}", at(path))]
+    UnknownHint { hint: String, path: Path },
+
+    /// A type hint with the wrong kind of value or other keys it can't go
+    /// with, or a map key of the wrong kind
+    #[error("invalid {hint}{}", at(path))]
+    Invalid { hint: String, path: Path },
+}
+
+impl PlainJsonError {
+    fn invalid(hint: &str) -> Self {
+        Self::Invalid {
+            hint: hint.to_owned(),
+            path: Path::default(),
+        }
+    }
+
+    fn unknown(hint: &str) -> Self {
+        Self::UnknownHint {
+            hint: hint.to_owned(),
+            path: Path::default(),
+        }
+    }
+
+    // the same error, found inside a container at `key`
+    fn within(mut self, key: Segment) -> Self {
+        match &mut self {
+            Self::UnknownHint { path, .. } | Self::Invalid { path, .. } => path.prepend(key),
+        }
+        self
+    }
+}
+
+/// `value` as plain json
+pub fn to_plain_json(value: &Value) -> Json {
+    match value {
+        Value::Null => Json::Null,
+        Value::Bool(value) => Json::Bool(*value),
+        Value::Int(value) => Json::from(*value),
+        Value::Float(Float::Normal(value)) => Json::from(value.as_f64()),
+        Value::Float(special) => {
+            let name = match special {
+                Float::Nan => "nan",
+                Float::PositiveInfinity => "inf",
+                _ => "-inf",
+            };
+            json!({ "$float": name })
+        }
+        Value::String(value) => Json::from(value.as_ref()),
+        Value::Date(date) => json!({ "$date": date }),
+        Value::Bytes(bytes) => json!({ "$bytes": STANDARD.encode(bytes) }),
+        Value::Array(items) => items_to_json(items),
+        Value::List(items) => json!({ "$list": items_to_json(items) }),
+        Value::StringMap(map) => {
+            json!({ "$stringmap": entries_to_json(map, |key| key.to_string()) })
+        }
+        Value::IntMap(map) => json!({ "$intmap": entries_to_json(map, i32::to_string) }),
+        Value::ObjectMap(map) => {
+            let entries = map
+                .iter()
+                .map(|(key, value)| json!([to_plain_json(key), to_plain_json(value)]));
+            json!({ "$objectmap": entries.collect::<Vec<_>>() })
+        }
+        Value::Struct { fields } => fields_to_json(JsonMap::new(), fields),
+        Value::Class { name, fields } => {
+            fields_to_json(JsonMap::from_iter([("$class".into(), json!(name))]), fields)
+        }
+        Value::CustomFields { name, fields } => fields_to_json(
+            JsonMap::from_iter([("$custom".into(), json!(name))]),
+            fields,
+        ),
+        Value::Custom { name, raw } => json!({ "$custom": name, "$raw": items_to_json(raw) }),
+        Value::Enum {
+            name,
+            constructor,
+            fields,
+        } => enum_to_json(format!("{name}.{constructor}"), fields),
+        Value::EnumIndex {
+            name,
+            index,
+            fields,
+        } => enum_to_json(format!("{name}.{index}"), fields),
+        Value::Exception(value) => json!({ "$exception": to_plain_json(value) }),
+        Value::Ref(index) => json!({ "$ref": index }),
+    }
+}
+
+fn items_to_json(items: &[Value]) -> Json {
+    Json::Array(items.iter().map(to_plain_json).collect())
+}
+
+fn entries_to_json<K>(map: &Map<K, Value>, key: impl Fn(&K) -> String) -> Json {
+    Json::Object(
+        map.iter()
+            .map(|(k, value)| (key(k), to_plain_json(value)))
+            .collect(),
+    )
+}
+
+fn fields_to_json(mut object: JsonMap<String, Json>, fields: &Map<Cow<str>, Value>) -> Json {
+    let fields: JsonMap<_, _> = fields
+        .iter()
+        .map(|(key, value)| (key.to_string(), to_plain_json(value)))
+        .collect();
+    if fields.keys().any(|key| key.starts_with('$')) {
+        object.insert("$fields".into(), Json::Object(fields));
+    } else {
+        object.extend(fields);
+    }
+    Json::Object(object)
+}
+
+fn enum_to_json(name: String, fields: &[Value]) -> Json {
+    let mut object = JsonMap::from_iter([("$enum".into(), Json::String(name))]);
+    if !fields.is_empty() {
+        object.insert("$fields".into(), items_to_json(fields));
+    }
+    Json::Object(object)
+}
+
+/// Read a value from plain json, the reverse of [`to_plain_json`]
+pub fn from_plain_json(json: &Json) -> Result<Value<'static>, PlainJsonError> {
+    Ok(match json {
+        Json::Null => Value::Null,
+        Json::Bool(value) => Value::Bool(*value),
+        Json::Number(number) => number_from_json(number),
+        Json::String(value) => Value::String(Cow::Owned(value.clone())),
+        Json::Array(items) => Value::Array(items_from_json(items)?),
+        Json::Object(object) => object_from_json(object)?,
+    })
+}
+
+// a file's top level values, written as an array
+pub(crate) fn values_from_plain_json(json: &Json) -> Result<Vec<Value<'static>>, PlainJsonError> {
+    match json {
+        Json::Array(items) => items_from_json(items),
+        _ => Err(PlainJsonError::invalid("array of values")),
+    }
+}
+
+fn number_from_json(number: &Number) -> Value<'static> {
+    match number.as_i64().map(i32::try_from) {
+        Some(Ok(int)) => Value::Int(int),
+        // every json number is finite
+        _ => Value::Float(Float::new(number.as_f64().unwrap())),
+    }
+}
+
+fn items_from_json(items: &[Json]) -> Result<Vec<Value<'static>>, PlainJsonError> {
+    items
+        .iter()
+        .enumerate()
+        .map(|(index, item)| {
+            from_plain_json(item).map_err(|err| err.within(Segment::Int(index as i64)))
+        })
+        .collect()
+}
+
+fn object_from_json(object: &JsonMap<String, Json>) -> Result<Value<'static>, PlainJsonError> {
+    let hint = |name: &'static str| object.get(name);
+
+    if let Some(name) = hint("$class") {
+        return Ok(Value::Class {
+            name: name_from_json(name, "$class")?,
+            fields: fields_from_json(object, &["$class"])?,
+        });
+    }
+    if let Some(name) = hint("$custom") {
+        let name = name_from_json(name, "$custom")?;
+        return Ok(match hint("$raw") {
+            Some(raw) => {
+                only_hints(object, &["$custom", "$raw"])?;
+                let Json::Array(raw) = raw else {
+                    return Err(PlainJsonError::invalid("$raw"));
+                };
+                Value::Custom {
+                    name,
+                    raw: within_hint(items_from_json(raw), "$raw")?,
+                }
+            }
+            None => Value::CustomFields {
+                name,
+                fields: fields_from_json(object, &["$custom"])?,
+            },
+        });
+    }
+    if let Some(name) = hint("$enum") {
+        only_hints(object, &["$enum", "$fields"])?;
+        return enum_from_json(name, hint("$fields"));
+    }
+
+    let Some((key, value)) = object.iter().find(|(key, _)| key.starts_with('$')) else {
+        return Ok(Value::Struct {
+            fields: fields_from_json(object, &[])?,
+        });
+    };
+    if key == "$fields" {
+        return Ok(Value::Struct {
+            fields: fields_from_json(object, &[])?,
+        });
+    }
+    if object.len() > 1 {
+        return Err(PlainJsonError::invalid(key));
+    }
+
+    let invalid = || PlainJsonError::invalid(key);
+    let value = match (key.as_str(), value) {
+        ("$float", Json::String(name)) => Value::Float(match name.as_str() {
+            "nan" => Float::Nan,
+            "inf" => Float::PositiveInfinity,
+            "-inf" => Float::NegativeInfinity,
+            _ => return Err(invalid()),
+        }),
+        ("$date", date) => {
+            let date = serde_json::from_value::<HaxeDate>(date.clone());
+            Value::Date(date.map_err(|_| invalid())?)
+        }
+        ("$bytes", Json::String(bytes)) => {
+            Value::Bytes(STANDARD.decode(bytes).map_err(|_| invalid())?)
+        }
+        ("$list", Json::Array(items)) => Value::List(within_hint(items_from_json(items), key)?),
+        ("$stringmap", Json::Object(map)) => {
+            let map = entries_from_json(map, |key| Some(Cow::Owned(key.to_owned())));
+            Value::StringMap(within_hint(map, key)?)
+        }
+        ("$intmap", Json::Object(map)) => {
+            let map = entries_from_json(map, |key| key.parse().ok());
+            Value::IntMap(within_hint(map, key)?)
+        }
+        ("$objectmap", Json::Array(entries)) => {
+            Value::ObjectMap(within_hint(object_map_from_json(entries), key)?)
+        }
+        ("$exception", value) => {
+            Value::Exception(Box::new(within_hint(from_plain_json(value), key)?))
+        }
+        ("$ref", Json::Number(index)) => Value::Ref(
+            index
+                .as_u64()
+                .and_then(|index| index.try_into().ok())
+                .ok_or_else(invalid)?,
+        ),
+        ("$float" | "$bytes" | "$list" | "$stringmap" | "$intmap" | "$objectmap" | "$ref", _) => {
+            return Err(invalid())
+        }
+        _ => return Err(PlainJsonError::unknown(key)),
+    };
+    Ok(value)
+}
+
+// errors inside a hint's value are placed under the hint
+fn within_hint<T>(result: Result<T, PlainJsonError>, hint: &str) -> Result<T, PlainJsonError> {
+    result.map_err(|err| err.within(Segment::Name(hint.to_owned())))
+}
+
+// objects with the first of `hints` can't have any keys besides them
+fn only_hints(object: &JsonMap<String, Json>, hints: &[&str]) -> Result<(), PlainJsonError> {
+    match object.keys().find(|key| !hints.contains(&key.as_str())) {
+        Some(key) if key.starts_with('$') => Err(PlainJsonError::unknown(key)),
+        Some(_) => Err(PlainJsonError::invalid(hints[0])),
+        None => Ok(()),
+    }
+}
+
+fn name_from_json(name: &Json, hint: &'static str) -> Result<Cow<'static, str>, PlainJsonError> {
+    match name {
+        Json::String(name) => Ok(Cow::Owned(name.clone())),
+        _ => Err(PlainJsonError::invalid(hint)),
+    }
+}
+
+// the fields of an object besides its `hints`, or its `$fields`
+fn fields_from_json(
+    object: &JsonMap<String, Json>,
+    hints: &[&str],
+) -> Result<Map<Cow<'static, str>, Value<'static>>, PlainJsonError> {
+    if let Some(fields) = object.get("$fields") {
+        let hints = [hints, &["$fields"]].concat();
+        only_hints(object, &hints)?;
+        let Json::Object(fields) = fields else {
+            return Err(PlainJsonError::invalid("$fields"));
+        };
+        return entries_from_json(fields, |key| Some(Cow::Owned(key.to_owned())));
+    }
+
+    let mut fields = Map::new();
+    for (key, value) in object {
+        if hints.contains(&key.as_str()) {
+            continue;
+        }
+        if key.starts_with('$') {
+            return Err(PlainJsonError::unknown(key));
+        }
+        let value = from_plain_json(value).map_err(|err| err.within(Segment::Name(key.clone())))?;
+        fields.insert(Cow::Owned(key.clone()), value);
+    }
+    Ok(fields)
+}
+
+fn entries_from_json<K: Eq>(
+    object: &JsonMap<String, Json>,
+    key: impl Fn(&str) -> Option<K>,
+) -> Result<Map<K, Value<'static>>, PlainJsonError> {
+    object
+        .iter()
+        .map(|(k, value)| {
+            let segment = || Segment::Name(k.clone());
+            let key = key(k).ok_or_else(|| PlainJsonError::invalid("key").within(segment()))?;
+            let value = from_plain_json(value).map_err(|err| err.within(segment()))?;
+            Ok((key, value))
+        })
+        .collect()
+}
+
+fn object_map_from_json(
+    entries: &[Json],
+) -> Result<Map<Value<'static>, Value<'static>>, PlainJsonError> {
+    entries
+        .iter()
+        .enumerate()
+        .map(|(index, entry)| {
+            let within = |err: PlainJsonError| err.within(Segment::Int(index as i64));
+            let Some([key, value]) = entry.as_array().map(Vec::as_slice) else {
+                return Err(within(PlainJsonError::invalid("entry")));
+            };
+            Ok((
+                from_plain_json(key).map_err(within)?,
+                from_plain_json(value).map_err(within)?,
+            ))
+        })
+        .collect()
+}
+
+// `Name.Constructor`, or `Name.3` for an enum written by index
+fn enum_from_json(name: &Json, fields: Option<&Json>) -> Result<Value<'static>, PlainJsonError> {
+    let invalid = || PlainJsonError::invalid("$enum");
+    let name = name.as_str().ok_or_else(invalid)?;
+    let (name, constructor) = name.rsplit_once('.').ok_or_else(invalid)?;
+    let name = Cow::Owned(name.to_owned());
+    let fields = match fields {
+        None => Vec::new(),
+        Some(Json::Array(fields)) => within_hint(items_from_json(fields), "$fields")?,
+        Some(_) => return Err(PlainJsonError::invalid("$fields")),
+    };
+
+    let is_index = !constructor.is_empty() && constructor.bytes().all(|b| b.is_ascii_digit());
+    Ok(if is_index {
+        Value::EnumIndex {
+            name,
+            index: constructor.parse().map_err(|_| invalid())?,
+            fields,
+        }
+    } else {
+        Value::Enum {
+            name,
+            constructor: Cow::Owned(constructor.to_owned()),
+            fields,
+        }
+    })
+}
@@ -1,37 +1,346 @@
 use std::{
     borrow::Cow,
+    collections::{hash_map::Entry, HashMap},
     fmt::{self, Write},
-    hash::{DefaultHasher, Hash, Hasher},
+    io,
+    sync::Arc,
 };
 
 use base64::{engine::general_purpose::STANDARD, Engine as _};
+use percent_encoding::{AsciiSet, CONTROLS};
 use vecmap::VecMap as Map;
 
-use super::value::{float::Float, Value};
+use super::{
+    custom::{self, CustomCodec, Encoded},
+    spelling::{self, Spelling, StringToken, Token},
+    value::{date::HaxeDate, float::Float, Value},
+};
+
+struct State<'v, 's> {
+    serializer: &'v Serializer,
+    output: &'s mut dyn Write,
+    string_cache: HashMap<&'v str, usize>,
+    // every string written literally, by string cache index
+    string_cache_entries: Vec<&'v str>,
+
+    object_cache: HashMap<&'v Value<'v>, usize>,
+    // every cached object by index, `None` for the arrays inside customs
+    object_cache_entries: Vec<Option<&'v Value<'v>>>,
+    // where each object the input would have cached ended up, since objects
+    // written as references aren't cached again and shift the ones after
+    object_indices: Vec<usize>,
+}
+
+/// Characters percent encoded in strings by default, TBD if this exactly
+/// matches the game's `StringTools.urlEncode`
+#[rustfmt::skip]
+pub const DEFAULT_ENCODE_SET: &AsciiSet = &CONTROLS
+    .add(b'\'') // TBD if this is actually percent encoded
+    .add(b'*') // TBD if this is actually percent encoded
+    .add(b' ').add(b'!').add(b'"').add(b'#').add(b'$').add(b'%').add(b'&')
+    .add(b'(').add(b')').add(b'+').add(b',').add(b'/').add(b':').add(b';')
+    .add(b'<').add(b'=').add(b'>').add(b'?').add(b'@').add(b'[').add(b'\\')
+    .add(b']').add(b'^').add(b'`').add(b'{').add(b'|').add(b'}').add(b'~');
+
+/// Characters percent encoded by javascript's `encodeURIComponent`, which is
+/// what `StringTools.urlEncode` uses on the js target
+#[rustfmt::skip]
+pub const JS_ENCODE_SET: &AsciiSet = &CONTROLS
+    .add(b' ').add(b'"').add(b'#').add(b'$').add(b'%').add(b'&').add(b'+')
+    .add(b',').add(b'/').add(b':').add(b';').add(b'<').add(b'=').add(b'>')
+    .add(b'?').add(b'@').add(b'[').add(b'\\').add(b']').add(b'^').add(b'`')
+    .add(b'{').add(b'|').add(b'}');
+
+/// How finite floats are written
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum FloatFormat {
+    /// The shortest digits that read back to the same float, never using an
+    /// exponent (`0.0000001`)
+    #[default]
+    Shortest,
+
+    /// Javascript's `Number.prototype.toString`, which switches to an
+    /// exponent for very large and small numbers (`1e-7`, `1e+21`)
+    Js,
+
+    /// hxcpp's `Std.string`, C's `%.15g`: 15 significant digits and an
+    /// exponent of at least two digits (`0.3`, `1e-07`, `1e+15`)
+    ///
+    /// This isn't always enough digits to read back the same float, but it's
+    /// what the game writes.
+    Hxcpp,
+}
 
-#[derive(Debug, Clone, Default)]
-struct State {
-    output: String,
-    string_cache: Vec<u64>,
+/// Encoding options, named after haxe's `Serializer`
+#[derive(Clone)]
+pub struct Serializer {
+    use_cache: bool,
+    pub(super) use_enum_index: Option<bool>,
+    pub(super) use_string_cache: bool,
+    pub(super) float_format: FloatFormat,
+    pub(super) encode_set: &'static AsciiSet,
+    enum_constructors: HashMap<String, Vec<String>>,
+    custom_codecs: custom::Codecs,
 }
 
-impl State {
-    fn new() -> Self {
+// AsciiSet isn't Debug, so the encode set is left out
+impl fmt::Debug for Serializer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Serializer")
+            .field("use_cache", &self.use_cache)
+            .field("use_enum_index", &self.use_enum_index)
+            .field("use_string_cache", &self.use_string_cache)
+            .field("float_format", &self.float_format)
+            .field("enum_constructors", &self.enum_constructors)
+            .field("custom_codecs", &self.custom_codecs)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for Serializer {
+    fn default() -> Self {
+        Self {
+            use_cache: false,
+            use_enum_index: None,
+            use_string_cache: true,
+            float_format: FloatFormat::default(),
+            encode_set: DEFAULT_ENCODE_SET,
+            enum_constructors: HashMap::new(),
+            custom_codecs: custom::Codecs::new(),
+        }
+    }
+}
+
+impl Serializer {
+    pub fn new() -> Self {
         Self::default()
     }
+
+    /// Emit object cache references (`r<n>`) for objects equal to one that
+    /// was already serialized, like haxe's `Serializer.USE_CACHE`
+    #[must_use]
+    pub fn use_cache(mut self, use_cache: bool) -> Self {
+        self.use_cache = use_cache;
+        self
+    }
+
+    /// Write enums by constructor index (`j`) or by constructor name (`w`),
+    /// like haxe's `Serializer.USE_ENUM_INDEX`
+    ///
+    /// Converting between the two needs the enum's constructors from
+    /// [`Serializer::enum_constructors`]. Enums without them are written the
+    /// way they were decoded, as are all enums when this isn't set.
+    #[must_use]
+    pub fn use_enum_index(mut self, use_enum_index: bool) -> Self {
+        self.use_enum_index = Some(use_enum_index);
+        self
+    }
+
+    /// Register the constructors of enum `name` in declaration order
+    #[must_use]
+    pub fn enum_constructors<S: Into<String>>(
+        mut self,
+        name: impl Into<String>,
+        constructors: impl IntoIterator<Item = S>,
+    ) -> Self {
+        let constructors = constructors.into_iter().map(Into::into).collect();
+        self.enum_constructors.insert(name.into(), constructors);
+        self
+    }
+
+    /// Encode custom classes named `name` with `codec` instead of
+    /// [`FieldArrays`](super::FieldArrays)
+    #[must_use]
+    pub fn custom_codec(
+        mut self,
+        name: impl Into<String>,
+        codec: impl CustomCodec + 'static,
+    ) -> Self {
+        self.custom_codecs.insert(name.into(), Arc::new(codec));
+        self
+    }
+
+    /// Emit string cache references (`R<n>`) for repeated strings, on by
+    /// default as haxe always does this
+    #[must_use]
+    pub fn use_string_cache(mut self, use_string_cache: bool) -> Self {
+        self.use_string_cache = use_string_cache;
+        self
+    }
+
+    #[must_use]
+    pub fn float_format(mut self, float_format: FloatFormat) -> Self {
+        self.float_format = float_format;
+        self
+    }
+
+    /// Characters percent encoded in strings, see [`DEFAULT_ENCODE_SET`] and
+    /// [`JS_ENCODE_SET`]
+    #[must_use]
+    pub fn encode_set(mut self, encode_set: &'static AsciiSet) -> Self {
+        self.encode_set = encode_set;
+        self
+    }
+
+    pub fn encode(&self, values: &[Value]) -> String {
+        let mut output = String::new();
+        self.encode_to_fmt_writer(values, &mut output)
+            .expect("writing to a string can't fail");
+        output
+    }
+
+    pub fn encode_to_fmt_writer(&self, values: &[Value], writer: &mut impl Write) -> fmt::Result {
+        self.encode_spelled(values, None, writer)
+    }
+
+    /// Encode `values` the way `spelling` says they were written, see
+    /// [`Unserializer::decode_lossless`](super::Unserializer::decode_lossless)
+    ///
+    /// Values that were left untouched since decoding come out byte for byte
+    /// the same, everything else is written as [`Serializer::encode`] would.
+    pub fn encode_lossless(&self, values: &[Value], spelling: &Spelling) -> String {
+        let mut output = String::new();
+        self.encode_spelled(values, Some(spelling), &mut output)
+            .expect("writing to a string can't fail");
+        output
+    }
+
+    fn encode_spelled(
+        &self,
+        values: &[Value],
+        spelling: Option<&Spelling>,
+        writer: &mut impl Write,
+    ) -> fmt::Result {
+        let mut state = State {
+            serializer: self,
+            output: writer,
+            string_cache: HashMap::new(),
+            string_cache_entries: Vec::new(),
+            object_cache: HashMap::new(),
+            object_cache_entries: Vec::new(),
+            object_indices: Vec::new(),
+        };
+
+        for (i, value) in values.iter().enumerate() {
+            serialize_value(&mut state, value, Spelling::child(spelling, i))?;
+        }
+
+        Ok(())
+    }
+
+    pub fn encode_to_writer(&self, values: &[Value], writer: impl io::Write) -> io::Result<()> {
+        // fmt::Write can't carry an io::Error, so stash it until we're done
+        struct Adapter<W> {
+            inner: W,
+            error: Option<io::Error>,
+        }
+
+        impl<W: io::Write> Write for Adapter<W> {
+            fn write_str(&mut self, s: &str) -> fmt::Result {
+                self.inner.write_all(s.as_bytes()).map_err(|err| {
+                    self.error = Some(err);
+                    fmt::Error
+                })
+            }
+        }
+
+        let mut adapter = Adapter {
+            inner: writer,
+            error: None,
+        };
+        match self.encode_to_fmt_writer(values, &mut adapter) {
+            Ok(()) => Ok(()),
+            Err(fmt::Error) => Err(adapter
+                .error
+                .unwrap_or_else(|| io::Error::other("formatter error"))),
+        }
+    }
+
+    fn enum_index(&self, name: &str, constructor: &str) -> Option<usize> {
+        let constructors = self.enum_constructors.get(name)?;
+        constructors.iter().position(|c| c == constructor)
+    }
+
+    fn enum_constructor(&self, name: &str, index: usize) -> Option<&str> {
+        let constructors = self.enum_constructors.get(name)?;
+        constructors.get(index).map(String::as_str)
+    }
 }
 
 pub fn to_string(values: &[Value]) -> String {
-    let mut state = State::new();
+    Serializer::new().encode(values)
+}
 
-    for value in values {
-        serialize_value(&mut state, value).unwrap();
+// Mirrors the order haxe caches objects in: containers are cached before
+// their contents, enums only after their fields.
+fn find_cached_object<'v>(state: &mut State<'v, '_>, value: &'v Value<'v>) -> Option<usize> {
+    if !state.serializer.use_cache {
+        return None;
     }
 
-    state.output
+    state.object_cache.get(value).copied()
 }
 
-fn serialize_value<'a>(state: &mut State, value: &'a Value<'a>) -> fmt::Result {
+fn cache_object<'v>(state: &mut State<'v, '_>, value: Option<&'v Value<'v>>) {
+    let index = state.object_cache_entries.len();
+    if let (true, Some(value)) = (state.serializer.use_cache, value) {
+        state.object_cache.entry(value).or_insert(index);
+    }
+    state.object_cache_entries.push(value);
+    state.object_indices.push(index);
+}
+
+// `r<index>` in the input points at an object cache index of the input, which
+// is only the same index in the output while nothing has been deduplicated
+fn object_index(state: &State, index: usize) -> usize {
+    state.object_indices.get(index).copied().unwrap_or(index)
+}
+
+fn serialize_value<'v>(
+    state: &mut State<'v, '_>,
+    value: &'v Value<'v>,
+    spelling: Option<&'v Spelling>,
+) -> fmt::Result {
+    if let Some(Token::ObjectRef(index)) = spelling.map(|s| &s.token) {
+        let index = object_index(state, *index);
+        if state.object_cache_entries.get(index) == Some(&Some(value)) {
+            return state.output.write_fmt(format_args!("r{index}"));
+        }
+    }
+
+    let cacheable = matches!(
+        value,
+        Value::Date(_)
+            | Value::Bytes(_)
+            | Value::Array(_)
+            | Value::List(_)
+            | Value::StringMap(_)
+            | Value::IntMap(_)
+            | Value::ObjectMap(_)
+            | Value::Struct { .. }
+            | Value::Class { .. }
+            | Value::Enum { .. }
+            | Value::EnumIndex { .. }
+            | Value::Custom { .. }
+            | Value::CustomFields { .. }
+    );
+    if cacheable {
+        if let Some(index) = find_cached_object(state, value) {
+            state.object_indices.push(index);
+            return state.output.write_fmt(format_args!("r{index}"));
+        }
+
+        if !matches!(value, Value::Enum { .. } | Value::EnumIndex { .. }) {
+            cache_object(state, Some(value));
+        }
+    }
+
+    if let Some(Token::Raw(raw)) = spelling.map(|s| &s.token) {
+        if spelling::raw_matches(raw, value) {
+            return state.output.write_str(raw);
+        }
+    }
+
     let output = &mut state.output;
     match value {
         Value::Null => output.write_char('n'),
@@ -42,117 +351,223 @@ fn serialize_value<'a>(state: &mut State, value: &'a Value<'a>) -> fmt::Result {
         Value::Float(Float::Nan) => output.write_char('k'),
         Value::Float(Float::PositiveInfinity) => output.write_char('p'),
         Value::Float(Float::NegativeInfinity) => output.write_char('m'),
-        Value::Float(Float::Normal(n)) => output.write_fmt(format_args!("d{}", n.as_f64())),
-        Value::String(s) => serialize_string(state, s),
+        Value::Float(Float::Normal(n)) => serialize_float(state, n.as_f64()),
+        Value::String(s) => serialize_string(state, s, Spelling::string(spelling, 0)),
         Value::Date(s) => serialize_date(state, s),
         Value::Bytes(bytes) => serialize_bytes(state, bytes),
-        Value::Array(v) => serialize_array(state, v),
-        Value::List(v) => serialize_list(state, v),
-        Value::StringMap(map) => serialize_string_map(state, map),
-        Value::IntMap(map) => serialize_int_map(state, map),
-        Value::ObjectMap(map) => serialize_object_map(state, map),
-        Value::Struct { fields } => serialize_struct(state, fields),
-        Value::Class { name, fields } => serialize_class(state, name, fields),
+        Value::Array(v) => serialize_array(state, v.iter().map(Encoded::Value), spelling),
+        Value::List(v) => serialize_list(state, v, spelling),
+        Value::StringMap(map) => serialize_string_map(state, map, spelling),
+        Value::IntMap(map) => serialize_int_map(state, map, spelling),
+        Value::ObjectMap(map) => serialize_object_map(state, map, spelling),
+        Value::Struct { fields } => serialize_struct(state, fields, spelling),
+        Value::Class { name, fields } => serialize_class(state, name, fields, spelling),
         Value::Enum {
             name,
             constructor,
             fields,
-        } => serialize_enum(state, name, constructor, fields),
-        Value::Exception(_) => todo!(),
-        Value::Custom { name, fields } => {
-            state.output.write_char('C')?;
-            serialize_string(state, name)?;
-
-            serialize_array(
-                state,
-                &fields
-                    .keys()
-                    .map(|v| Value::String(v.clone()))
-                    .collect::<Vec<_>>(),
-            )?;
-            serialize_array(state, fields.values())?;
-            state.output.write_char('g')?;
+        } => {
+            let serializer = state.serializer;
+            match serializer.enum_index(name, constructor) {
+                Some(index) if serializer.use_enum_index == Some(true) => {
+                    serialize_enum_index(state, name, index, fields, spelling)?;
+                }
+                _ => serialize_enum(state, name, constructor, fields, spelling)?,
+            }
+            cache_object(state, Some(value));
             Ok(())
         }
+        Value::EnumIndex {
+            name,
+            index,
+            fields,
+        } => {
+            let serializer = state.serializer;
+            match serializer.enum_constructor(name, *index) {
+                Some(constructor) if serializer.use_enum_index == Some(false) => {
+                    serialize_enum(state, name, constructor, fields, spelling)?;
+                }
+                _ => serialize_enum_index(state, name, *index, fields, spelling)?,
+            }
+            cache_object(state, Some(value));
+            Ok(())
+        }
+        Value::Exception(value) => {
+            state.output.write_char('x')?;
+            serialize_value(state, value, Spelling::child(spelling, 0))
+        }
+        Value::Custom { name, raw } => {
+            let raw = raw.iter().map(Encoded::Value).collect();
+            serialize_custom(state, name, raw, spelling)
+        }
+        Value::CustomFields { name, fields } => {
+            let raw = custom::codec(&state.serializer.custom_codecs, name).encode(fields);
+            serialize_custom(state, name, raw, spelling)
+        }
+        Value::Ref(index) => {
+            let index = object_index(state, *index);
+            state.output.write_fmt(format_args!("r{index}"))
+        }
     }
 }
 
-fn serialize_enum(
-    state: &mut State,
-    name: &str,
-    constructor: &str,
-    fields: &[Value],
+fn serialize_custom<'v>(
+    state: &mut State<'v, '_>,
+    name: &'v str,
+    raw: Vec<Encoded<'v>>,
+    spelling: Option<&'v Spelling>,
+) -> fmt::Result {
+    state.output.write_char('C')?;
+    serialize_string(state, name, Spelling::string(spelling, 0))?;
+    for (i, value) in raw.into_iter().enumerate() {
+        serialize_encoded(state, value, Spelling::child(spelling, i))?;
+    }
+    state.output.write_char('g')?;
+    Ok(())
+}
+
+fn serialize_encoded<'v>(
+    state: &mut State<'v, '_>,
+    value: Encoded<'v>,
+    spelling: Option<&'v Spelling>,
+) -> fmt::Result {
+    // scalars are written like the values they'd decode to, spelling and all
+    let scalar = match value {
+        Encoded::Value(value) => return serialize_value(state, value, spelling),
+        Encoded::String(s) => return serialize_string(state, s, Spelling::string(spelling, 0)),
+        Encoded::Array(items) => {
+            // never referenced since it isn't a value anyone can point at
+            cache_object(state, None);
+            return serialize_array(state, items, spelling);
+        }
+        Encoded::Null => Value::Null,
+        Encoded::Bool(b) => Value::Bool(b),
+        Encoded::Int(n) => Value::Int(n),
+        Encoded::Float(n) => Value::Float(Float::new(n)),
+    };
+
+    if let Some(Token::Raw(raw)) = spelling.map(|s| &s.token) {
+        if spelling::raw_matches(raw, &scalar) {
+            return state.output.write_str(raw);
+        }
+    }
+    match scalar {
+        Value::Null => state.output.write_char('n'),
+        Value::Bool(true) => state.output.write_char('t'),
+        Value::Bool(false) => state.output.write_char('f'),
+        Value::Int(0) => state.output.write_char('z'),
+        Value::Int(n) => state.output.write_fmt(format_args!("i{n}")),
+        Value::Float(Float::Nan) => state.output.write_char('k'),
+        Value::Float(Float::PositiveInfinity) => state.output.write_char('p'),
+        Value::Float(Float::NegativeInfinity) => state.output.write_char('m'),
+        Value::Float(Float::Normal(n)) => serialize_float(state, n.as_f64()),
+        _ => unreachable!("only scalars are left"),
+    }
+}
+
+fn serialize_enum<'v>(
+    state: &mut State<'v, '_>,
+    name: &'v str,
+    constructor: &'v str,
+    fields: &'v [Value<'v>],
+    spelling: Option<&'v Spelling>,
 ) -> Result<(), fmt::Error> {
     state.output.write_char('w')?;
-    serialize_string(state, name)?;
-    serialize_string(state, constructor)?;
+    serialize_string(state, name, Spelling::string(spelling, 0))?;
+    serialize_string(state, constructor, Spelling::string(spelling, 1))?;
     state.output.write_char(':')?;
     state.output.write_fmt(format_args!("{}", fields.len()))?;
-    for field in fields {
-        serialize_value(state, field)?;
+    for (i, field) in fields.iter().enumerate() {
+        serialize_value(state, field, Spelling::child(spelling, i))?;
+    }
+    Ok(())
+}
+
+fn serialize_enum_index<'v>(
+    state: &mut State<'v, '_>,
+    name: &'v str,
+    index: usize,
+    fields: &'v [Value<'v>],
+    spelling: Option<&'v Spelling>,
+) -> Result<(), fmt::Error> {
+    state.output.write_char('j')?;
+    serialize_string(state, name, Spelling::string(spelling, 0))?;
+    state
+        .output
+        .write_fmt(format_args!(":{index}:{}", fields.len()))?;
+    for (i, field) in fields.iter().enumerate() {
+        serialize_value(state, field, Spelling::child(spelling, i))?;
     }
     Ok(())
 }
 
-fn serialize_class(
-    state: &mut State,
-    name: &str,
-    fields: &Map<Cow<'_, str>, Value<'_>>,
+fn serialize_class<'v>(
+    state: &mut State<'v, '_>,
+    name: &'v str,
+    fields: &'v Map<Cow<'v, str>, Value<'v>>,
+    spelling: Option<&'v Spelling>,
 ) -> Result<(), fmt::Error> {
     state.output.write_char('c')?;
-    serialize_string(state, name)?;
-    for (key, value) in fields {
-        serialize_string(state, key)?;
-        serialize_value(state, value)?;
+    serialize_string(state, name, Spelling::string(spelling, 0))?;
+    for (i, (key, value)) in fields.iter().enumerate() {
+        serialize_string(state, key, Spelling::string(spelling, i + 1))?;
+        serialize_value(state, value, Spelling::child(spelling, i))?;
     }
     state.output.write_char('g')?;
     Ok(())
 }
 
-fn serialize_struct(
-    state: &mut State,
-    fields: &Map<Cow<'_, str>, Value<'_>>,
+fn serialize_struct<'v>(
+    state: &mut State<'v, '_>,
+    fields: &'v Map<Cow<'v, str>, Value<'v>>,
+    spelling: Option<&'v Spelling>,
 ) -> Result<(), fmt::Error> {
     state.output.write_char('o')?;
-    for (key, value) in fields {
-        serialize_string(state, key)?;
-        serialize_value(state, value)?;
+    for (i, (key, value)) in fields.iter().enumerate() {
+        serialize_string(state, key, Spelling::string(spelling, i))?;
+        serialize_value(state, value, Spelling::child(spelling, i))?;
     }
     state.output.write_char('g')?;
     Ok(())
 }
 
-fn serialize_object_map(
-    state: &mut State,
-    map: &Map<Value<'_>, Value<'_>>,
+fn serialize_object_map<'v>(
+    state: &mut State<'v, '_>,
+    map: &'v Map<Value<'v>, Value<'v>>,
+    spelling: Option<&'v Spelling>,
 ) -> Result<(), fmt::Error> {
     state.output.write_char('M')?;
-    for (key, value) in map {
-        serialize_value(state, key)?;
-        serialize_value(state, value)?;
+    for (i, (key, value)) in map.iter().enumerate() {
+        serialize_value(state, key, Spelling::child(spelling, 2 * i))?;
+        serialize_value(state, value, Spelling::child(spelling, 2 * i + 1))?;
     }
     state.output.write_char('h')?;
     Ok(())
 }
 
-fn serialize_int_map(state: &mut State, map: &Map<i32, Value<'_>>) -> Result<(), fmt::Error> {
+fn serialize_int_map<'v>(
+    state: &mut State<'v, '_>,
+    map: &'v Map<i32, Value<'v>>,
+    spelling: Option<&'v Spelling>,
+) -> Result<(), fmt::Error> {
     state.output.write_char('q')?;
-    for (key, value) in map {
+    for (i, (key, value)) in map.iter().enumerate() {
         state.output.write_fmt(format_args!(":{key}"))?;
-        serialize_value(state, value)?;
+        serialize_value(state, value, Spelling::child(spelling, i))?;
     }
     state.output.write_char('h')?;
     Ok(())
 }
 
-fn serialize_string_map(
-    state: &mut State,
-    map: &Map<Cow<'_, str>, Value<'_>>,
+fn serialize_string_map<'v>(
+    state: &mut State<'v, '_>,
+    map: &'v Map<Cow<'v, str>, Value<'v>>,
+    spelling: Option<&'v Spelling>,
 ) -> Result<(), fmt::Error> {
     state.output.write_char('b')?;
-    for (key, value) in map {
-        serialize_string(state, key)?;
-        serialize_value(state, value)?;
+    for (i, (key, value)) in map.iter().enumerate() {
+        serialize_string(state, key, Spelling::string(spelling, i))?;
+        serialize_value(state, value, Spelling::child(spelling, i))?;
     }
     state.output.write_char('h')?;
     Ok(())
@@ -166,80 +581,205 @@ fn serialize_bytes(state: &mut State, bytes: &[u8]) -> Result<(), fmt::Error> {
     ))
 }
 
-fn serialize_string<'a>(state: &mut State, value: &'a str) -> fmt::Result {
-    use percent_encoding::{percent_encode, AsciiSet, CONTROLS};
+fn serialize_string<'v>(
+    state: &mut State<'v, '_>,
+    value: &'v str,
+    token: Option<&'v StringToken>,
+) -> fmt::Result {
+    use percent_encoding::{percent_decode_str, percent_encode};
 
-    #[rustfmt::skip]
-    const PERCENT_ENCODED_CHARACTERS: &AsciiSet = &CONTROLS
-        .add(b'\'') // TBD if this is actually percent encoded
-        .add(b'*') // TBD if this is actually percent encoded
-        .add(b' ').add(b'!').add(b'"').add(b'#').add(b'$').add(b'%').add(b'&')
-        .add(b'(').add(b')').add(b'+').add(b',').add(b'/').add(b':').add(b';')
-        .add(b'<').add(b'=').add(b'>').add(b'?').add(b'@').add(b'[').add(b'\\')
-        .add(b']').add(b'^').add(b'`').add(b'{').add(b'|').add(b'}').add(b'~');
+    let raw = match token {
+        Some(StringToken::Ref(index)) if state.string_cache_entries.get(*index) == Some(&value) => {
+            return state.output.write_fmt(format_args!("R{index}"));
+        }
+        // written literally even when it's already cached, which takes up
+        // another slot in the cache
+        Some(StringToken::Literal(raw)) => Some(raw.as_deref().filter(|raw| {
+            percent_decode_str(raw)
+                .decode_utf8()
+                .is_ok_and(|s| s == value)
+        })),
+        _ => None,
+    };
 
-    let output = &mut state.output;
+    let index = state.string_cache_entries.len();
+    if state.serializer.use_string_cache {
+        match state.string_cache.entry(value) {
+            Entry::Occupied(entry) if raw.is_none() => {
+                return state.output.write_fmt(format_args!("R{}", entry.get()));
+            }
+            Entry::Occupied(_) => {}
+            Entry::Vacant(entry) => {
+                entry.insert(index);
+            }
+        }
+    }
+    state.string_cache_entries.push(value);
+
+    let encoded: Cow<'_, str> = match raw.flatten() {
+        Some(raw) => raw.into(),
+        None => percent_encode(value.as_bytes(), state.serializer.encode_set).into(),
+    };
+    state
+        .output
+        .write_fmt(format_args!("y{len}:{encoded}", len = encoded.len()))
+}
+
+fn serialize_float(state: &mut State, value: f64) -> fmt::Result {
+    write_float(state.output, state.serializer.float_format, value)
+}
+
+pub(super) fn write_float(output: &mut dyn Write, format: FloatFormat, value: f64) -> fmt::Result {
+    output.write_char('d')?;
+    write_float_digits(output, format, value)
+}
 
-    let mut hasher = DefaultHasher::new();
-    value.hash(&mut hasher);
-    let s_hash = hasher.finish();
+fn write_float_digits(output: &mut dyn Write, format: FloatFormat, value: f64) -> fmt::Result {
+    match format {
+        FloatFormat::Shortest => output.write_fmt(format_args!("{value}")),
+        FloatFormat::Js => write_js_float(output, value),
+        FloatFormat::Hxcpp => write_hxcpp_float(output, value),
+    }
+}
+
+// https://en.cppreference.com/w/c/io/fprintf, `%g` with a precision of 15
+fn write_hxcpp_float(output: &mut dyn Write, value: f64) -> fmt::Result {
+    const PRECISION: i32 = 15;
+
+    if value.is_sign_negative() {
+        output.write_char('-')?;
+    }
+    let value = value.abs();
 
-    let string_cache_index = state.string_cache.iter().position(|hash| *hash == s_hash);
-    if let Some(n) = string_cache_index {
-        output.write_fmt(format_args!("R{n}"))
+    // the exponent is the one after rounding to the precision
+    let scientific = format!("{value:.*e}", PRECISION as usize - 1);
+    let (mantissa, exponent) = scientific.split_once('e').unwrap();
+    let exponent = exponent.parse::<i32>().unwrap();
+
+    if (-4..PRECISION).contains(&exponent) {
+        let fixed = format!("{value:.*}", (PRECISION - 1 - exponent) as usize);
+        output.write_str(trim_fraction(&fixed))
     } else {
-        state.string_cache.push(s_hash);
-        let encoded: Cow<'a, str> =
-            percent_encode(value.as_bytes(), PERCENT_ENCODED_CHARACTERS).into();
+        let sign = if exponent < 0 { '-' } else { '+' };
+        output.write_fmt(format_args!(
+            "{}e{sign}{:02}",
+            trim_fraction(mantissa),
+            exponent.abs()
+        ))
+    }
+}
 
-        output.write_fmt(format_args!("y{len}:{encoded}", len = encoded.len()))
+// `%g` drops trailing zeros after the decimal point, and the point with them
+fn trim_fraction(digits: &str) -> &str {
+    if digits.contains('.') {
+        digits.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        digits
     }
 }
 
-fn serialize_date(state: &mut State, value: &str) -> fmt::Result {
+// https://tc39.es/ecma262/#sec-numeric-types-number-tostring
+fn write_js_float(output: &mut dyn Write, value: f64) -> fmt::Result {
+    if value == 0.0 {
+        return output.write_char('0');
+    }
+    if value < 0.0 {
+        output.write_char('-')?;
+    }
+
+    // rust's exponent formatting already gives the shortest digits
+    let scientific = format!("{:e}", value.abs());
+    let (mantissa, exponent) = scientific.split_once('e').unwrap();
+    let digits = mantissa.replace('.', "");
+    let k = i32::try_from(digits.len()).unwrap();
+    let n = exponent.parse::<i32>().unwrap() + 1;
+
+    if k <= n && n <= 21 {
+        output.write_str(&digits)?;
+        for _ in k..n {
+            output.write_char('0')?;
+        }
+        Ok(())
+    } else if 0 < n && n <= 21 {
+        let (int, frac) = digits.split_at(n.unsigned_abs() as usize);
+        output.write_fmt(format_args!("{int}.{frac}"))
+    } else if -6 < n && n <= 0 {
+        output.write_str("0.")?;
+        for _ in n..0 {
+            output.write_char('0')?;
+        }
+        output.write_str(&digits)
+    } else {
+        let (first, rest) = digits.split_at(1);
+        output.write_str(first)?;
+        if !rest.is_empty() {
+            output.write_fmt(format_args!(".{rest}"))?;
+        }
+        let sign = if n > 0 { '+' } else { '-' };
+        output.write_fmt(format_args!("e{sign}{}", (n - 1).abs()))
+    }
+}
+
+fn serialize_date(state: &mut State, value: &HaxeDate) -> fmt::Result {
     state.output.write_char('v')?;
-    state.output.write_str(value)?;
-    Ok(())
+    match value {
+        HaxeDate::Local(date) => state.output.write_fmt(format_args!("{date}")),
+        HaxeDate::Timestamp(millis) => {
+            write_float_digits(state.output, state.serializer.float_format, millis.as_f64())
+        }
+    }
 }
 
-fn serialize_list(state: &mut State, values: &[Value]) -> fmt::Result {
+fn serialize_list<'v>(
+    state: &mut State<'v, '_>,
+    values: &'v [Value<'v>],
+    spelling: Option<&'v Spelling>,
+) -> fmt::Result {
     state.output.write_char('l')?;
-    for value in values {
-        serialize_value(state, value)?;
+    for (i, value) in values.iter().enumerate() {
+        serialize_value(state, value, Spelling::child(spelling, i))?;
     }
     state.output.write_char('h')?;
 
     Ok(())
 }
 
-fn serialize_array<'a>(
-    state: &'a mut State,
-    values: impl IntoIterator<Item = &'a Value<'a>>,
+fn serialize_array<'v>(
+    state: &mut State<'v, '_>,
+    items: impl IntoIterator<Item = Encoded<'v>>,
+    spelling: Option<&'v Spelling>,
 ) -> fmt::Result {
-    let mut values = values.into_iter();
+    let mut items = items.into_iter().peekable();
 
     state.output.write_char('a')?;
-    loop {
-        let mut value = values.next();
-
-        if value == Some(&Value::Null) {
-            let mut null_count = 0;
-            while value == Some(&Value::Null) {
-                null_count += 1;
-                value = values.next();
-            }
-
-            if null_count == 1 {
-                state.output.write_fmt(format_args!("n"))?;
-            } else {
-                state.output.write_fmt(format_args!("u{null_count}"))?;
-            }
+    let mut i = 0;
+    while let Some(item) = items.next() {
+        let child = Spelling::child(spelling, i);
+        if !item.is_null() {
+            serialize_encoded(state, item, child)?;
+            i += 1;
+            continue;
         }
 
-        match value {
-            Some(value) => serialize_value(state, value)?,
-            None => break,
+        let mut nulls = 1;
+        let run = match child.map(|c| &c.token) {
+            Some(Token::Null) => 1,
+            Some(&Token::Nulls(count)) => count,
+            _ => usize::MAX,
         };
+        while nulls < run && items.next_if(Encoded::is_null).is_some() {
+            nulls += 1;
+        }
+
+        // a recorded run that got shorter is written like any other
+        match child.map(|c| &c.token) {
+            Some(&Token::Nulls(count)) if count == nulls => {
+                state.output.write_fmt(format_args!("u{count}"))?;
+            }
+            _ if nulls == 1 => state.output.write_char('n')?,
+            _ => state.output.write_fmt(format_args!("u{nulls}"))?,
+        }
+        i += nulls;
     }
     state.output.write_char('h')?;
 
@@ -0,0 +1,86 @@
+use super::{
+    de::from_str,
+    ser::to_string,
+    value::{date::HaxeDate, Value},
+};
+
+/// How decoded values were written, recorded by
+/// [`Unserializer::decode_lossless`](super::Unserializer::decode_lossless) so
+/// [`Serializer::encode_lossless`](super::Serializer::encode_lossless) can
+/// write them the same way
+///
+/// There is one `Spelling` per value, the one returned from decoding has one
+/// child per top level value. A recorded choice is only used while it still
+/// reads back as the value being encoded, so edited values fall back to the
+/// serializer's usual choices.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Spelling {
+    pub(super) token: Token,
+    // every string in the value in the order they're written: the string
+    // itself, names and keys
+    pub(super) strings: Vec<StringToken>,
+    // nested values in the order they're written, map keys included
+    pub(super) children: Vec<Spelling>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(super) enum Token {
+    #[default]
+    Default,
+    /// A scalar written differently than the serializer would (`i0`,
+    /// `d1e-7`)
+    Raw(Box<str>),
+    /// A copy of an object written as `r<index>`
+    ObjectRef(usize),
+    /// A lone `n` in an array, rather than part of a run
+    Null,
+    /// The first of `u<count>` nulls in an array
+    Nulls(usize),
+    /// Covered by an earlier [`Token::Nulls`]
+    InNulls,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) enum StringToken {
+    /// Written as a literal, with the raw literal if its percent encoding
+    /// isn't the default one
+    Literal(Option<Box<str>>),
+    /// Written as `R<index>`
+    Ref(usize),
+}
+
+impl Spelling {
+    pub(super) fn child(spelling: Option<&Self>, index: usize) -> Option<&Self> {
+        spelling?.children.get(index)
+    }
+
+    pub(super) fn string(spelling: Option<&Self>, index: usize) -> Option<&StringToken> {
+        spelling?.strings.get(index)
+    }
+}
+
+// how a value was written given its `raw` input, strings are recorded as
+// they're parsed instead
+pub(super) fn token(raw: &str, keep_references: bool) -> Token {
+    match raw.as_bytes().first() {
+        // a copy of the object, unless the reference itself was kept
+        Some(b'r') if !keep_references => raw[1..].parse().map_or(Token::Default, Token::ObjectRef),
+        // the serializer might be set to another float format, so floats
+        // always keep their lexeme
+        Some(b'd') => Token::Raw(raw.into()),
+        Some(b'v') if matches!(raw[1..].parse(), Ok(HaxeDate::Timestamp(_))) => {
+            Token::Raw(raw.into())
+        }
+        Some(b'i' | b's')
+            if from_str(raw).map(|values| to_string(&values)).as_deref() != Ok(raw) =>
+        {
+            Token::Raw(raw.into())
+        }
+        _ => Token::Default,
+    }
+}
+
+// whether a raw scalar still decodes to `value`
+pub(super) fn raw_matches(raw: &str, value: &Value) -> bool {
+    matches!(from_str(raw).as_deref(), Ok([decoded]) if decoded == value)
+}
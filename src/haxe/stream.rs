@@ -0,0 +1,607 @@
+//! Decoding from a reader one value, or one event, at a time
+
+use std::{borrow::Cow, collections::VecDeque, io::BufRead, sync::Arc};
+
+use super::{
+    custom::{self, CustomCodec},
+    de::Streamed,
+    object_cache::{Cached, ObjectCache},
+    value::{date::HaxeDate, float::Float, Value},
+    visitor::ValueBuilder,
+    DecodeError, Visitor,
+};
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error(transparent)]
+    Decode(#[from] DecodeError),
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// A step of decoding, containers are started by one of the `Start*` events
+/// and finished by [`Event::End`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    Null,
+    Bool(bool),
+    Int(i32),
+    Float(Float),
+    String(String),
+    Date(HaxeDate),
+    Bytes(Vec<u8>),
+
+    /// Index into the object cache, references aren't followed when
+    /// decoding events
+    Ref(usize),
+
+    StartArray,
+    StartList,
+    StartStringMap,
+    StartIntMap,
+    /// Followed by alternating keys and values
+    StartObjectMap,
+    StartStruct,
+    StartClass {
+        name: String,
+    },
+    StartEnum {
+        name: String,
+        constructor: String,
+        len: usize,
+    },
+    StartEnumIndex {
+        name: String,
+        index: usize,
+        len: usize,
+    },
+    StartException,
+    /// Followed by the values its `hxSerialize` wrote
+    StartCustom {
+        name: String,
+    },
+
+    /// Key of the next value in a struct, class or string map
+    Field(String),
+    /// Key of the next value in an int map
+    IntKey(i32),
+
+    End,
+}
+
+// bytes read at a time, values longer than that are read in bigger steps
+const CHUNK: usize = 8 * 1024;
+
+// bytes of input kept around for error snippets
+const RECENT: usize = 64;
+
+/// Decodes haxe serialized data from a reader without holding all of it in
+/// memory
+///
+/// The input is parsed one top level value at a time. Like
+/// [`Unserializer`](super::Unserializer) object references are copied in
+/// values unless [`StreamDecoder::keep_references`] is set, which means the
+/// input from the first object that can be referenced on is kept around to
+/// decode referenced objects again. Otherwise the input is let go of once
+/// each top level value is decoded. Events never follow references, so
+/// reading a stream only as events keeps nothing but the string cache and
+/// the events of the top level value being read. Values can't reference an
+/// object that was read as events.
+pub struct StreamDecoder<R> {
+    reader: R,
+    failed: bool,
+    keep_references: bool,
+
+    // the input from byte `base` on, as far as it's been read
+    text: String,
+    base: usize,
+    // how much of `text` is decoded
+    decoded: usize,
+    // bytes read after `text` that don't make up a whole character yet
+    pending: Vec<u8>,
+    ended: bool,
+    // where the input stops being utf-8, nothing after that is read
+    invalid_at: Option<usize>,
+
+    // what's left of the top level value being read, and the error that cut
+    // it short
+    events: VecDeque<Event>,
+    error: Option<DecodeError>,
+    // where each `Event::Ref` in `events` is
+    ref_offsets: VecDeque<usize>,
+
+    string_cache: Vec<String>,
+    object_cache: ObjectCache,
+    // nulls from `u<count>` runs so far, see `MAX_NULLS`
+    nulls: usize,
+
+    custom_codecs: Arc<custom::Codecs>,
+}
+
+impl<R: BufRead> StreamDecoder<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            failed: false,
+            keep_references: false,
+            text: String::new(),
+            base: 0,
+            decoded: 0,
+            pending: Vec::new(),
+            ended: false,
+            invalid_at: None,
+            events: VecDeque::new(),
+            error: None,
+            ref_offsets: VecDeque::new(),
+            string_cache: Vec::new(),
+            object_cache: ObjectCache::default(),
+            nulls: 0,
+            custom_codecs: Arc::default(),
+        }
+    }
+
+    /// Decode object cache references (`r<n>`) as [`Value::Ref`] instead of
+    /// copying the object they point to
+    #[must_use]
+    pub fn keep_references(mut self, keep_references: bool) -> Self {
+        self.keep_references = keep_references;
+        self
+    }
+
+    /// Decode custom classes named `name` with `codec` instead of
+    /// [`FieldArrays`](super::FieldArrays), only used for values
+    #[must_use]
+    pub fn custom_codec(
+        mut self,
+        name: impl Into<String>,
+        codec: impl CustomCodec + 'static,
+    ) -> Self {
+        Arc::make_mut(&mut self.custom_codecs).insert(name.into(), Arc::new(codec));
+        self
+    }
+
+    /// Bytes decoded so far, which is up to the end of the last top level
+    /// value read
+    pub fn offset(&self) -> usize {
+        self.base + self.decoded
+    }
+
+    /// Iterate over events instead of values
+    pub fn events(&mut self) -> Events<'_, R> {
+        Events(self)
+    }
+
+    /// The next event, or `None` once the input ends or decoding failed
+    pub fn next_event(&mut self) -> Option<Result<Event>> {
+        if self.failed {
+            return None;
+        }
+
+        match self.read_event(true) {
+            Ok(event) => event.map(|(event, _)| Ok(event)),
+            Err(err) => Some(Err(self.fail(err))),
+        }
+    }
+
+    /// The next whole value, or `None` once the input ends or decoding failed
+    ///
+    /// When in the middle of a container started by an event, this is the
+    /// next value inside of it, or `None` once the next event is the
+    /// container's [`Event::End`].
+    pub fn next_value(&mut self) -> Option<Result<Value<'static>>> {
+        if self.failed {
+            return None;
+        }
+
+        match self.read_value() {
+            Ok(value) => value.map(Ok),
+            Err(err) => Some(Err(self.fail(err))),
+        }
+    }
+
+    fn fail(&mut self, err: Error) -> Error {
+        self.failed = true;
+        match err {
+            Error::Decode(err) => {
+                Error::Decode(err.with_snippet_from(self.text.as_bytes(), self.base))
+            }
+            err => err,
+        }
+    }
+
+    fn read_value(&mut self) -> Result<Option<Value<'static>>> {
+        if self.events.front() == Some(&Event::End) {
+            return Ok(None);
+        }
+
+        // only the codecs are shared, everything else changes while decoding
+        let custom_codecs = Arc::clone(&self.custom_codecs);
+        let mut builder = ValueBuilder::new(&custom_codecs);
+        loop {
+            let Some((event, ref_offset)) = self.read_event(false)? else {
+                return Ok(None);
+            };
+
+            self.build(&mut builder, event, ref_offset)?;
+            if let Some(value) = builder.finished() {
+                return Ok(Some(value));
+            }
+        }
+    }
+
+    // the next event along with where it is if it's a reference, parsing the
+    // next top level value `as_events` once they've all been read
+    fn read_event(&mut self, as_events: bool) -> Result<Option<(Event, usize)>> {
+        if self.events.is_empty() && self.error.is_none() && !self.parse_next(as_events)? {
+            return Ok(None);
+        }
+
+        match self.events.pop_front() {
+            Some(event) => {
+                let ref_offset = match event {
+                    Event::Ref(_) => self.ref_offsets.pop_front(),
+                    _ => None,
+                };
+                Ok(Some((event, ref_offset.unwrap_or(self.offset()))))
+            }
+            None => {
+                let err = self.error.take().expect("checked above");
+                Err(Error::Decode(err))
+            }
+        }
+    }
+
+    fn build(
+        &mut self,
+        builder: &mut ValueBuilder<'static, '_>,
+        event: Event,
+        ref_offset: usize,
+    ) -> Result<()> {
+        match event {
+            Event::Null => builder.visit_null(),
+            Event::Bool(b) => builder.visit_bool(b),
+            Event::Int(n) => builder.visit_int(n),
+            Event::Float(n) => builder.visit_float(n),
+            Event::String(s) => builder.visit_string(s.into()),
+            Event::Date(date) => builder.visit_date(date),
+            Event::Bytes(bytes) => builder.visit_bytes(bytes),
+            Event::Ref(index) if self.keep_references => builder.object_ref(index),
+            // values parsed as values copy their references while they're
+            // parsed, these are inside of a value read as events
+            Event::Ref(index) => match self.object_cache.get(index, ref_offset) {
+                Some(Cached::Object { start }) => {
+                    let mut events = EventRecorder::default();
+                    self.streamed().replay(start, ref_offset, &mut events)?;
+                    for event in events.0 {
+                        self.build(builder, event, ref_offset)?;
+                    }
+                }
+                // a value can't contain itself, like when decoding values
+                Some(Cached::Unfinished) => builder.visit_null(),
+                Some(Cached::Dropped) | None => {
+                    return Err(Error::Decode(DecodeError::BadObjectCacheRef {
+                        index,
+                        offset: ref_offset,
+                        snippet: String::new(),
+                    }))
+                }
+            },
+
+            Event::Field(key) => builder.field_name(key.into()),
+            Event::IntKey(key) => builder.int_key(key),
+            Event::End => builder.end(),
+
+            Event::StartArray => builder.begin_array(),
+            Event::StartList => builder.begin_list(),
+            Event::StartStringMap => builder.begin_string_map(),
+            Event::StartIntMap => builder.begin_int_map(),
+            Event::StartObjectMap => builder.begin_object_map(),
+            Event::StartStruct => builder.begin_struct(),
+            Event::StartClass { name } => builder.begin_class(name.into()),
+            Event::StartEnum {
+                name,
+                constructor,
+                len,
+            } => builder.begin_enum(name.into(), constructor.into(), len),
+            Event::StartEnumIndex { name, index, len } => {
+                builder.begin_enum_index(name.into(), index, len)
+            }
+            Event::StartException => builder.begin_exception(),
+            Event::StartCustom { name } => builder.begin_custom(name.into()),
+        }
+        Ok(())
+    }
+
+    fn streamed(&mut self) -> Streamed<'_, '_> {
+        Streamed {
+            text: &self.text,
+            base: self.base,
+            strings: &self.string_cache,
+            object_cache: &mut self.object_cache,
+            nulls: &mut self.nulls,
+            ref_offsets: &mut self.ref_offsets,
+        }
+    }
+
+    // parses the next top level value into `events`, `false` once the input
+    // has ended
+    fn parse_next(&mut self, as_events: bool) -> Result<bool> {
+        // events don't follow references, so they don't keep any input
+        let keep_references = as_events || self.keep_references;
+        loop {
+            if self.decoded == self.text.len() {
+                self.read_more(CHUNK)?;
+            }
+            if self.decoded == self.text.len() {
+                return match self.invalid_at {
+                    Some(offset) => Err(Error::Decode(DecodeError::InvalidUtf8 {
+                        offset,
+                        snippet: String::new(),
+                    })),
+                    None => Ok(false),
+                };
+            }
+
+            let more = !self.ended && self.invalid_at.is_none();
+            let mut events = EventRecorder::default();
+            let decoded = self.decoded;
+            match self
+                .streamed()
+                .parse(decoded, more, keep_references, &mut events)
+            {
+                Ok(Some((end, strings))) => {
+                    self.string_cache.extend(strings);
+                    self.decoded = end;
+                    self.events.extend(events.0);
+                    self.release();
+                    return Ok(true);
+                }
+                // it's parsed again from the start with more input, which
+                // takes a few tries at most as each one reads more
+                Ok(None) => self.read_more(CHUNK.max(self.text.len() - self.decoded))?,
+                Err(err) => {
+                    // input that isn't utf-8 ends the text early
+                    let err = match self.invalid_at {
+                        Some(offset) if err.offset() >= offset => DecodeError::InvalidUtf8 {
+                            offset,
+                            snippet: String::new(),
+                        },
+                        _ => err,
+                    };
+                    self.events.extend(events.0);
+                    self.error = Some(err);
+                    return Ok(true);
+                }
+            }
+        }
+    }
+
+    // reads at least `len` more bytes unless the input ends or stops being
+    // utf-8 first
+    fn read_more(&mut self, len: usize) -> Result<()> {
+        let mut read = 0;
+        while read < len && !self.ended && self.invalid_at.is_none() {
+            let bytes = self.reader.fill_buf()?;
+            if bytes.is_empty() {
+                self.ended = true;
+                // a character cut off by the end of the input
+                if !self.pending.is_empty() {
+                    self.invalid_at = Some(self.base + self.text.len());
+                }
+                break;
+            }
+            self.pending.extend_from_slice(bytes);
+            let consumed = bytes.len();
+            self.reader.consume(consumed);
+            read += consumed;
+
+            let valid = match std::str::from_utf8(&self.pending) {
+                Ok(_) => self.pending.len(),
+                Err(err) => {
+                    if err.error_len().is_some() {
+                        self.invalid_at = Some(self.base + self.text.len() + err.valid_up_to());
+                    }
+                    err.valid_up_to()
+                }
+            };
+            let text = std::str::from_utf8(&self.pending[..valid]).expect("checked above");
+            self.text.push_str(text);
+            self.pending.drain(..valid);
+        }
+        Ok(())
+    }
+
+    // lets go of the input before the first object references can be copied
+    // from, but for the last few bytes
+    fn release(&mut self) {
+        let offset = self.offset();
+        let keep = self
+            .object_cache
+            .first_start()
+            .map_or(offset, |start| start.min(offset));
+        let mut released = (keep - self.base).saturating_sub(RECENT);
+        while !self.text.is_char_boundary(released) {
+            released -= 1;
+        }
+        // once it's most of the text, so the rest isn't moved for every value
+        if released * 2 >= self.text.len() {
+            self.text.drain(..released);
+            self.base += released;
+            self.decoded -= released;
+        }
+    }
+}
+
+// the events of a top level value as it's parsed
+#[derive(Default)]
+struct EventRecorder(Vec<Event>);
+
+impl<'a> Visitor<'a> for EventRecorder {
+    fn visit_null(&mut self) {
+        self.0.push(Event::Null);
+    }
+
+    fn visit_bool(&mut self, value: bool) {
+        self.0.push(Event::Bool(value));
+    }
+
+    fn visit_int(&mut self, value: i32) {
+        self.0.push(Event::Int(value));
+    }
+
+    fn visit_float(&mut self, value: Float) {
+        self.0.push(Event::Float(value));
+    }
+
+    fn visit_string(&mut self, value: Cow<'a, str>) {
+        self.0.push(Event::String(value.into_owned()));
+    }
+
+    fn string_ref(&mut self, _index: usize, value: Cow<'a, str>) {
+        self.0.push(Event::String(value.into_owned()));
+    }
+
+    fn visit_date(&mut self, value: HaxeDate) {
+        self.0.push(Event::Date(value));
+    }
+
+    fn visit_bytes(&mut self, value: Vec<u8>) {
+        self.0.push(Event::Bytes(value));
+    }
+
+    fn object_ref(&mut self, index: usize) {
+        self.0.push(Event::Ref(index));
+    }
+
+    fn begin_array(&mut self) {
+        self.0.push(Event::StartArray);
+    }
+
+    fn null_run(&mut self, count: usize) {
+        for _ in 0..count {
+            self.0.push(Event::Null);
+        }
+    }
+
+    fn end_array(&mut self) {
+        self.0.push(Event::End);
+    }
+
+    fn begin_list(&mut self) {
+        self.0.push(Event::StartList);
+    }
+
+    fn end_list(&mut self) {
+        self.0.push(Event::End);
+    }
+
+    fn begin_string_map(&mut self) {
+        self.0.push(Event::StartStringMap);
+    }
+
+    fn end_string_map(&mut self) {
+        self.0.push(Event::End);
+    }
+
+    fn begin_int_map(&mut self) {
+        self.0.push(Event::StartIntMap);
+    }
+
+    fn int_key(&mut self, key: i32) {
+        self.0.push(Event::IntKey(key));
+    }
+
+    fn end_int_map(&mut self) {
+        self.0.push(Event::End);
+    }
+
+    fn begin_object_map(&mut self) {
+        self.0.push(Event::StartObjectMap);
+    }
+
+    fn end_object_map(&mut self) {
+        self.0.push(Event::End);
+    }
+
+    fn begin_struct(&mut self) {
+        self.0.push(Event::StartStruct);
+    }
+
+    fn field_name(&mut self, name: Cow<'a, str>) {
+        self.0.push(Event::Field(name.into_owned()));
+    }
+
+    fn end_struct(&mut self) {
+        self.0.push(Event::End);
+    }
+
+    fn begin_class(&mut self, name: Cow<'a, str>) {
+        self.0.push(Event::StartClass {
+            name: name.into_owned(),
+        });
+    }
+
+    fn end_class(&mut self) {
+        self.0.push(Event::End);
+    }
+
+    fn begin_enum(&mut self, name: Cow<'a, str>, constructor: Cow<'a, str>, len: usize) {
+        self.0.push(Event::StartEnum {
+            name: name.into_owned(),
+            constructor: constructor.into_owned(),
+            len,
+        });
+    }
+
+    fn begin_enum_index(&mut self, name: Cow<'a, str>, index: usize, len: usize) {
+        self.0.push(Event::StartEnumIndex {
+            name: name.into_owned(),
+            index,
+            len,
+        });
+    }
+
+    fn end_enum(&mut self) {
+        self.0.push(Event::End);
+    }
+
+    fn begin_exception(&mut self) {
+        self.0.push(Event::StartException);
+    }
+
+    fn end_exception(&mut self) {
+        self.0.push(Event::End);
+    }
+
+    fn begin_custom(&mut self, name: Cow<'a, str>) {
+        self.0.push(Event::StartCustom {
+            name: name.into_owned(),
+        });
+    }
+
+    fn end_custom(&mut self) {
+        self.0.push(Event::End);
+    }
+}
+
+impl<R: BufRead> Iterator for StreamDecoder<R> {
+    type Item = Result<Value<'static>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_value()
+    }
+}
+
+/// Iterator over the events of a [`StreamDecoder`]
+pub struct Events<'d, R>(&'d mut StreamDecoder<R>);
+
+impl<R: BufRead> Iterator for Events<'_, R> {
+    type Item = Result<Event>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next_event()
+    }
+}
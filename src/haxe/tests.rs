@@ -1,8 +1,43 @@
+use crate::haxe;
 use vecmap::VecMap as Map;
 
 use super::*;
 
+// every kind of value, in the formats values are exported to
+#[cfg(any(feature = "export-yaml", feature = "export-toml"))]
+const EVERY_VARIANT: [&str; 27] = [
+    "n",
+    "t",
+    "z",
+    "i-12",
+    "d1.5",
+    "d-0",
+    "k",
+    "p",
+    "m",
+    "y5:hello",
+    "y4:Null",
+    "v2024-01-01 10:00:00",
+    "v1700000000000.5",
+    "s4:AQID",
+    "ai1nh",
+    "li1nh",
+    "by1:ai1h",
+    "q:1n:-2i3h",
+    "q:2i1:1nh",
+    "oy1:bzy1:ang",
+    "Moy1:xi1gy1:ai2i3h",
+    "og",
+    "cy9:game.Itemy2:idi3g",
+    "wy5:Colory3:Rgb:3i1i2i3",
+    "jy4:Mode:1:1n",
+    "xd1.5",
+    "Cy5:Pointi1i2g",
+];
+
 mod roundtrip {
+    use std::borrow::Cow;
+
     use super::*;
     use value::float::Float;
 
@@ -84,17 +119,22 @@ mod roundtrip {
     fn date() {
         roundtrip_helper(
             "v2015-10-11 20:08:02",
-            &vec![Value::Date("2015-10-11 20:08:02".into())],
+            &vec![Value::Date("2015-10-11 20:08:02".parse().unwrap())],
         );
 
         roundtrip_helper(
             "v2016-11-30 06:32:22",
-            &vec![Value::Date("2016-11-30 06:32:22".into())],
+            &vec![Value::Date("2016-11-30 06:32:22".parse().unwrap())],
         );
 
         roundtrip_helper(
             "v2017-02-01 12:38:06",
-            &vec![Value::Date("2017-02-01 12:38:06".into())],
+            &vec![Value::Date("2017-02-01 12:38:06".parse().unwrap())],
+        );
+
+        roundtrip_helper(
+            "v1485952686000",
+            &vec![Value::Date("1485952686000".parse().unwrap())],
         );
     }
 
@@ -206,7 +246,7 @@ mod roundtrip {
         roundtrip_helper(
             "Moy4:namey1:agi12oR0y1:bgd2.71oR0y1:cgfh",
             &vec![Value::ObjectMap({
-                fn make_struct_key(name: &str) -> Value {
+                fn make_struct_key(name: &str) -> Value<'_> {
                     let fields = {
                         let mut map = Map::new();
                         map.insert("name".into(), Value::String(name.into()));
@@ -337,16 +377,61 @@ mod roundtrip {
         );
     }
 
+    #[test]
+    fn enum_index_object() {
+        fn make_enum_index<'a, const N: usize>(
+            name: &'static str,
+            index: usize,
+            fields: [Value<'a>; N],
+        ) -> Value<'a> {
+            Value::EnumIndex {
+                name: name.into(),
+                index,
+                fields: fields.into(),
+            }
+        }
+
+        roundtrip_helper("jy0::0:0", &vec![make_enum_index("", 0, [])]);
+        roundtrip_helper("jy3:foo:12:0", &vec![make_enum_index("foo", 12, [])]);
+
+        roundtrip_helper(
+            "jy3:foo:1:2nz",
+            &vec![make_enum_index("foo", 1, [Value::Null, Value::Int(0)])],
+        );
+
+        roundtrip_helper(
+            "jy3:foo:0:3ni10fjR0:2:1jR0:3:0",
+            &vec![
+                make_enum_index("foo", 0, [Value::Null, Value::Int(10), Value::Bool(false)]),
+                make_enum_index("foo", 2, [make_enum_index("foo", 3, [])]),
+            ],
+        );
+    }
+
     #[test]
     fn exception() {
-        todo!()
+        roundtrip_helper("xn", &vec![Value::Exception(Box::new(Value::Null))]);
+        roundtrip_helper(
+            "xy5:error",
+            &vec![Value::Exception(Box::new(Value::String("error".into())))],
+        );
+
+        roundtrip_helper(
+            "xoy7:messagey4:failgxR0",
+            &vec![
+                Value::Exception(Box::new(Value::Struct {
+                    fields: [("message".into(), Value::String("fail".into()))].into(),
+                })),
+                Value::Exception(Box::new(Value::String("message".into()))),
+            ],
+        );
     }
 
     #[test]
     fn custom() {
         roundtrip_helper(
             "Cy0:ahahg",
-            &vec![Value::Custom {
+            &vec![Value::CustomFields {
                 name: "".into(),
                 fields: Map::new(),
             }],
@@ -354,7 +439,7 @@ mod roundtrip {
 
         roundtrip_helper(
             "Cy3:fooay1:ay1:by1:chai12d2.71fhg",
-            &vec![Value::Custom {
+            &vec![Value::CustomFields {
                 name: "foo".into(),
                 fields: {
                     let mut map = Map::new();
@@ -386,6 +471,127 @@ mod roundtrip {
             ],
         );
     }
+
+    #[test]
+    fn borrowed_strings() {
+        let values = from_str("y3:abcy5:a%20bR0").unwrap();
+        assert!(matches!(&values[0], Value::String(Cow::Borrowed("abc"))));
+        assert!(matches!(&values[1], Value::String(Cow::Owned(s)) if s == "a b"));
+        assert!(matches!(&values[2], Value::String(Cow::Borrowed("abc"))));
+    }
+
+    #[test]
+    fn object_reference() {
+        let struct_value = Value::Struct {
+            fields: [("a".into(), Value::Int(0))].into(),
+        };
+
+        // references are copied by default
+        assert_eq!(
+            from_str("aoy1:azgr1h").unwrap(),
+            vec![Value::Array(vec![
+                struct_value.clone(),
+                struct_value.clone()
+            ])],
+        );
+
+        let data = "aoy1:azgr1h";
+        let values = Unserializer::new()
+            .keep_references(true)
+            .decode(data)
+            .unwrap();
+        assert_eq!(
+            values,
+            vec![Value::Array(vec![struct_value, Value::Ref(1)])]
+        );
+        assert_eq!(to_string(&values), data, "value failed to roundtrip");
+
+        // enums are only cached after their fields
+        let enum_value = Value::Enum {
+            name: "E".into(),
+            constructor: "A".into(),
+            fields: vec![Value::Array(vec![])],
+        };
+        assert_eq!(
+            from_str("awy1:Ey1:A:1ahr1r2h").unwrap(),
+            vec![Value::Array(vec![
+                enum_value.clone(),
+                Value::Array(vec![]),
+                enum_value
+            ])],
+        );
+
+        let data = "awy1:Ey1:A:1ahr1r2h";
+        let values = Unserializer::new()
+            .keep_references(true)
+            .decode(data)
+            .unwrap();
+        assert_eq!(to_string(&values), data, "value failed to roundtrip");
+
+        // referenced objects keep the strings and references inside of them
+        let inner = Value::Struct {
+            fields: [("a".into(), Value::String("b".into()))].into(),
+        };
+        let outer = Value::Struct {
+            fields: [("x".into(), inner.clone()), ("y".into(), inner.clone())].into(),
+        };
+        assert_eq!(
+            from_str("aoy1:xoy1:ay1:bgy1:yr2gR0r1r2h").unwrap(),
+            vec![Value::Array(vec![
+                outer.clone(),
+                Value::String("x".into()),
+                outer,
+                inner
+            ])],
+        );
+
+        // references to an object they're inside of would make the value
+        // contain itself, so they're cut with a null
+        assert_eq!(
+            from_str("aoy1:ar0y1:br1gr1h").unwrap(),
+            vec![Value::Array(vec![
+                Value::Struct {
+                    fields: [("a".into(), Value::Null), ("b".into(), Value::Null)].into(),
+                },
+                Value::Struct {
+                    fields: [("a".into(), Value::Null), ("b".into(), Value::Null)].into(),
+                },
+            ])],
+        );
+    }
+
+    #[test]
+    fn object_cache() {
+        fn use_cache_roundtrip_helper(data: &str) {
+            let values = from_str(data).unwrap();
+            let roundtriped_data = Serializer::new().use_cache(true).encode(&values);
+            assert_eq!(roundtriped_data, data, "value failed to roundtrip");
+        }
+
+        use_cache_roundtrip_helper("aoy1:azgr1h");
+        use_cache_roundtrip_helper("awy1:Ey1:A:1ahr1r2h");
+        use_cache_roundtrip_helper("abhr1oy1:ar1gr2h");
+
+        // both of a custom's arrays take up a slot
+        use_cache_roundtrip_helper("aCy1:Cay1:xhai1hgahr4h");
+
+        // without the cache, equal objects are written out in full
+        let values = from_str("aoy1:azgr1h").unwrap();
+        assert_eq!(to_string(&values), "aoy1:azgoR0zgh");
+
+        // kept references follow objects that moved when equal ones before
+        // them were written as references
+        let values = Unserializer::new()
+            .keep_references(true)
+            .decode("aoy1:xzgoy1:xzgr2h")
+            .unwrap();
+        let data = Serializer::new().use_cache(true).encode(&values);
+        assert_eq!(data, "aoy1:xzgr1r1h");
+        assert_eq!(
+            from_str(&data).unwrap(),
+            from_str("aoy1:xzgoy1:xzgr2h").unwrap()
+        );
+    }
 }
 
 #[cfg(feature = "export-json")]
@@ -475,17 +681,22 @@ mod roundtrip_json {
     fn date() {
         roundtrip_json_helper(
             "v2015-10-11 20:08:02",
-            &vec![Value::Date("2015-10-11 20:08:02".into())],
+            &vec![Value::Date("2015-10-11 20:08:02".parse().unwrap())],
         );
 
         roundtrip_json_helper(
             "v2016-11-30 06:32:22",
-            &vec![Value::Date("2016-11-30 06:32:22".into())],
+            &vec![Value::Date("2016-11-30 06:32:22".parse().unwrap())],
         );
 
         roundtrip_json_helper(
             "v2017-02-01 12:38:06",
-            &vec![Value::Date("2017-02-01 12:38:06".into())],
+            &vec![Value::Date("2017-02-01 12:38:06".parse().unwrap())],
+        );
+
+        roundtrip_json_helper(
+            "v1485952686000",
+            &vec![Value::Date("1485952686000".parse().unwrap())],
         );
     }
 
@@ -597,7 +808,7 @@ mod roundtrip_json {
         roundtrip_json_helper(
             "Moy4:namey1:agi12oR0y1:bgd2.71oR0y1:cgfh",
             &vec![Value::ObjectMap({
-                fn make_struct_key(name: &str) -> Value {
+                fn make_struct_key(name: &str) -> Value<'_> {
                     let fields = {
                         let mut map = Map::new();
                         map.insert("name".into(), Value::String(name.into()));
@@ -728,16 +939,61 @@ mod roundtrip_json {
         );
     }
 
+    #[test]
+    fn enum_index_object() {
+        fn make_enum_index<'a, const N: usize>(
+            name: &'static str,
+            index: usize,
+            fields: [Value<'a>; N],
+        ) -> Value<'a> {
+            Value::EnumIndex {
+                name: name.into(),
+                index,
+                fields: fields.into(),
+            }
+        }
+
+        roundtrip_json_helper("jy0::0:0", &vec![make_enum_index("", 0, [])]);
+        roundtrip_json_helper("jy3:foo:12:0", &vec![make_enum_index("foo", 12, [])]);
+
+        roundtrip_json_helper(
+            "jy3:foo:1:2nz",
+            &vec![make_enum_index("foo", 1, [Value::Null, Value::Int(0)])],
+        );
+
+        roundtrip_json_helper(
+            "jy3:foo:0:3ni10fjR0:2:1jR0:3:0",
+            &vec![
+                make_enum_index("foo", 0, [Value::Null, Value::Int(10), Value::Bool(false)]),
+                make_enum_index("foo", 2, [make_enum_index("foo", 3, [])]),
+            ],
+        );
+    }
+
     #[test]
     fn exception() {
-        todo!()
+        roundtrip_json_helper("xn", &vec![Value::Exception(Box::new(Value::Null))]);
+        roundtrip_json_helper(
+            "xy5:error",
+            &vec![Value::Exception(Box::new(Value::String("error".into())))],
+        );
+
+        roundtrip_json_helper(
+            "xoy7:messagey4:failgxR0",
+            &vec![
+                Value::Exception(Box::new(Value::Struct {
+                    fields: [("message".into(), Value::String("fail".into()))].into(),
+                })),
+                Value::Exception(Box::new(Value::String("message".into()))),
+            ],
+        );
     }
 
     #[test]
     fn custom() {
         roundtrip_json_helper(
             "Cy0:ahahg",
-            &vec![Value::Custom {
+            &vec![Value::CustomFields {
                 name: "".into(),
                 fields: Map::new(),
             }],
@@ -745,7 +1001,7 @@ mod roundtrip_json {
 
         roundtrip_json_helper(
             "Cy3:fooay1:ay1:by1:chai12d2.71fhg",
-            &vec![Value::Custom {
+            &vec![Value::CustomFields {
                 name: "foo".into(),
                 fields: {
                     let mut map = Map::new();
@@ -756,6 +1012,18 @@ mod roundtrip_json {
                 },
             }],
         );
+
+        // exports from before custom codecs still load
+        let json = r#"[{"Custom":{"name":"foo","fields":{"a":{"Int":12}}}}]"#;
+        let values: Vec<Value> = serde_json::from_str(json).unwrap();
+        assert_eq!(values, from_str("Cy3:fooay1:ahai12hg").unwrap());
+        assert_eq!(serde_json::to_string(&values).unwrap(), json);
+
+        let values = from_str("Cy3:fooi1g").unwrap();
+        assert_eq!(
+            serde_json::to_string(&values).unwrap(),
+            r#"[{"CustomRaw":{"name":"foo","raw":[{"Int":1}]}}]"#
+        );
     }
 
     #[test]
@@ -778,3 +1046,2706 @@ mod roundtrip_json {
         );
     }
 }
+
+#[cfg(feature = "export-yaml")]
+mod roundtrip_yaml {
+    use super::*;
+
+    #[test]
+    fn every_variant() {
+        for hxon in EVERY_VARIANT {
+            let values = from_str(hxon).unwrap();
+            let yaml = to_yaml(&values).unwrap();
+            let decoded: Vec<Value> = from_yaml(yaml.as_bytes()).unwrap();
+            assert_eq!(decoded, values, "{yaml}");
+            assert_eq!(to_string(&decoded), hxon, "{yaml}");
+        }
+    }
+
+    #[test]
+    fn written() {
+        // enums are single key maps, even inside other enums
+        let values = from_str("xd2.5").unwrap();
+        assert_eq!(
+            to_yaml(&values).unwrap(),
+            "- Exception:\n    Float:\n      Normal: 2.5\n"
+        );
+
+        let yaml = "
+            - Struct:
+                fields:
+                  gold:
+                    Int: 20 # comments are allowed
+            - 'Null'
+        ";
+        let values: Vec<Value> = from_yaml(yaml.as_bytes()).unwrap();
+        assert_eq!(values, [haxe!({ gold: 20 }), Value::Null]);
+    }
+}
+
+#[cfg(feature = "export-toml")]
+mod roundtrip_toml {
+    use super::*;
+
+    #[test]
+    fn every_variant() {
+        for hxon in EVERY_VARIANT {
+            let values = from_str(hxon).unwrap();
+            let toml = to_toml(&values).unwrap();
+            let decoded = from_toml(&toml).unwrap();
+            assert_eq!(decoded, values, "{toml}");
+            assert_eq!(to_string(&decoded), hxon, "{toml}");
+        }
+    }
+
+    #[test]
+    fn written() {
+        // the values are in a `values` array of the file's table
+        let values = from_str("i1y1:a").unwrap();
+        assert_eq!(
+            to_toml(&values).unwrap(),
+            "values = [\n    { Int = 1 },\n    { String = \"a\" },\n]\n"
+        );
+        // int map keys are strings, and entries stay in order
+        let values = from_str("q:2i1:-2nh").unwrap();
+        assert_eq!(
+            to_toml(&values).unwrap(),
+            "values = [\n    { IntMap = { 2 = { Int = 1 }, -2 = \"Null\" } },\n]\n"
+        );
+
+        let toml = r#"
+            # comments are allowed
+            [[values]]
+            Struct.fields.gold.Int = 20
+        "#;
+        assert_eq!(from_toml(toml).unwrap(), [haxe!({ gold: 20 })]);
+        assert!(from_toml("Int = 1").is_err());
+    }
+}
+
+mod decode_error {
+    use super::*;
+
+    #[test]
+    fn syntax() {
+        let err = from_str("oy1:ai1").unwrap_err();
+        assert!(
+            matches!(err, DecodeError::Syntax { offset: 7, .. }),
+            "{err:?}"
+        );
+
+        let err = from_str("iabc").unwrap_err();
+        assert!(
+            matches!(err, DecodeError::Syntax { offset: 1, .. }),
+            "{err:?}"
+        );
+    }
+
+    #[test]
+    fn unknown_tag() {
+        let err = from_str("azZh").unwrap_err();
+        assert_eq!(
+            err,
+            DecodeError::UnknownTag {
+                tag: 'Z',
+                offset: 2,
+                snippet: "azZh".into()
+            }
+        );
+    }
+
+    #[test]
+    fn bad_string_cache_ref() {
+        let err = from_str("y1:aR1").unwrap_err();
+        assert!(
+            matches!(
+                err,
+                DecodeError::BadStringCacheRef {
+                    index: 1,
+                    offset: 4,
+                    ..
+                }
+            ),
+            "{err:?}"
+        );
+    }
+
+    #[test]
+    fn bad_object_cache_ref() {
+        let err = from_str("ahr1").unwrap_err();
+        assert!(
+            matches!(
+                err,
+                DecodeError::BadObjectCacheRef {
+                    index: 1,
+                    offset: 2,
+                    ..
+                }
+            ),
+            "{err:?}"
+        );
+    }
+
+    #[test]
+    fn invalid_base64() {
+        let err = from_str("s4:Y!==").unwrap_err();
+        assert!(
+            matches!(err, DecodeError::InvalidBase64 { offset: 3, .. }),
+            "{err:?}"
+        );
+    }
+
+    #[test]
+    fn invalid_utf8() {
+        let err = from_str("y3:%FF").unwrap_err();
+        assert!(
+            matches!(err, DecodeError::InvalidUtf8 { offset: 3, .. }),
+            "{err:?}"
+        );
+    }
+
+    #[test]
+    fn invalid_date() {
+        let err = from_str("v2015-13-11 20:08:02").unwrap_err();
+        assert!(
+            matches!(err, DecodeError::InvalidDate { offset: 1, .. }),
+            "{err:?}"
+        );
+    }
+
+    #[test]
+    fn too_many_nulls() {
+        let err = from_str("au99999999999999h").unwrap_err();
+        assert!(
+            matches!(err, DecodeError::TooManyNulls { offset: 1, .. }),
+            "{err:?}"
+        );
+
+        // runs add up across the whole input
+        let run = format!("au{}h", MAX_NULLS / 2);
+        let Value::Array(items) = &from_str(&run).unwrap()[0] else {
+            panic!("not an array");
+        };
+        assert_eq!(items.len(), MAX_NULLS / 2);
+        let err = from_str(&format!("{run}{run}{run}")).unwrap_err();
+        assert!(matches!(err, DecodeError::TooManyNulls { .. }), "{err:?}");
+
+        let stream = StreamDecoder::new("au99999999999999h".as_bytes());
+        let err = stream.collect::<Result<Vec<_>, _>>().unwrap_err();
+        assert!(
+            matches!(
+                err,
+                crate::haxe::stream::Error::Decode(DecodeError::TooManyNulls { offset: 1, .. })
+            ),
+            "{err:?}"
+        );
+        assert!(from_str_typed::<Vec<Vec<()>>>("au99999999999999h").is_err());
+    }
+
+    #[test]
+    fn too_deep() {
+        let deep = "a".repeat(200_000);
+        let err = from_str(&deep).unwrap_err();
+        assert!(
+            matches!(
+                err,
+                DecodeError::TooDeep {
+                    offset: MAX_DEPTH,
+                    ..
+                }
+            ),
+            "{err:?}"
+        );
+        let err = StreamDecoder::new(deep.as_bytes()).next_value().unwrap();
+        assert!(
+            matches!(
+                err,
+                Err(crate::haxe::stream::Error::Decode(DecodeError::TooDeep {
+                    offset: MAX_DEPTH,
+                    ..
+                }))
+            ),
+            "{err:?}"
+        );
+        let err = from_str_typed::<(serde::de::IgnoredAny,)>(&deep).unwrap_err();
+        assert!(
+            matches!(
+                err,
+                haxe::typed::Error::Decode(DecodeError::TooDeep {
+                    offset: MAX_DEPTH,
+                    ..
+                })
+            ),
+            "{err:?}"
+        );
+        let err = from_text(&"[".repeat(200_000)).unwrap_err();
+        assert!(err.too_deep && err.offset == MAX_DEPTH, "{err:?}");
+
+        // references count the values they copy as nested where they are
+        let tall = format!("{}{}", "a".repeat(100), "h".repeat(100));
+        let err = from_str(&format!("{tall}{}r0{}", "a".repeat(50), "h".repeat(50))).unwrap_err();
+        assert!(matches!(err, DecodeError::TooDeep { .. }), "{err:?}");
+
+        let deepest = format!("{}{}", "a".repeat(MAX_DEPTH), "h".repeat(MAX_DEPTH));
+        assert_eq!(from_str(&deepest).unwrap().len(), 1);
+        let mut stream = StreamDecoder::new(deepest.as_bytes());
+        assert!(stream.next_value().unwrap().is_ok());
+        assert!(from_str_typed::<(serde::de::IgnoredAny,)>(&deepest).is_ok());
+        let text = format!("{}{}", "[".repeat(MAX_DEPTH), "]".repeat(MAX_DEPTH));
+        assert_eq!(from_text(&text).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn too_many_copies() {
+        // every array holds the one before it twice, doubling what the last
+        // one copies
+        let mut input = "ah".to_owned();
+        for i in 0..30 {
+            input += &format!("ar{i}r{i}h");
+        }
+        let err = from_str(&input).unwrap_err();
+        assert!(matches!(err, DecodeError::TooManyCopies { .. }), "{err:?}");
+        let err = StreamDecoder::new(input.as_bytes()).collect::<Result<Vec<_>, _>>();
+        assert!(
+            matches!(
+                err,
+                Err(crate::haxe::stream::Error::Decode(
+                    DecodeError::TooManyCopies { .. }
+                ))
+            ),
+            "{err:?}"
+        );
+        let err = from_str_typed::<serde::de::IgnoredAny>(&input).unwrap_err();
+        assert!(
+            matches!(
+                err,
+                haxe::typed::Error::Decode(DecodeError::TooManyCopies { .. })
+            ),
+            "{err:?}"
+        );
+
+        // kept references copy nothing
+        let values = Unserializer::new()
+            .keep_references(true)
+            .decode(&input)
+            .unwrap();
+        assert_eq!(values.len(), 31);
+    }
+
+    #[test]
+    fn snippet() {
+        let input = "oy4:namey32:abcdefghijklmnopqrstuvwxyz012345y3:agei1y3:bad?g";
+        let err = from_str(input).unwrap_err();
+        assert_eq!(err.offset(), 58);
+        assert_eq!(err.snippet(), "45y3:agei1y3:bad?g");
+    }
+}
+
+mod serializer {
+    use super::*;
+    use value::float::Float;
+
+    #[test]
+    fn enum_index() {
+        let values = from_str("wy5:Colory4:Blue:0jR0:0:0wR0y3:Red:1z").unwrap();
+        let serializer = Serializer::new().enum_constructors("Color", ["Red", "Green", "Blue"]);
+
+        // enums are written as they were decoded by default
+        assert_eq!(
+            serializer.encode(&values),
+            "wy5:Colory4:Blue:0jR0:0:0wR0y3:Red:1z"
+        );
+        assert_eq!(
+            serializer.clone().use_enum_index(true).encode(&values),
+            "jy5:Color:2:0jR0:0:0jR0:0:1z"
+        );
+        assert_eq!(
+            serializer.use_enum_index(false).encode(&values),
+            "wy5:Colory4:Blue:0wR0y3:Red:0wR0R2:1z"
+        );
+
+        // unknown enums are left alone
+        assert_eq!(
+            Serializer::new().use_enum_index(true).encode(&values),
+            "wy5:Colory4:Blue:0jR0:0:0wR0y3:Red:1z"
+        );
+    }
+
+    #[test]
+    fn string_cache() {
+        let values = from_str("y1:aR0").unwrap();
+        assert_eq!(
+            Serializer::new().use_string_cache(false).encode(&values),
+            "y1:ay1:a"
+        );
+    }
+
+    #[test]
+    fn float_format() {
+        let floats = [
+            (1.0, "d1", "d1"),
+            (-0.5, "d-0.5", "d-0.5"),
+            (1e-7, "d0.0000001", "d1e-7"),
+            (1.5e-7, "d0.00000015", "d1.5e-7"),
+            (0.000_001, "d0.000001", "d0.000001"),
+            (1e21, "d1000000000000000000000", "d1e+21"),
+            (1.25e22, "d12500000000000000000000", "d1.25e+22"),
+            (1e20, "d100000000000000000000", "d100000000000000000000"),
+            (123.456, "d123.456", "d123.456"),
+        ];
+
+        for (float, shortest, js) in floats {
+            let values = [Value::Float(Float::new(float))];
+            assert_eq!(Serializer::new().encode(&values), shortest);
+            assert_eq!(
+                Serializer::new()
+                    .float_format(FloatFormat::Js)
+                    .encode(&values),
+                js
+            );
+        }
+    }
+
+    #[test]
+    fn hxcpp_float_format() {
+        let floats = [
+            (1.0, "d1"),
+            (-0.5, "d-0.5"),
+            (-0.0, "d-0"),
+            (0.1 + 0.2, "d0.3"),
+            (1.0 / 3.0, "d0.333333333333333"),
+            (1e-7, "d1e-07"),
+            (0.0001, "d0.0001"),
+            (0.000_012_5, "d1.25e-05"),
+            (1e15, "d1e+15"),
+            (123_456_789_012_345.0, "d123456789012345"),
+            (9_999_999_999_999_999.0, "d1e+16"),
+            (1.25e300, "d1.25e+300"),
+        ];
+
+        for (float, hxcpp) in floats {
+            let values = [Value::Float(Float::new(float))];
+            assert_eq!(
+                Serializer::new()
+                    .float_format(FloatFormat::Hxcpp)
+                    .encode(&values),
+                hxcpp
+            );
+        }
+    }
+
+    #[test]
+    fn negative_zero() {
+        let values = from_str("d-0d0").unwrap();
+        assert_ne!(values[0], values[1]);
+        assert_eq!(to_string(&values), "d-0d0");
+    }
+
+    #[test]
+    fn encode_set() {
+        let values = [Value::String("a b(c)!*".into())];
+        assert_eq!(Serializer::new().encode(&values), "y18:a%20b%28c%29%21%2A");
+        assert_eq!(
+            Serializer::new().encode_set(JS_ENCODE_SET).encode(&values),
+            "y10:a%20b(c)!*"
+        );
+    }
+
+    #[test]
+    fn writers() {
+        let values = from_str("oy1:ay1:by1:cR1g").unwrap();
+
+        let mut fmt_output = String::new();
+        Serializer::new()
+            .encode_to_fmt_writer(&values, &mut fmt_output)
+            .unwrap();
+        assert_eq!(fmt_output, "oy1:ay1:by1:cR1g");
+
+        let mut io_output = Vec::new();
+        Serializer::new()
+            .encode_to_writer(&values, &mut io_output)
+            .unwrap();
+        assert_eq!(io_output, b"oy1:ay1:by1:cR1g");
+    }
+}
+
+mod string_cache {
+    use super::*;
+
+    #[test]
+    fn many_distinct_strings() {
+        let values: Vec<Value> = (0..5000)
+            .map(|n| Value::String(format!("string{n}").into()))
+            .chain(
+                (0..5000)
+                    .rev()
+                    .map(|n| Value::String(format!("string{n}").into())),
+            )
+            .collect();
+
+        let data = to_string(&values);
+        assert_eq!(from_str(&data).unwrap(), values);
+
+        // every string after the first 5000 is a reference
+        assert_eq!(data.matches('R').count(), 5000);
+        assert!(data.starts_with("y7:string0y7:string1"));
+        assert!(data.ends_with("R3R2R1R0"));
+    }
+
+    #[test]
+    fn equal_strings_in_different_places() {
+        let values = vec![Value::Class {
+            name: "a".into(),
+            fields: [
+                ("a".into(), Value::String("b".into())),
+                (
+                    "b".into(),
+                    Value::Enum {
+                        name: "a".into(),
+                        constructor: "b".into(),
+                        fields: vec![],
+                    },
+                ),
+            ]
+            .into(),
+        }];
+
+        let data = to_string(&values);
+        assert_eq!(data, "cy1:aR0y1:bR1wR0R1:0g");
+        assert_eq!(from_str(&data).unwrap(), values);
+    }
+}
+
+mod date {
+    use super::*;
+
+    #[test]
+    fn forms() {
+        let values = from_str("v2015-10-11 20:08:02v1444594082000").unwrap();
+        let [Value::Date(local), Value::Date(timestamp)] = values.as_slice() else {
+            panic!("expected two dates: {values:?}");
+        };
+        assert!(matches!(local, HaxeDate::Local(_)));
+        assert!(matches!(timestamp, HaxeDate::Timestamp(_)));
+        assert_eq!(local.unix_millis(), timestamp.unix_millis());
+        assert_eq!(to_string(&values), "v2015-10-11 20:08:02v1444594082000");
+    }
+
+    #[test]
+    fn validation() {
+        assert!(LocalDate::new(2016, 2, 29, 0, 0, 0).is_ok());
+        assert_eq!(
+            LocalDate::new(2015, 2, 29, 0, 0, 0),
+            Err(DateError::OutOfRange {
+                field: "day",
+                value: 29
+            })
+        );
+        assert!("2015-10-11 24:00:00".parse::<HaxeDate>().is_err());
+        assert!("2015-10-11T20:08:02".parse::<HaxeDate>().is_err());
+        assert!("2015-1-11 20:08:02".parse::<HaxeDate>().is_err());
+        assert_eq!(
+            HaxeDate::from_unix_millis(f64::NAN),
+            Err(DateError::NotFinite)
+        );
+    }
+
+    #[test]
+    fn unix_time() {
+        let date = LocalDate::from_unix(0).unwrap();
+        assert_eq!(date.to_string(), "1970-01-01 00:00:00");
+
+        let date: LocalDate = "2000-02-29 23:59:59".parse().unwrap();
+        assert_eq!(date.unix(), 951_868_799);
+        assert_eq!(LocalDate::from_unix(date.unix()), Ok(date));
+        assert_eq!((date.year(), date.month(), date.day()), (2000, 2, 29));
+
+        let date = LocalDate::from_unix(-1).unwrap();
+        assert_eq!(date.to_string(), "1969-12-31 23:59:59");
+    }
+
+    #[test]
+    fn editing_keeps_form() {
+        let local: HaxeDate = "2015-10-11 20:08:02".parse().unwrap();
+        let later = local
+            .with_unix_millis(local.unix_millis() + 1500.0)
+            .unwrap();
+        assert_eq!(later.to_string(), "2015-10-11 20:08:03");
+
+        let timestamp = HaxeDate::from_unix_millis(0.0).unwrap();
+        let later = timestamp.with_unix_millis(1500.0).unwrap();
+        assert_eq!(to_string(&[Value::Date(later)]), "v1500");
+    }
+}
+
+mod lossless {
+    use super::*;
+
+    fn lossless_helper(data: &str) {
+        let (values, spelling) = Unserializer::new().decode_lossless(data).unwrap();
+        assert_eq!(
+            values,
+            from_str(data).unwrap(),
+            "lossless decoding changed values"
+        );
+        let encoded = Serializer::new().encode_lossless(&values, &spelling);
+        assert_eq!(encoded, data, "value failed to roundtrip losslessly");
+    }
+
+    #[test]
+    fn scalars() {
+        lossless_helper("i0");
+        lossless_helper("d1e-7");
+        lossless_helper("d1.50");
+        lossless_helper("d-0");
+        lossless_helper("s4:AQI=");
+        lossless_helper("zi1d1tfnkmp");
+        lossless_helper("v1.5e12v2024-01-02 03:04:05");
+
+        // float lexemes survive any float format
+        let (values, spelling) = Unserializer::new()
+            .decode_lossless("d0.30000000000000004d1e-7")
+            .unwrap();
+        let serializer = Serializer::new().float_format(FloatFormat::Hxcpp);
+        assert_eq!(
+            serializer.encode_lossless(&values, &spelling),
+            "d0.30000000000000004d1e-7"
+        );
+        assert_eq!(serializer.encode(&values), "d0.3d1e-07");
+
+        assert_eq!(to_string(&from_str("i0d1e-7").unwrap()), "zd0.0000001");
+    }
+
+    #[test]
+    fn cycles() {
+        // haxe gives references to an object they're inside of the object
+        // itself, which only a kept reference can write back
+        for data in ["ar0h", "oy1:ar0g", "aoy1:xzgr1r0h"] {
+            let (values, spelling) = Unserializer::new().decode_lossless(data).unwrap();
+            let encoded = Serializer::new().encode_lossless(&values, &spelling);
+            assert_eq!(encoded, data);
+        }
+
+        let (values, _) = Unserializer::new().decode_lossless("ar0h").unwrap();
+        assert_eq!(values, [Value::Array(vec![Value::Ref(0)])]);
+        assert_eq!(from_str("ar0h").unwrap(), [Value::Array(vec![Value::Null])]);
+    }
+
+    #[test]
+    fn null_runs() {
+        lossless_helper("anznu1u2h");
+        lossless_helper("annnh");
+        lossless_helper("au3h");
+        lossless_helper("Cy3:Fooay1:ay1:bhanu1hg");
+    }
+
+    #[test]
+    fn strings() {
+        lossless_helper("y3:abcR0y3:abc");
+        lossless_helper("y5:%61bcy5:a%2fb");
+        lossless_helper("oy1:xy1:xy3:abcy1:xg");
+        lossless_helper("cy3:Fooy1:xR0gwR0R1:0jR0:0:0");
+        lossless_helper("by1:aR0h");
+
+        assert_eq!(
+            to_string(&from_str("y3:abcR0y3:abc").unwrap()),
+            "y3:abcR0R0"
+        );
+    }
+
+    #[test]
+    fn object_references() {
+        lossless_helper("aoy1:xzgr1h");
+        lossless_helper("Mlhr1oy1:xi0gr2h");
+        lossless_helper("xq:1oy1:xzg:2r1h");
+
+        assert_eq!(
+            to_string(&from_str("aoy1:xzgr1h").unwrap()),
+            "aoy1:xzgoR0zgh"
+        );
+    }
+
+    #[test]
+    fn keep_references() {
+        let data = "aoy1:xi0gr1h";
+        let unserializer = Unserializer::new().keep_references(true);
+        let (values, spelling) = unserializer.decode_lossless(data).unwrap();
+        assert_eq!(Serializer::new().encode_lossless(&values, &spelling), data);
+    }
+
+    #[test]
+    fn edited_values() {
+        let data = "ai0d1e-7y3:abcR0nnh";
+        let (mut values, spelling) = Unserializer::new().decode_lossless(data).unwrap();
+        let Value::Array(items) = &mut values[0] else {
+            panic!("expected an array");
+        };
+        items[0] = Value::Int(5);
+        items[2] = Value::String("x".into());
+        items[5] = Value::Null;
+        items.push(Value::Null);
+
+        assert_eq!(
+            Serializer::new().encode_lossless(&values, &spelling),
+            "ai5d1e-7y1:xy3:abcnnnh"
+        );
+    }
+
+    #[test]
+    fn other_values() {
+        let (values, spelling) = Unserializer::new().decode_lossless("i0").unwrap();
+        assert_eq!(values, vec![Value::Int(0)]);
+
+        // spellings only apply to values that still match them
+        let values = vec![Value::Int(1), Value::Int(0)];
+        assert_eq!(Serializer::new().encode_lossless(&values, &spelling), "i1z");
+    }
+}
+
+mod custom {
+    use std::borrow::Cow;
+
+    use super::*;
+
+    // a class whose `hxSerialize` writes an int and then a string
+    #[derive(Debug)]
+    struct Point;
+
+    impl CustomCodec for Point {
+        fn decode<'a>(
+            &self,
+            raw: Vec<Value<'a>>,
+        ) -> Result<Map<Cow<'a, str>, Value<'a>>, Vec<Value<'a>>> {
+            match <[Value; 2]>::try_from(raw) {
+                Ok([x @ Value::Int(_), label @ Value::String(_)]) => {
+                    Ok([("x".into(), x), ("label".into(), label)].into())
+                }
+                Ok(raw) => Err(raw.into()),
+                Err(raw) => Err(raw),
+            }
+        }
+
+        fn encode<'v>(&self, fields: &'v Map<Cow<'v, str>, Value<'v>>) -> Vec<Encoded<'v>> {
+            ["x", "label"]
+                .iter()
+                .map(|name| fields.get(*name).map_or(Encoded::Null, Encoded::Value))
+                .collect()
+        }
+    }
+
+    fn point() -> Value<'static> {
+        Value::CustomFields {
+            name: "Point".into(),
+            fields: [
+                ("x".into(), Value::Int(1)),
+                ("label".into(), Value::String("x".into())),
+            ]
+            .into(),
+        }
+    }
+
+    #[test]
+    fn unknown_payloads_stay_raw() {
+        let data = "Cy5:Pointi1y1:xgCy3:fooay1:ahahg";
+        let values = from_str(data).unwrap();
+        assert_eq!(
+            values,
+            vec![
+                Value::Custom {
+                    name: "Point".into(),
+                    raw: vec![Value::Int(1), Value::String("x".into())],
+                },
+                Value::Custom {
+                    name: "foo".into(),
+                    raw: vec![
+                        Value::Array(vec![Value::String("a".into())]),
+                        Value::Array(vec![]),
+                    ],
+                },
+            ]
+        );
+        assert_eq!(to_string(&values), data);
+    }
+
+    #[test]
+    fn registered_codec() {
+        let data = "Cy5:Pointi1y1:xgr0";
+        let values = Unserializer::new()
+            .custom_codec("Point", Point)
+            .decode(data)
+            .unwrap();
+        assert_eq!(values, vec![point(), point()]);
+        assert_eq!(
+            Serializer::new()
+                .use_cache(true)
+                .custom_codec("Point", Point)
+                .encode(&values),
+            data
+        );
+
+        let mut decoder = StreamDecoder::new(data.as_bytes()).custom_codec("Point", Point);
+        assert_eq!(decoder.next_value().unwrap().unwrap(), point());
+
+        #[derive(serde::Deserialize, Debug, PartialEq)]
+        struct Labeled<'a> {
+            x: i32,
+            label: &'a str,
+        }
+
+        let mut deserializer =
+            haxe::typed::Deserializer::from_str(data).custom_codec("Point", Point);
+        for _ in 0..2 {
+            let labeled: Labeled = serde::Deserialize::deserialize(&mut deserializer).unwrap();
+            assert_eq!(labeled, Labeled { x: 1, label: "x" });
+        }
+        assert!(deserializer.is_empty());
+
+        // other classes keep using field arrays
+        let values = Unserializer::new()
+            .custom_codec("Point", Point)
+            .decode("Cy3:fooay1:ahai1hg")
+            .unwrap();
+        assert!(matches!(&values[0], Value::CustomFields { name, .. } if name == "foo"));
+    }
+
+    #[test]
+    fn missing_fields_encode_null() {
+        let values = vec![Value::CustomFields {
+            name: "Point".into(),
+            fields: Map::new(),
+        }];
+        assert_eq!(
+            Serializer::new()
+                .custom_codec("Point", Point)
+                .encode(&values),
+            "Cy5:Pointnng"
+        );
+    }
+}
+
+mod owned {
+    use std::{borrow::Cow, thread};
+
+    use super::*;
+
+    fn is_owned(value: &Value) -> bool {
+        let owned = |s: &Cow<str>| matches!(s, Cow::Owned(_));
+        let fields_owned = |fields: &Map<Cow<str>, Value>| {
+            fields
+                .iter()
+                .all(|(key, value)| owned(key) && is_owned(value))
+        };
+        match value {
+            Value::String(s) => owned(s),
+            Value::Array(values) | Value::List(values) => values.iter().all(is_owned),
+            Value::StringMap(fields) | Value::Struct { fields } => fields_owned(fields),
+            Value::IntMap(map) => map.values().all(is_owned),
+            Value::ObjectMap(map) => map.iter().all(|(k, v)| is_owned(k) && is_owned(v)),
+            Value::Class { name, fields } | Value::CustomFields { name, fields } => {
+                owned(name) && fields_owned(fields)
+            }
+            Value::Enum {
+                name,
+                constructor,
+                fields,
+            } => owned(name) && owned(constructor) && fields.iter().all(is_owned),
+            Value::EnumIndex { name, fields, .. } | Value::Custom { name, raw: fields } => {
+                owned(name) && fields.iter().all(is_owned)
+            }
+            Value::Exception(value) => is_owned(value),
+            _ => true,
+        }
+    }
+
+    const DATA: &str = "y1:aalhbR0zhq:1nhMR0y1:bhoR0ngcy1:cR0R1gwy1:Ey1:F:1R0jR4:0:1y1:dxR2\
+        Cy1:Cay1:ehai1hgCy1:Gi1gs4:AQI=v2024-01-02 03:04:05r1h";
+
+    #[test]
+    fn into_owned() {
+        let values = from_str(DATA).unwrap();
+        assert!(!values.iter().all(is_owned));
+
+        let owned: Vec<_> = values.iter().map(Value::to_owned).collect();
+        assert!(owned.iter().all(is_owned));
+        assert_eq!(owned, values);
+
+        let owned: Vec<_> = values.clone().into_iter().map(Value::into_owned).collect();
+        assert!(owned.iter().all(is_owned));
+        assert_eq!(owned, values);
+    }
+
+    #[test]
+    fn outlives_input() {
+        let data = String::from(DATA);
+        let values = from_str_owned(&data).unwrap();
+        drop(data);
+
+        let expected = from_str(DATA).unwrap();
+        let values = thread::spawn(move || values).join().unwrap();
+        assert_eq!(values, expected);
+    }
+}
+
+mod path {
+    use super::*;
+
+    fn path(s: &str) -> Path {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            path("0.player.inventory[3][\"a \\\"b\\\"\"].*[*][=y1:k][-1]").segments(),
+            [
+                Segment::Name("0".into()),
+                Segment::Name("player".into()),
+                Segment::Name("inventory".into()),
+                Segment::Int(3),
+                Segment::Str("a \"b\"".into()),
+                Segment::Wildcard,
+                Segment::Wildcard,
+                Segment::Object(Value::String("k".into())),
+                Segment::Int(-1),
+            ]
+        );
+        assert!(path("").segments().is_empty());
+        assert_eq!(path(".a"), path("a"));
+        assert_eq!(path("[0].a").segments().len(), 2);
+
+        for s in ["a.b[3][\"c\\\\\"][=oy1:xzg][*]", "[\"\"]", "[0]"] {
+            assert_eq!(path(s).to_string(), s);
+        }
+        assert_eq!(path("a.*").to_string(), "a[*]");
+
+        // names that can't be written bare are quoted
+        let names = Path::from(vec![
+            Segment::Name("a.b".into()),
+            Segment::Name("".into()),
+            Segment::Name("*".into()),
+            Segment::Name("c]".into()),
+        ]);
+        assert_eq!(names.to_string(), r#"["a.b"][""]["*"]["c]"]"#);
+        let value = from_str_owned("oy3:a.bi1g").unwrap().remove(0);
+        assert_eq!(value.get_path(&path(r#"["a.b"]"#)), Some(&Value::Int(1)));
+    }
+
+    #[test]
+    fn parse_errors() {
+        let cases = [
+            (".", PathError::Syntax { offset: 1 }),
+            ("a..b", PathError::Syntax { offset: 2 }),
+            ("a]", PathError::Syntax { offset: 1 }),
+            ("a[0]b", PathError::Syntax { offset: 4 }),
+            ("a[0", PathError::Unclosed { offset: 1 }),
+            ("a[\"b]", PathError::Unclosed { offset: 1 }),
+            ("a[\"b\"c]", PathError::Unclosed { offset: 1 }),
+            ("a[b]", PathError::InvalidKey { offset: 2 }),
+            ("a[\"\\n\"]", PathError::InvalidKey { offset: 2 }),
+            ("a[=zz]", PathError::InvalidKey { offset: 2 }),
+            ("a[=?]", PathError::InvalidKey { offset: 2 }),
+        ];
+        for (s, expected) in cases {
+            assert_eq!(s.parse::<Path>(), Err(expected), "{s}");
+        }
+    }
+
+    fn save() -> Value<'static> {
+        from_str_owned(
+            "oy6:playercy6:Playery9:inventoryaoy2:idi1y5:counti3goR3i2R4i5ghy5:statsq:1i10:-2i20h\
+             gy5:flagsby3:a.bty1:*fhy4:keysMoy1:xzgy5:firsty1:1y6:secondi1y5:thirdhy4:gearwy4:Gear\
+             y5:Sword:2i7y4:irong",
+        )
+        .unwrap()
+        .remove(0)
+    }
+
+    #[test]
+    fn get_path() {
+        let save = save();
+        let get = |s: &str| save.get_path(&path(s));
+
+        assert_eq!(get(""), Some(&save));
+        assert_eq!(get("player.inventory[1].count"), Some(&Value::Int(5)));
+        assert_eq!(get("player.inventory.0.id"), Some(&Value::Int(1)));
+        assert_eq!(get("player.stats[-2]"), Some(&Value::Int(20)));
+        assert_eq!(get("player.stats.1"), Some(&Value::Int(10)));
+        assert_eq!(get("flags[\"a.b\"]"), Some(&Value::Bool(true)));
+        assert_eq!(get("flags[\"*\"]"), Some(&Value::Bool(false)));
+        assert_eq!(get("keys[=oy1:xzg]"), Some(&Value::String("first".into())));
+        assert_eq!(get("keys.1"), Some(&Value::String("second".into())));
+        assert_eq!(get("keys[1]"), Some(&Value::String("third".into())));
+        assert_eq!(get("keys[\"1\"]"), Some(&Value::String("second".into())));
+        assert_eq!(get("gear[1]"), Some(&Value::String("iron".into())));
+        assert_eq!(get("player.inventory[*].count"), Some(&Value::Int(3)));
+
+        assert_eq!(get("player.inventory[2]"), None);
+        assert_eq!(get("player.inventory[-1]"), None);
+        assert_eq!(get("player.name"), None);
+        assert_eq!(get("player.inventory[\"0\"]"), None);
+        assert_eq!(get("player.inventory[0].id.x"), None);
+    }
+
+    #[test]
+    fn get_path_mut() {
+        let mut save = save();
+        *save
+            .get_path_mut(&path("player.inventory[1].count"))
+            .unwrap() = Value::Int(6);
+        *save.get_path_mut(&path("keys[=oy1:xzg]")).unwrap() = Value::Null;
+        *save.get_path_mut(&path("*.stats[*]")).unwrap() = Value::Int(0);
+
+        assert_eq!(
+            save.select_all(&path("player.inventory[*].count")),
+            [&Value::Int(3), &Value::Int(6)]
+        );
+        assert_eq!(save.get_path(&path("keys[=oy1:xzg]")), Some(&Value::Null));
+        assert_eq!(
+            save.select_all(&path("player.stats.*")),
+            [&Value::Int(0), &Value::Int(20)]
+        );
+        assert_eq!(save.get_path_mut(&path("player.nothing")), None);
+    }
+
+    #[test]
+    fn select_all() {
+        let save = save();
+        let select = |s: &str| save.select_all(&path(s));
+
+        assert_eq!(
+            select("player.inventory[*].id"),
+            [&Value::Int(1), &Value::Int(2)]
+        );
+        assert_eq!(select("*.*[*].id"), [&Value::Int(1), &Value::Int(2)]);
+        assert_eq!(select("flags.*"), [&Value::Bool(true), &Value::Bool(false)]);
+        assert_eq!(select("keys[*]").len(), 3);
+        assert_eq!(
+            select("gear.*"),
+            [&Value::Int(7), &Value::String("iron".into())]
+        );
+        assert_eq!(select("player.inventory[0]").len(), 1);
+        assert!(select("player.inventory[*].name").is_empty());
+        assert!(select("flags.*.*").is_empty());
+    }
+}
+
+mod diff {
+    use super::*;
+
+    fn value(data: &str) -> Value<'_> {
+        from_str(data).unwrap().remove(0)
+    }
+
+    fn report(old: &str, new: &str) -> String {
+        diff(&value(old), &value(new)).to_string()
+    }
+
+    #[test]
+    fn same() {
+        let old = value("oy1:ai1g");
+        assert_eq!(diff(&old, &old), Diff::Same);
+        assert!(diff(&old, &old).is_same());
+        assert_eq!(diff(&old, &old).to_string(), "");
+    }
+
+    #[test]
+    fn replaced() {
+        let (old, new) = (value("i1"), value("i2"));
+        assert_eq!(
+            diff(&old, &new),
+            Diff::Replaced {
+                old: &old,
+                new: &new
+            }
+        );
+        assert_eq!(report("i1", "i2"), "~ 1 -> 2\n");
+
+        // another kind of value, class or constructor
+        assert_eq!(report("i1", "y1:1"), "~ 1 -> \"1\"\n");
+        assert_eq!(report("ah", "lh"), "~ [] -> []\n");
+        assert_eq!(
+            report("cy1:Ay1:xzg", "cy1:By1:xzg"),
+            "~ class A { x: 0 } -> class B { x: 0 }\n"
+        );
+        assert_eq!(report("wy1:Ey1:A:0", "wy1:Ey1:B:0"), "~ E.A -> E.B\n");
+        assert_eq!(report("jy1:E:0:0", "jy1:E:1:0"), "~ E.0 -> E.1\n");
+    }
+
+    #[test]
+    fn fields() {
+        let (old, new) = (
+            value("oy1:ai1y1:bi2y1:coy1:dtgg"),
+            value("oy1:ai1y1:coy1:dfgy1:ei5g"),
+        );
+        assert_eq!(
+            diff(&old, &new),
+            Diff::Entries(vec![
+                Entry {
+                    key: Segment::Name("b".into()),
+                    change: Change::Removed(&Value::Int(2)),
+                },
+                Entry {
+                    key: Segment::Name("c".into()),
+                    change: Change::Changed(Diff::Entries(vec![Entry {
+                        key: Segment::Name("d".into()),
+                        change: Change::Changed(Diff::Replaced {
+                            old: &Value::Bool(true),
+                            new: &Value::Bool(false),
+                        }),
+                    }])),
+                },
+                Entry {
+                    key: Segment::Name("e".into()),
+                    change: Change::Added(&Value::Int(5)),
+                },
+            ])
+        );
+        assert_eq!(
+            diff(&old, &new).to_string(),
+            "- b: 2\n~ c.d: true -> false\n+ e: 5\n"
+        );
+
+        assert_eq!(
+            report("cy1:Ay3:a.bzg", "cy1:Ay3:a.bi1g"),
+            "~ [\"a.b\"]: 0 -> 1\n"
+        );
+        assert_eq!(report("by1:kzh", "by1:ki1h"), "~ k: 0 -> 1\n");
+    }
+
+    #[test]
+    fn maps() {
+        assert_eq!(
+            report("q:1z:2zh", "q:2i1:3zh"),
+            "- [1]: 0\n~ [2]: 0 -> 1\n+ [3]: 0\n"
+        );
+        assert_eq!(
+            report("Moy1:xzgzy1:kzh", "Moy1:xzgi1h"),
+            "~ [=oy1:xzg]: 0 -> 1\n- [=y1:k]: 0\n"
+        );
+    }
+
+    #[test]
+    fn items() {
+        // changed in place
+        assert_eq!(report("ai1i2i3h", "ai1i5i3h"), "~ [1]: 2 -> 5\n");
+        // inserted and removed in the middle
+        assert_eq!(report("ai1i2i3h", "ai1i2i9i3h"), "+ [2]: 9\n");
+        assert_eq!(report("ai1i2i3h", "ai1i3h"), "- [1]: 2\n");
+        // changed and added past the end
+        assert_eq!(
+            report("ai1i2h", "ai1i5i6i7h"),
+            "~ [1]: 2 -> 5\n+ [2]: 6\n+ [3]: 7\n"
+        );
+        assert_eq!(report("li1i2i3h", "li3h"), "- [0]: 1\n- [1]: 2\n");
+        // enum fields and custom values
+        assert_eq!(report("wy1:Ey1:A:2zz", "wy1:Ey1:A:2zi1"), "~ [1]: 0 -> 1\n");
+        assert_eq!(report("Cy1:Ci1g", "Cy1:Ci2g"), "~ [0]: 1 -> 2\n");
+    }
+}
+
+mod patch {
+    use super::*;
+
+    fn path(path: &str) -> Path {
+        path.parse().unwrap()
+    }
+
+    fn set(at: &str, value: Value<'static>) -> Operation {
+        Operation::Set {
+            path: path(at),
+            value,
+            old: None,
+        }
+    }
+
+    fn remove(at: &str) -> Operation {
+        Operation::Remove {
+            path: path(at),
+            old: None,
+        }
+    }
+
+    fn insert(at: &str, value: Value<'static>) -> Operation {
+        Operation::Insert {
+            path: path(at),
+            value,
+        }
+    }
+
+    // applies to the single value in `data`, returning it serialized
+    fn apply(data: &str, operations: Vec<Operation>) -> Result<String, PatchError> {
+        let mut value = from_str_owned(data).unwrap().remove(0);
+        Patch::from(operations).apply(&mut value)?;
+        Ok(to_string(&[value]))
+    }
+
+    #[test]
+    fn set_values() {
+        assert_eq!(
+            apply("oy1:ai1g", vec![set("a", Value::Int(2))]).unwrap(),
+            "oy1:ai2g"
+        );
+        // missing fields are added
+        assert_eq!(
+            apply("cy1:Ay1:ai1g", vec![set("b", Value::Null)]).unwrap(),
+            "cy1:Ay1:ai1y1:bng"
+        );
+        assert_eq!(
+            apply("ai1i2h", vec![set("[1]", Value::Int(5))]).unwrap(),
+            "ai1i5h"
+        );
+        assert_eq!(
+            apply(
+                "q:1zh",
+                vec![set("[1]", Value::Int(3)), set("[2]", Value::Int(4))]
+            )
+            .unwrap(),
+            "q:1i3:2i4h"
+        );
+        // object map keys match ints and strings by value
+        assert_eq!(
+            apply(
+                "Mi1zy1:kzh",
+                vec![set("[1]", Value::Bool(true)), set("k", Value::Null)]
+            )
+            .unwrap(),
+            "Mi1ty1:knh"
+        );
+        assert_eq!(
+            apply("Moy1:xzgzh", vec![set("[=oy1:xzg]", Value::Int(1))]).unwrap(),
+            "Moy1:xzgi1h"
+        );
+    }
+
+    #[test]
+    fn remove_values() {
+        assert_eq!(
+            apply("oy1:ai1y1:bi2g", vec![remove("a")]).unwrap(),
+            "oy1:bi2g"
+        );
+        assert_eq!(apply("li1i2i3h", vec![remove("[0]")]).unwrap(), "li2i3h");
+        assert_eq!(apply("q:1z:2zh", vec![remove("[1]")]).unwrap(), "q:2zh");
+        assert_eq!(
+            apply("Moy1:xzgzh", vec![remove("[=oy1:xzg]")]).unwrap(),
+            "Mh"
+        );
+    }
+
+    #[test]
+    fn insert_values() {
+        assert_eq!(
+            apply("ai1i2h", vec![insert("[0]", Value::Int(0))]).unwrap(),
+            "azi1i2h"
+        );
+        // at the end
+        assert_eq!(
+            apply("ai1i2h", vec![insert("[2]", Value::Int(3))]).unwrap(),
+            "ai1i2i3h"
+        );
+        assert_eq!(
+            apply("oy1:aahg", vec![insert("a[0]", Value::Null)]).unwrap(),
+            "oy1:aanhg"
+        );
+    }
+
+    #[test]
+    fn move_values() {
+        let move_ = |from: &str, to: &str| Operation::Move {
+            from: path(from),
+            path: path(to),
+        };
+        // into an array it's inserted
+        assert_eq!(
+            apply("oy1:ai1y1:bai2hg", vec![move_("a", "b[0]")]).unwrap(),
+            "oy1:bai1i2hg"
+        );
+        // anywhere else it's set
+        assert_eq!(
+            apply("oy1:ai1y1:bi2g", vec![move_("a", "b")]).unwrap(),
+            "oy1:bi1g"
+        );
+        assert_eq!(
+            apply("ai1i2i3h", vec![move_("[0]", "[2]")]).unwrap(),
+            "ai2i3i1h"
+        );
+    }
+
+    #[test]
+    fn old_values() {
+        let set_if = |old: Value<'static>| Operation::Set {
+            path: path("a"),
+            value: Value::Int(2),
+            old: Some(old),
+        };
+        assert_eq!(
+            apply("oy1:ai1g", vec![set_if(Value::Int(1))]).unwrap(),
+            "oy1:ai2g"
+        );
+        assert_eq!(
+            apply("oy1:ai3g", vec![set_if(Value::Int(1))]),
+            Err(PatchError::Mismatch {
+                index: 0,
+                path: path("a"),
+                expected: Box::new(Value::Int(1)),
+                found: Box::new(Value::Int(3)),
+            })
+        );
+        // something has to be there to compare against
+        assert_eq!(
+            apply("oy1:bi1g", vec![set_if(Value::Int(1))]),
+            Err(PatchError::NotFound {
+                index: 0,
+                path: path("a"),
+            })
+        );
+
+        let remove_if = Operation::Remove {
+            path: path("[1]"),
+            old: Some(Value::Int(2)),
+        };
+        assert_eq!(apply("ai1i2h", vec![remove_if.clone()]).unwrap(), "ai1h");
+        assert!(matches!(
+            apply("ai1i5h", vec![remove_if]),
+            Err(PatchError::Mismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            apply("oy1:ai1g", vec![set("b.c", Value::Null)]),
+            Err(PatchError::NotFound {
+                index: 0,
+                path: path("b.c"),
+            })
+        );
+        assert_eq!(
+            apply("ai1h", vec![remove("[1]")]),
+            Err(PatchError::NotFound {
+                index: 0,
+                path: path("[1]"),
+            })
+        );
+        // past the end of an array
+        assert!(matches!(
+            apply("ai1h", vec![insert("[2]", Value::Null)]),
+            Err(PatchError::NotFound { .. })
+        ));
+        // only arrays and lists can be inserted into
+        assert_eq!(
+            apply("oy1:ai1g", vec![insert("a", Value::Null)]),
+            Err(PatchError::InvalidTarget {
+                index: 0,
+                op: "insert",
+                path: path("a"),
+            })
+        );
+        // the value itself, wildcards, and keys of the wrong kind
+        for at in ["", "[*]", "a.b"] {
+            assert!(matches!(
+                apply("oy1:ai1g", vec![set(at, Value::Null)]),
+                Err(PatchError::InvalidTarget { .. })
+            ));
+        }
+        assert!(matches!(
+            apply("ai1h", vec![set("name", Value::Null)]),
+            Err(PatchError::InvalidTarget { .. })
+        ));
+    }
+
+    #[test]
+    fn all_or_nothing() {
+        let mut value = from_str_owned("ai1i2h").unwrap().remove(0);
+        let before = value.clone();
+        let patch = Patch::from(vec![set("[0]", Value::Int(5)), remove("[3]")]);
+        assert_eq!(
+            patch.apply(&mut value),
+            Err(PatchError::NotFound {
+                index: 1,
+                path: path("[3]"),
+            })
+        );
+        assert_eq!(value, before);
+    }
+
+    #[test]
+    fn top_level_values() {
+        let mut values = from_str_owned("i1oy1:ai2g").unwrap();
+        let patch = Patch::from(vec![set("1.a", Value::Int(3)), remove("0")]);
+        patch.apply_to_all(&mut values).unwrap();
+        assert_eq!(to_string(&values), "oy1:ai3g");
+
+        // left as they were on failure
+        assert!(Patch::from(vec![remove("5")])
+            .apply_to_all(&mut values)
+            .is_err());
+        assert_eq!(to_string(&values), "oy1:ai3g");
+    }
+
+    #[test]
+    fn cyclic_saves() {
+        // patched saves are decoded losslessly, like the patch commands do,
+        // so a child pointing back at its parent stays a reference
+        let data = "oy4:goldi5y5:childoy6:parentr0gg";
+        let (mut values, spelling) = Unserializer::new().decode_lossless(data).unwrap();
+        let patch = Patch::from(vec![set("0.gold", Value::Int(7))]);
+        patch.apply_to_all(&mut values).unwrap();
+        assert_eq!(
+            Serializer::new().encode_lossless(&values, &spelling),
+            "oy4:goldi7y5:childoy6:parentr0gg"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "export-json")]
+    fn json() {
+        let json = r#"[
+            { "op": "set", "path": "0.gold", "value": { "Int": 500 }, "old": { "Int": 20 } },
+            { "op": "remove", "path": "0.debuffs[1]" },
+            { "op": "insert", "path": "0.items[0]", "value": "Null" },
+            { "op": "move", "from": "0.pet", "path": "1" }
+        ]"#;
+        let patch: Patch = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            patch.operations,
+            vec![
+                Operation::Set {
+                    path: path("0.gold"),
+                    value: Value::Int(500),
+                    old: Some(Value::Int(20)),
+                },
+                remove("0.debuffs[1]"),
+                insert("0.items[0]", Value::Null),
+                Operation::Move {
+                    from: path("0.pet"),
+                    path: path("1"),
+                },
+            ]
+        );
+        let json = serde_json::to_string(&patch).unwrap();
+        assert_eq!(serde_json::from_str::<Patch>(&json).unwrap(), patch);
+
+        assert!(serde_json::from_str::<Patch>(r#"[{ "op": "set", "path": "a[" }]"#).is_err());
+    }
+}
+
+mod convert {
+    use std::collections::{BTreeMap, HashMap};
+
+    use super::*;
+
+    const SAVE: &str = "oy6:playercy6:Playery4:goldi20y4:namey3:Boby5:itemsai1i2hy5:speedd1.5gy5:flagsby4:seenthy6:questsq:1i3:2zhg";
+
+    #[test]
+    fn accessors() {
+        let save = from_str(SAVE).unwrap().remove(0);
+        let player = &save["player"];
+        assert_eq!(player["gold"].as_int(), Some(20));
+        assert_eq!(player["gold"].as_f64(), Some(20.0));
+        assert_eq!(player["speed"].as_f64(), Some(1.5));
+        assert_eq!(player["speed"].as_int(), None);
+        assert_eq!(player["name"].as_str(), Some("Bob"));
+        assert_eq!(save["flags"]["seen"].as_bool(), Some(true));
+        assert_eq!(
+            player["items"].as_array(),
+            Some(&vec![Value::Int(1), Value::Int(2)])
+        );
+        assert_eq!(player["items"][1], Value::Int(2));
+        assert_eq!(save["quests"][2], Value::Int(0));
+        assert_eq!(player.as_fields().unwrap().len(), 4);
+        // string maps aren't fields
+        assert_eq!(save["flags"].as_fields(), None);
+        assert_eq!(save["quests"].as_fields(), None);
+
+        // missing entries and the wrong kind of key index as null
+        assert!(save["missing"].is_null());
+        assert!(player["items"][5].is_null());
+        assert!(player["items"]["name"].is_null());
+        assert!(player["gold"]["x"].is_null());
+        assert_eq!(save.get("missing"), None);
+        assert_eq!(save.get("player").map(Value::kind), Some("class"));
+
+        // object map keys are looked up by value
+        let map = from_str("Mi1y1:ay1:ky1:bh").unwrap().remove(0);
+        assert_eq!(map[1].as_str(), Some("a"));
+        assert_eq!(map["k"].as_str(), Some("b"));
+    }
+
+    #[test]
+    fn mutable_accessors() {
+        let mut save = from_str(SAVE).unwrap().remove(0);
+        save["player"]["gold"] = Value::Int(500);
+        save["player"]["items"]
+            .as_array_mut()
+            .unwrap()
+            .push(Value::Int(3));
+        save["player"]
+            .as_fields_mut()
+            .unwrap()
+            .insert("level".into(), Value::Int(1));
+        *save.get_mut("quests").unwrap().get_mut(1).unwrap() = Value::Null;
+
+        assert_eq!(save["player"]["gold"], Value::Int(500));
+        assert_eq!(save["player"]["items"].as_array().unwrap().len(), 3);
+        assert_eq!(save["player"]["level"], Value::Int(1));
+        assert!(save["quests"][1].is_null());
+    }
+
+    #[test]
+    #[should_panic(expected = "no [\"missing\"] in struct")]
+    fn index_mut_missing() {
+        let mut save = from_str(SAVE).unwrap().remove(0);
+        save["missing"] = Value::Null;
+    }
+
+    #[test]
+    fn from() {
+        assert_eq!(Value::from(true), Value::Bool(true));
+        assert_eq!(Value::from(3), Value::Int(3));
+        assert_eq!(to_string(&[Value::from(1.5)]), "d1.5");
+        assert_eq!(Value::from("a"), Value::String("a".into()));
+        assert_eq!(Value::from(String::from("a")), Value::String("a".into()));
+        assert_eq!(to_string(&[Value::from(vec![1, 2])]), "ai1i2h");
+        assert_eq!(
+            to_string(&[Value::from(vec![vec!["a"], vec![]])]),
+            "aay1:ahahh"
+        );
+
+        let map = HashMap::from([("b".to_owned(), 2), ("a".to_owned(), 1)]);
+        assert_eq!(to_string(&[Value::from(map)]), "by1:ai1y1:bi2h");
+        let map = BTreeMap::from([(2, "x"), (1, "y")]);
+        assert_eq!(to_string(&[Value::from(map)]), "q:1y1:y:2y1:xh");
+    }
+
+    #[test]
+    fn try_from() {
+        let save = from_str(SAVE).unwrap().remove(0);
+        let player = &save["player"];
+        assert_eq!(i32::try_from(&player["gold"]), Ok(20));
+        assert_eq!(i64::try_from(&player["gold"]), Ok(20));
+        assert_eq!(f64::try_from(&player["speed"]), Ok(1.5));
+        assert_eq!(String::try_from(&player["name"]).as_deref(), Ok("Bob"));
+        assert_eq!(bool::try_from(&save["flags"]["seen"]), Ok(true));
+
+        let items: Vec<i32> = player["items"].clone().try_into().unwrap();
+        assert_eq!(items, [1, 2]);
+        let quests: BTreeMap<i32, i32> = save["quests"].clone().try_into().unwrap();
+        assert_eq!(quests, BTreeMap::from([(1, 3), (2, 0)]));
+        let flags: HashMap<String, bool> = save["flags"].clone().try_into().unwrap();
+        assert_eq!(flags, HashMap::from([("seen".to_owned(), true)]));
+
+        // owned strings survive the value they came from
+        let names: Vec<String> = from_str("ay1:ay1:bh")
+            .unwrap()
+            .remove(0)
+            .try_into()
+            .unwrap();
+        assert_eq!(names, ["a", "b"]);
+    }
+
+    #[test]
+    fn type_errors() {
+        let save = from_str(SAVE).unwrap().remove(0);
+        let err = i32::try_from(&save["player"]["name"]).unwrap_err();
+        assert_eq!(
+            err,
+            TypeError {
+                expected: "int",
+                found: "string",
+                path: Path::default(),
+            }
+        );
+        assert_eq!(err.to_string(), "expected int, found string");
+        assert_eq!(
+            bool::try_from(&save["missing"]).unwrap_err().to_string(),
+            "expected bool, found null"
+        );
+        assert_eq!(
+            Vec::<i32>::try_from(save["quests"].clone())
+                .unwrap_err()
+                .to_string(),
+            "expected array, found int map"
+        );
+
+        // where in a container the mismatch is
+        let value = from_str("aai1hai2y1:xhh").unwrap().remove(0);
+        let err = Vec::<Vec<i32>>::try_from(value).unwrap_err();
+        assert_eq!(err.path, "[1][1]".parse().unwrap());
+        assert_eq!(err.to_string(), "expected int at [1][1], found string");
+
+        let err = HashMap::<String, i32>::try_from(save["player"].clone()).unwrap_err();
+        assert_eq!(err.to_string(), "expected int at name, found string");
+        let err = BTreeMap::<i32, String>::try_from(save["quests"].clone()).unwrap_err();
+        assert_eq!(err.to_string(), "expected string at [1], found int");
+    }
+}
+
+mod macros {
+    use std::borrow::Cow;
+
+    use super::*;
+    use value::float::Float;
+
+    #[test]
+    fn scalars() {
+        assert_eq!(haxe!(null), Value::Null);
+        assert_eq!(haxe!(true), Value::Bool(true));
+        assert_eq!(haxe!(-3), Value::Int(-3));
+        assert_eq!(haxe!(2.5), Value::Float(Float::new(2.5)));
+        assert_eq!(haxe!("a"), Value::String("a".into()));
+
+        // any expression that converts into a value
+        let gold = 20;
+        assert_eq!(haxe!(gold * 2), Value::Int(40));
+        assert_eq!(haxe!(vec![1, 2]), haxe!([1, 2]));
+        assert_eq!(haxe!(Value::Bytes(vec![1])), Value::Bytes(vec![1]));
+    }
+
+    #[test]
+    fn arrays() {
+        assert_eq!(haxe!([]), Value::Array(vec![]));
+        assert_eq!(
+            haxe!([1, null, -2, "a",]),
+            Value::Array(vec![
+                Value::Int(1),
+                Value::Null,
+                Value::Int(-2),
+                Value::String("a".into()),
+            ])
+        );
+        assert_eq!(
+            to_string(&[haxe!([[1], [], { a: [2] }])]),
+            "aai1hahoy1:aai2hgh"
+        );
+    }
+
+    #[test]
+    fn structs() {
+        let value = haxe!({ path: "a", type: "IMAGE", "with space": { x: 1 + 1 } });
+        assert_eq!(
+            value,
+            Value::Struct {
+                fields: [
+                    (Cow::from("path"), Value::String("a".into())),
+                    ("type".into(), Value::String("IMAGE".into())),
+                    (
+                        "with space".into(),
+                        Value::Struct {
+                            fields: [("x".into(), Value::Int(2))].into_iter().collect(),
+                        },
+                    ),
+                ]
+                .into_iter()
+                .collect(),
+            }
+        );
+        // fields keep their order
+        assert_eq!(to_string(&[haxe!({ b: 1, a: 2 })]), "oy1:bi1y1:ai2g");
+    }
+
+    #[test]
+    fn classes() {
+        assert_eq!(
+            haxe!(class game.Point { x: 1, y: null }),
+            Value::Class {
+                name: "game.Point".into(),
+                fields: [("x".into(), Value::Int(1)), ("y".into(), Value::Null)]
+                    .into_iter()
+                    .collect(),
+            }
+        );
+        assert_eq!(to_string(&[haxe!(class "" {})]), "cy0:g");
+    }
+
+    #[test]
+    fn enums() {
+        assert_eq!(
+            haxe!(enum Color.Rgb(1, 2, 3)),
+            Value::Enum {
+                name: "Color".into(),
+                constructor: "Rgb".into(),
+                fields: vec![Value::Int(1), Value::Int(2), Value::Int(3)],
+            }
+        );
+        assert_eq!(
+            to_string(&[haxe!(enum game.Color.Red)]),
+            "wy10:game.Colory3:Red:0"
+        );
+        assert_eq!(
+            to_string(&[haxe!(enum game.Color.Red())]),
+            "wy10:game.Colory3:Red:0"
+        );
+        assert_eq!(
+            to_string(&[haxe!(enum "a.b"."c"([enum E.A, class C {}]))]),
+            "wy3:a.by1:c:1awy1:Ey1:A:0cy1:Cgh"
+        );
+    }
+}
+
+mod text {
+    use std::borrow::Cow;
+
+    use super::*;
+    use value::float::Float;
+
+    // every value survives being written as text and read back
+    fn roundtrip(values: &[Value]) {
+        let text = to_text(values);
+        assert_eq!(from_text(&text).as_deref(), Ok(values), "{text}");
+    }
+
+    #[test]
+    fn every_variant() {
+        let hxon = [
+            "n",
+            "t",
+            "f",
+            "z",
+            "i-12",
+            "i2147483647",
+            "d1.5",
+            "d-0",
+            "d1e300",
+            "k",
+            "p",
+            "m",
+            "y5:hello",
+            "y12:a%22b%5Cc%0A",
+            "v2024-01-01 10:00:00",
+            "v1700000000000.5",
+            "s4:AQID",
+            "ai1nh",
+            "li1nh",
+            "ah",
+            "lh",
+            "by1:ai1h",
+            "bh",
+            "q:1n:-2i3h",
+            "qh",
+            "Moy1:xi1gy1:ai2i3h",
+            "Mh",
+            "og",
+            "oy1:ai1y3:b.cng",
+            "cy9:game.Itemy2:idi3g",
+            "cy0:g",
+            "cy3:a bg",
+            "wy5:Colory3:Rgb:3i1i2i3",
+            "wy5:Colory3:Red:0",
+            "wy0:y0::0",
+            "wy3:a.by3:c d:0",
+            "jy4:Mode:2:0",
+            "jy4:Mode:1:1n",
+            "xy6:failed",
+            "Cy5:Pointi1i2g",
+        ];
+        for hxon in hxon {
+            roundtrip(&from_str(hxon).unwrap());
+        }
+        roundtrip(&[
+            Value::CustomFields {
+                name: "Point".into(),
+                fields: [("x".into(), Value::Int(1))].into_iter().collect(),
+            },
+            Value::Ref(3),
+            Value::String("\u{1}\t\r\"é😀".into()),
+            Value::Float(Float::new(0.1 + 0.2)),
+            Value::Float(Float::new(f64::MIN_POSITIVE)),
+            haxe!({ "": 1, "with space": 2, "1": 3, ok_name: 4 }),
+        ]);
+        // several top level values
+        roundtrip(&from_str("i1y1:aoy1:xzg").unwrap());
+        roundtrip(&[]);
+    }
+
+    #[test]
+    fn written() {
+        let values =
+            from_str("cy9:game.Itemy2:idi3y4:tagsly1:ahy4:costd2gwy1:Ey1:A:1pi-1").unwrap();
+        assert_eq!(
+            to_text(&values),
+            "class game.Item {\n    id: 3,\n    tags: list [\n        \"a\",\n    ],\n    cost: 2.0,\n}\nenum E.A(\n    inf,\n)\n-1\n"
+        );
+        assert_eq!(to_text(&[haxe!(class "a b" {})]), "class \"a b\" {}\n");
+        assert_eq!(to_text(&[haxe!(enum ""."1")]), "enum \"\".\"1\"\n");
+        assert_eq!(to_text(&from_str("jy4:Mode:2:0").unwrap()), "enum Mode.2\n");
+    }
+
+    #[test]
+    fn hand_written() {
+        let text = r#"
+            // a comment
+            { a: 1, "b c": [1, 2,], } // trailing commas
+            enum game.Color.Rgb(1, 2, 3)
+            enum Mode.2
+            enum "a b".C
+            enum E."a b"()
+            intmap { -1: null }
+            objectmap { { x: 1 }: "a", 2: "b" }
+            date "2024-01-01 10:00:00"
+            "\u{48}i\n"
+            1E3
+        "#;
+        assert_eq!(
+            from_text(text).unwrap(),
+            vec![
+                haxe!({ a: 1, "b c": [1, 2] }),
+                haxe!(enum game.Color.Rgb(1, 2, 3)),
+                Value::EnumIndex {
+                    name: "Mode".into(),
+                    index: 2,
+                    fields: vec![],
+                },
+                haxe!(enum "a b"."C"),
+                haxe!(enum "E"."a b"),
+                Value::IntMap([(-1, Value::Null)].into_iter().collect()),
+                Value::ObjectMap(
+                    [(haxe!({ x: 1 }), haxe!("a")), (Value::Int(2), haxe!("b")),]
+                        .into_iter()
+                        .collect()
+                ),
+                Value::Date("2024-01-01 10:00:00".parse().unwrap()),
+                haxe!("Hi\n"),
+                haxe!(1000.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn quoted_enum_names() {
+        assert_eq!(
+            from_text(r#"enum "game.Color".Red"#).unwrap(),
+            vec![haxe!(enum "game.Color"."Red")]
+        );
+        assert_eq!(
+            from_text(r#"enum "a\u{62}".C(1)"#).unwrap(),
+            vec![haxe!(enum "ab"."C"(1))]
+        );
+        assert!(from_text(r#"enum "a\u{62}"(1)"#).is_err());
+        assert!(from_text(r#"enum "game.Color""#).is_err());
+    }
+
+    #[test]
+    fn borrowed_strings() {
+        let values = from_text(r#"["plain", "esc\"aped"]"#).unwrap();
+        let items = values[0].as_array().unwrap();
+        assert!(matches!(&items[0], Value::String(Cow::Borrowed("plain"))));
+        assert!(matches!(&items[1], Value::String(Cow::Owned(s)) if s == "esc\"aped"));
+    }
+
+    #[test]
+    fn errors() {
+        let error = |text: &str| {
+            let err = from_text(text).unwrap_err();
+            (err.line, err.column)
+        };
+        assert_eq!(error("{ a: 1,\n  b 2 }"), (2, 5));
+        assert_eq!(error("[1, 2"), (1, 6));
+        assert_eq!(error("nope"), (1, 1));
+        assert_eq!(error("  3000000000"), (1, 3));
+        assert_eq!(error("\"\\q\""), (1, 3));
+        assert_eq!(error("date \"yesterday\""), (1, 6));
+        assert_eq!(error("bytes \"!!\""), (1, 7));
+        assert_eq!(error("enum Color"), (1, 11));
+        assert_eq!(error("// only a comment\n}"), (2, 1));
+        assert_eq!(
+            from_text("[\n\n  ?]").unwrap_err().to_string(),
+            "invalid text at line 3, column 3"
+        );
+        assert_eq!(from_text("[\n\n  ?]").unwrap_err().offset, 5);
+    }
+}
+
+#[cfg(feature = "export-json")]
+mod plain_json {
+    use serde_json::json;
+
+    use super::*;
+    use value::float::Float;
+
+    fn roundtrip(value: &Value) {
+        let json = to_plain_json(value);
+        // through a string, so numbers are read back the way they're written
+        let json = serde_json::from_str(&json.to_string()).unwrap();
+        assert_eq!(from_plain_json(&json).as_ref(), Ok(value), "{json}");
+    }
+
+    #[test]
+    fn written() {
+        let value =
+            from_str("oy4:namey3:Boby4:goldi20y5:speedd2y5:itemsai1nhy3:petcy8:game.Pety3:agei3gg")
+                .unwrap()
+                .remove(0);
+        assert_eq!(
+            to_plain_json(&value),
+            json!({
+                "name": "Bob",
+                "gold": 20,
+                "speed": 2.0,
+                "items": [1, null],
+                "pet": { "$class": "game.Pet", "age": 3 },
+            })
+        );
+        // fields keep their order
+        assert_eq!(
+            serde_json::to_string(&to_plain_json(&haxe!({ b: 1, a: 2 }))).unwrap(),
+            r#"{"b":1,"a":2}"#
+        );
+
+        let plain = |hxon: &str| to_plain_json(&from_str(hxon).unwrap()[0]);
+        assert_eq!(plain("k"), json!({ "$float": "nan" }));
+        assert_eq!(plain("m"), json!({ "$float": "-inf" }));
+        assert_eq!(
+            plain("v2024-01-01 10:00:00"),
+            json!({ "$date": "2024-01-01 10:00:00" })
+        );
+        assert_eq!(plain("v1000"), json!({ "$date": 1000.0 }));
+        assert_eq!(plain("s4:AQID"), json!({ "$bytes": "AQID" }));
+        assert_eq!(plain("li1h"), json!({ "$list": [1] }));
+        assert_eq!(plain("by1:ai1h"), json!({ "$stringmap": { "a": 1 } }));
+        assert_eq!(plain("q:-1nh"), json!({ "$intmap": { "-1": null } }));
+        assert_eq!(plain("Mi1i2h"), json!({ "$objectmap": [[1, 2]] }));
+        assert_eq!(
+            plain("wy5:Colory3:Rgb:2i1i2"),
+            json!({ "$enum": "Color.Rgb", "$fields": [1, 2] })
+        );
+        assert_eq!(plain("jy4:Mode:2:0"), json!({ "$enum": "Mode.2" }));
+        assert_eq!(plain("xn"), json!({ "$exception": null }));
+        assert_eq!(plain("Cy1:Pi1g"), json!({ "$custom": "P", "$raw": [1] }));
+        assert_eq!(
+            to_plain_json(&Value::CustomFields {
+                name: "P".into(),
+                fields: [("x".into(), Value::Int(1))].into_iter().collect(),
+            }),
+            json!({ "$custom": "P", "x": 1 })
+        );
+        assert_eq!(to_plain_json(&Value::Ref(2)), json!({ "$ref": 2 }));
+        // fields that look like hints are kept apart
+        assert_eq!(
+            plain("cy1:Ay7:%24datei1g"),
+            json!({ "$class": "A", "$fields": { "$date": 1 } })
+        );
+    }
+
+    #[test]
+    fn every_variant() {
+        let hxon = [
+            "n",
+            "t",
+            "z",
+            "i-12",
+            "d1.5",
+            "d2",
+            "d-0",
+            "d1e300",
+            "k",
+            "p",
+            "m",
+            "y5:hello",
+            "v2024-01-01 10:00:00",
+            "v1700000000000.5",
+            "s4:AQID",
+            "ai1nh",
+            "li1nh",
+            "ah",
+            "by1:ai1h",
+            "q:1n:-2i3h",
+            "Moy1:xi1gy1:ai2i3h",
+            "og",
+            "oy7:%24listng",
+            "cy9:game.Itemy2:idi3g",
+            "cy0:g",
+            "cy1:Ay9:%24fieldsng",
+            "wy5:Colory3:Rgb:3i1i2i3",
+            "wy5:Colory3:Red:0",
+            "wy0:y0::0",
+            "wy3:a.by1:c:0",
+            "jy4:Mode:2:1n",
+            "xy6:failed",
+            "Cy5:Pointi1i2g",
+        ];
+        for hxon in hxon {
+            roundtrip(&from_str(hxon).unwrap()[0]);
+        }
+        roundtrip(&Value::CustomFields {
+            name: "Point".into(),
+            fields: [("$x".into(), Value::Int(1))].into_iter().collect(),
+        });
+        roundtrip(&Value::Ref(3));
+    }
+
+    #[test]
+    fn numbers() {
+        let value = |json| from_plain_json(&json).unwrap();
+        assert_eq!(value(json!(3)), Value::Int(3));
+        assert_eq!(value(json!(3.0)), Value::Float(Float::new(3.0)));
+        // too big for a haxe int
+        assert_eq!(value(json!(3000000000_i64)), Value::Float(Float::new(3e9)));
+    }
+
+    #[test]
+    fn errors() {
+        let error = |json| from_plain_json(&json).unwrap_err().to_string();
+        assert_eq!(error(json!({ "$what": 1 })), "unknown type hint \"$what\"");
+        assert_eq!(error(json!({ "$list": 1 })), "invalid $list");
+        assert_eq!(error(json!({ "$list": [], "a": 1 })), "invalid $list");
+        assert_eq!(error(json!({ "$enum": "NoDot" })), "invalid $enum");
+        assert_eq!(error(json!({ "$float": "big" })), "invalid $float");
+        assert_eq!(error(json!({ "$date": "soon" })), "invalid $date");
+        assert_eq!(
+            error(json!({ "$enum": "E.A", "$fields": [], "x": 1 })),
+            "invalid $enum"
+        );
+        assert_eq!(
+            error(json!({ "$class": "A", "$fields": {}, "x": 1 })),
+            "invalid $class"
+        );
+        // where in the value the error is
+        assert_eq!(
+            error(json!({ "a": [1, { "$bytes": "!" }] })),
+            "invalid $bytes at a[1]"
+        );
+        assert_eq!(
+            error(json!({ "$intmap": { "x": 1 } })),
+            "invalid key at $intmap.x"
+        );
+        assert_eq!(
+            error(json!({ "$objectmap": [[1, 2, 3]] })),
+            "invalid entry at $objectmap[0]"
+        );
+    }
+}
+
+mod typed {
+    use std::collections::{BTreeMap, HashMap};
+
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::haxe::typed::Error;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Point {
+        x: i32,
+        y: f64,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    enum Shape {
+        Empty,
+        Circle(f64),
+        Rect(i32, i32),
+        Polygon { points: Vec<Point> },
+    }
+
+    #[test]
+    fn primitives() {
+        let (a, b, c, d, e): (Option<i32>, bool, i32, f64, String) =
+            from_str_typed("ntzd1.5y5:hello").unwrap();
+        assert_eq!((a, b, c, d, e.as_str()), (None, true, 0, 1.5, "hello"));
+
+        let values: Vec<f64> = from_str_typed("kmpi-3").unwrap();
+        assert!(values[0].is_nan());
+        assert_eq!(values[1..], [f64::NEG_INFINITY, f64::INFINITY, -3.0]);
+    }
+
+    #[test]
+    fn borrowed_strings() {
+        let (a, b): (&str, String) = from_str_typed("y3:abcy5:a%20b").unwrap();
+        assert_eq!((a, b.as_str()), ("abc", "a b"));
+
+        assert!(from_str_typed::<(&str,)>("y5:a%20b").is_err());
+    }
+
+    #[test]
+    fn structs_and_classes() {
+        let points: Vec<Point> = from_str_typed("oy1:xi1y1:yd2.5gcy5:Pointy1:xzR1d-1g").unwrap();
+        assert_eq!(points, [Point { x: 1, y: 2.5 }, Point { x: 0, y: -1.0 }]);
+    }
+
+    #[test]
+    fn top_level_struct() {
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct Save {
+            version: i32,
+            names: Vec<String>,
+        }
+
+        let save: Save = from_str_typed("i2ly1:ay1:bh").unwrap();
+        assert_eq!(
+            save,
+            Save {
+                version: 2,
+                names: vec!["a".to_owned(), "b".to_owned()]
+            }
+        );
+    }
+
+    #[test]
+    fn arrays() {
+        let (array, list): (Vec<Option<i32>>, Vec<i32>) = from_str_typed("azu3i1nhli1i2h").unwrap();
+        assert_eq!(array, [Some(0), None, None, None, Some(1), None]);
+        assert_eq!(list, [1, 2]);
+    }
+
+    #[test]
+    fn maps() {
+        let (strings, ints): (HashMap<String, i32>, HashMap<i32, bool>) =
+            from_str_typed("by1:ai1y1:bi2hq:1t:-2fh").unwrap();
+        assert_eq!(strings, [("a".to_owned(), 1), ("b".to_owned(), 2)].into());
+        assert_eq!(ints, [(1, true), (-2, false)].into());
+
+        #[derive(Deserialize, Debug, PartialEq, Eq, Hash)]
+        struct Key {
+            id: i32,
+        }
+
+        let (objects,): (HashMap<Key, i32>,) = from_str_typed("Moy2:idzgi3h").unwrap();
+        assert_eq!(objects, [(Key { id: 0 }, 3)].into());
+    }
+
+    #[test]
+    fn enums() {
+        let shapes: Vec<Shape> = from_str_typed(
+            "wy5:Shapey5:Empty:0\
+             wR0y6:Circle:1d1.5\
+             wR0y4:Rect:2i1i2\
+             wR0y7:Polygon:1aoy1:xzy1:yzghy5:Empty",
+        )
+        .unwrap();
+        assert_eq!(
+            shapes,
+            [
+                Shape::Empty,
+                Shape::Circle(1.5),
+                Shape::Rect(1, 2),
+                Shape::Polygon {
+                    points: vec![Point { x: 0, y: 0.0 }]
+                },
+                Shape::Empty,
+            ]
+        );
+
+        let shapes: Vec<Shape> = from_str_typed("jy5:Shape:0:0jR0:2:2i1i2").unwrap();
+        assert_eq!(shapes, [Shape::Empty, Shape::Rect(1, 2)]);
+
+        assert!(from_str_typed::<Vec<Shape>>("wy5:Shapey6:Circle:0").is_err());
+    }
+
+    #[test]
+    fn custom_classes() {
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct Inventory {
+            items: Vec<Option<i32>>,
+            owner: String,
+            gold: Option<i32>,
+        }
+
+        let (inventory, same): (Inventory, Inventory) =
+            from_str_typed("Cy9:Inventoryay5:itemsy5:ownery4:goldhaai1u2hR1nhgr0").unwrap();
+        assert_eq!(
+            inventory,
+            Inventory {
+                items: vec![Some(1), None, None],
+                owner: "items".to_owned(),
+                gold: None,
+            }
+        );
+        assert_eq!(inventory, same);
+
+        // values without names are read as a sequence
+        let (raw,): ((i32, String),) = from_str_typed("Cy5:Pointi1y1:xg").unwrap();
+        assert_eq!(raw, (1, "x".to_owned()));
+    }
+
+    #[test]
+    fn exception() {
+        let (point,): (Point,) = from_str_typed("xoy1:xi1y1:yzg").unwrap();
+        assert_eq!(point, Point { x: 1, y: 0.0 });
+    }
+
+    #[test]
+    fn references() {
+        // enums are cached after their fields, so the circle is r1
+        let (array, circle, array_ref, circle_ref, nested): (
+            Vec<i32>,
+            Shape,
+            Vec<i32>,
+            Shape,
+            Vec<Vec<i32>>,
+        ) = from_str_typed("ai1hwy5:Shapey6:Circle:1d2r0r1ar0r0h").unwrap();
+        assert_eq!(array, array_ref);
+        assert_eq!(circle, circle_ref);
+        assert_eq!(circle, Shape::Circle(2.0));
+        assert_eq!(nested, [[1], [1]]);
+
+        // strings read again for a reference don't add to the string cache
+        let (a, b, c): (Point, Point, String) = from_str_typed("oy1:xzy1:yzgr0y1:z").unwrap();
+        assert_eq!(a, b);
+        assert_eq!(c, "z");
+        let (_, _, c): (Point, Point, String) = from_str_typed("oy1:xzy1:yzgr0R1").unwrap();
+        assert_eq!(c, "y");
+    }
+
+    #[test]
+    fn bytes_and_dates() {
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct Data<'a> {
+            #[serde(with = "serde_bytes_buf")]
+            bytes: Vec<u8>,
+            date: &'a str,
+        }
+
+        mod serde_bytes_buf {
+            pub fn deserialize<'de, D: serde::Deserializer<'de>>(
+                deserializer: D,
+            ) -> Result<Vec<u8>, D::Error> {
+                struct Bytes;
+                impl serde::de::Visitor<'_> for Bytes {
+                    type Value = Vec<u8>;
+
+                    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                        f.write_str("bytes")
+                    }
+
+                    fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<Vec<u8>, E> {
+                        Ok(v)
+                    }
+                }
+                deserializer.deserialize_byte_buf(Bytes)
+            }
+        }
+
+        let (data,): (Data,) =
+            from_str_typed("oy5:bytess4:AQIDy4:datev2024-01-02 03:04:05g").unwrap();
+        assert_eq!(data.bytes, [1, 2, 3]);
+        assert_eq!(data.date, "2024-01-02 03:04:05");
+    }
+
+    #[test]
+    fn errors() {
+        let err = from_str_typed::<(i32, i32)>("i1y1:a").unwrap_err();
+        assert!(err.to_string().ends_with("at byte 2"), "{err}");
+
+        assert!(matches!(
+            from_str_typed::<(i32,)>("i1i2").unwrap_err(),
+            Error::Message {
+                offset: Some(2),
+                ..
+            }
+        ));
+        assert!(matches!(
+            from_str_typed::<(Vec<i32>,)>("r0").unwrap_err(),
+            Error::Decode(DecodeError::BadObjectCacheRef { index: 0, .. })
+        ));
+        assert!(matches!(
+            from_str_typed::<(Vec<i32>,)>("ai1r0h").unwrap_err(),
+            Error::Message {
+                offset: Some(3),
+                ..
+            }
+        ));
+        assert!(matches!(
+            from_str_typed::<(Point,)>("oy1:xi1g").unwrap_err(),
+            Error::Message {
+                offset: Some(0),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn serialize_primitives() {
+        let data = to_string_typed(&(None::<i32>, true, 0, 1.5, "a b", 'c', f64::NAN)).unwrap();
+        assert_eq!(data, "ntzd1.5y5:a%20by1:ck");
+
+        assert!(to_string_typed(&(u32::MAX,)).is_err());
+        assert!(to_string_typed(&1).is_err());
+    }
+
+    #[test]
+    fn serialize_structs() {
+        #[derive(Serialize)]
+        #[serde(rename = "class:Player")]
+        struct Player {
+            name: &'static str,
+            position: Point,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            title: Option<&'static str>,
+        }
+
+        #[derive(Serialize)]
+        #[serde(rename = "custom:Inventory")]
+        struct Inventory {
+            items: Vec<Option<i32>>,
+            owner: &'static str,
+            gold: Option<i32>,
+            gems: Option<i32>,
+        }
+
+        let values = (
+            Player {
+                name: "x",
+                position: Point { x: 1, y: 0.5 },
+                title: None,
+            },
+            Inventory {
+                items: vec![Some(1), None, None],
+                owner: "name",
+                gold: None,
+                gems: None,
+            },
+        );
+        let data = to_string_typed(&values).unwrap();
+        assert_eq!(
+            data,
+            "cy6:Playery4:namey1:xy8:positionoR2i1y1:yd0.5gg\
+             Cy9:Inventoryay5:itemsy5:ownery4:goldy4:gemshaai1u2hR1u2hg"
+        );
+
+        let value = &from_str(&data).unwrap()[1];
+        let Value::CustomFields { name, fields } = value else {
+            panic!("{value:?}");
+        };
+        assert_eq!(name, "Inventory");
+        assert_eq!(fields.get("gems"), Some(&Value::Null));
+    }
+
+    #[test]
+    fn serialize_arrays_and_lists() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Lists {
+            array: Vec<Option<i32>>,
+            #[serde(with = "crate::haxe::typed::list")]
+            list: Vec<Option<i32>>,
+        }
+
+        let lists = Lists {
+            array: vec![None, None, Some(1), None],
+            list: vec![None, None],
+        };
+        let data = to_string_typed(&(&lists,)).unwrap();
+        assert_eq!(data, "oy5:arrayau2i1nhy4:listlnnhg");
+
+        let (decoded,): (Lists,) = from_str_typed(&data).unwrap();
+        assert_eq!(decoded, lists);
+
+        // only the sequence right inside is written as a list
+        #[derive(Serialize)]
+        struct NotASequence {
+            #[serde(with = "crate::haxe::typed::list")]
+            inner: Lists,
+        }
+
+        let data = to_string_typed(&(NotASequence { inner: lists },)).unwrap();
+        assert_eq!(data, "oy5:inneroy5:arrayau2i1nhy4:listlnnhgg");
+    }
+
+    #[test]
+    fn serialize_maps() {
+        let strings: BTreeMap<&str, i32> = [("a", 1), ("b", 2)].into();
+        let ints: BTreeMap<i32, bool> = [(-2, false), (1, true)].into();
+        let objects: BTreeMap<(i32, i32), i32> = [((1, 2), 3)].into();
+        let empty: BTreeMap<i32, i32> = BTreeMap::new();
+
+        let data = to_string_typed(&(strings, ints, objects, empty)).unwrap();
+        assert_eq!(data, "by1:ai1y1:bi2hq:-2f:1thMai1i2hi3hbh");
+    }
+
+    #[test]
+    fn serialize_enums() {
+        let shapes = vec![
+            Shape::Empty,
+            Shape::Circle(1.5),
+            Shape::Rect(1, 2),
+            Shape::Polygon {
+                points: vec![Point { x: 0, y: 0.0 }],
+            },
+        ];
+
+        let data = to_string_typed(&shapes).unwrap();
+        assert_eq!(
+            data,
+            "wy5:Shapey5:Empty:0\
+             wR0y6:Circle:1d1.5\
+             wR0y4:Rect:2i1i2\
+             wR0y7:Polygon:1aoy1:xzy1:yd0gh"
+        );
+        assert_eq!(from_str_typed::<Vec<Shape>>(&data).unwrap(), shapes);
+
+        let data = Serializer::new()
+            .use_enum_index(true)
+            .encode_typed(&shapes[..3])
+            .unwrap();
+        assert_eq!(data, "jy5:Shape:0:0jR0:1:1d1.5jR0:2:2i1i2");
+        assert_eq!(from_str_typed::<Vec<Shape>>(&data).unwrap(), shapes[..3]);
+    }
+
+    #[test]
+    fn serialize_matches_value() {
+        let values = (
+            Point { x: 1, y: 2.0 },
+            vec![Some("a"), None, Some("a")],
+            Shape::Rect(3, 4),
+        );
+        let typed = to_string_typed(&values).unwrap();
+        let decoded = from_str(&typed).unwrap();
+        assert_eq!(to_string(&decoded), typed);
+    }
+}
+
+mod stream {
+    use std::io::BufReader;
+
+    use super::*;
+    use crate::haxe::stream::Error;
+
+    fn decode(data: &str) -> Result<Vec<Value<'static>>, Error> {
+        // a tiny buffer makes every value cross a buffer boundary
+        let reader = BufReader::with_capacity(1, data.as_bytes());
+        StreamDecoder::new(reader).collect()
+    }
+
+    #[test]
+    fn matches_from_str() {
+        let inputs = [
+            "ntfzi-12d1.5kmpy5:a%20bR0",
+            "ai1u3nlzhh",
+            "by1:ai1hq:1t:-2fhMoy1:xzgy1:yh",
+            "cy5:Pointy1:xzy1:yd0.5g",
+            "wy5:Shapey6:Circle:1d2jR0:2:2i1i2",
+            "xy5:error",
+            "s4:AQIDv2024-01-02 03:04:05",
+            "av1704164645000v2024-01-02 03:04:05h",
+            "Cy4:Itemay4:namey5:counthay5:swordnhg",
+            "ai1har0hwy1:Ey1:A:0r2oy1:xr0gr3",
+            "aoy1:ar0y1:br1gr1h",
+            "aay1:ar1hr1wy1:Ey1:A:1r1r2h",
+            "ar0h",
+            "Cy1:aazhahgCy1:by1:xi1gr2",
+        ];
+
+        for input in inputs {
+            let expected = from_str(input).unwrap();
+            assert_eq!(decode(input).unwrap(), expected, "{input}");
+        }
+    }
+
+    #[test]
+    fn keep_references() {
+        let input = "ai1har0hr0";
+        let reader = input.as_bytes();
+        let values: Vec<Value> = StreamDecoder::new(reader)
+            .keep_references(true)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            values,
+            Unserializer::new()
+                .keep_references(true)
+                .decode(input)
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn references_to_events() {
+        // objects read as events aren't kept, so values can't copy them
+        let mut decoder = StreamDecoder::new("ai1hr0ar0h".as_bytes());
+        while decoder.next_event().unwrap().unwrap() != Event::End {}
+        match decoder.next_value() {
+            Some(Err(Error::Decode(DecodeError::BadObjectCacheRef { index: 0, .. }))) => {}
+            result => panic!("{result:?}"),
+        }
+
+        // but a reference to an object still being read is a cycle, which is
+        // cut with a null like in values
+        let mut decoder = StreamDecoder::new("ar0h".as_bytes());
+        assert_eq!(decoder.next_event().unwrap().unwrap(), Event::StartArray);
+        assert_eq!(decoder.next_value().unwrap().unwrap(), Value::Null);
+
+        // and events never follow references
+        let mut decoder = StreamDecoder::new("ai1hr0".as_bytes());
+        let events: Vec<Event> = decoder.events().collect::<Result<_, _>>().unwrap();
+        assert_eq!(
+            events,
+            [Event::StartArray, Event::Int(1), Event::End, Event::Ref(0)]
+        );
+    }
+
+    #[test]
+    fn events() {
+        let mut decoder = StreamDecoder::new("oy1:aau2i1hgwy1:Ey1:B:1zq:3nh".as_bytes());
+        let events: Vec<Event> = decoder.events().collect::<Result<_, _>>().unwrap();
+        assert_eq!(
+            events,
+            [
+                Event::StartStruct,
+                Event::Field("a".to_owned()),
+                Event::StartArray,
+                Event::Null,
+                Event::Null,
+                Event::Int(1),
+                Event::End,
+                Event::End,
+                Event::StartEnum {
+                    name: "E".to_owned(),
+                    constructor: "B".to_owned(),
+                    len: 1
+                },
+                Event::Int(0),
+                Event::End,
+                Event::StartIntMap,
+                Event::IntKey(3),
+                Event::Null,
+                Event::End,
+            ]
+        );
+    }
+
+    #[test]
+    fn events_then_values() {
+        let mut decoder = StreamDecoder::new("ai1ai2hhi3".as_bytes());
+        assert_eq!(decoder.next_event().unwrap().unwrap(), Event::StartArray);
+        assert_eq!(decoder.next_value().unwrap().unwrap(), Value::Int(1));
+        assert_eq!(
+            decoder.next_value().unwrap().unwrap(),
+            Value::Array(vec![Value::Int(2)])
+        );
+        assert_eq!(decoder.next_event().unwrap().unwrap(), Event::End);
+        assert_eq!(decoder.next_value().unwrap().unwrap(), Value::Int(3));
+        assert!(decoder.next_value().is_none());
+    }
+
+    #[test]
+    fn errors() {
+        let errors = [
+            (
+                "ai1",
+                DecodeError::Syntax {
+                    offset: 3,
+                    snippet: String::new(),
+                },
+            ),
+            (
+                "i1?",
+                DecodeError::UnknownTag {
+                    tag: '?',
+                    offset: 2,
+                    snippet: String::new(),
+                },
+            ),
+            (
+                "R0",
+                DecodeError::BadStringCacheRef {
+                    index: 0,
+                    offset: 0,
+                    snippet: String::new(),
+                },
+            ),
+            (
+                "zr0",
+                DecodeError::BadObjectCacheRef {
+                    index: 0,
+                    offset: 1,
+                    snippet: String::new(),
+                },
+            ),
+            (
+                "s2:!!",
+                DecodeError::InvalidBase64 {
+                    offset: 3,
+                    snippet: String::new(),
+                },
+            ),
+            (
+                "y3:%FF",
+                DecodeError::InvalidUtf8 {
+                    offset: 3,
+                    snippet: String::new(),
+                },
+            ),
+        ];
+
+        for (input, expected) in errors {
+            match decode(input) {
+                Err(Error::Decode(err)) => {
+                    assert_eq!(err, expected.with_snippet(input), "{input}")
+                }
+                result => panic!("{input}: {result:?}"),
+            }
+        }
+
+        // lengths are only trusted as far as the input goes
+        for input in [
+            "y18446744073709551615:",
+            "y99999999999:a",
+            "s99999999999:AAAA",
+        ] {
+            match decode(input) {
+                Err(Error::Decode(err)) => {
+                    assert_eq!(err, from_str(input).unwrap_err(), "{input}")
+                }
+                result => panic!("{input}: {result:?}"),
+            }
+        }
+
+        // the snippet is what was read last and what comes after it
+        let input = format!("a{}?{}h", "z".repeat(100), "z".repeat(100));
+        match StreamDecoder::new(input.as_bytes()).next_value().unwrap() {
+            Err(Error::Decode(err)) => assert_eq!(err, from_str(&input).unwrap_err()),
+            result => panic!("{result:?}"),
+        }
+
+        // nothing more is decoded after an error
+        let mut decoder = StreamDecoder::new("?zz".as_bytes());
+        assert!(decoder.next().unwrap().is_err());
+        assert!(decoder.next().is_none());
+    }
+}
+
+mod visitor {
+    use std::borrow::Cow;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct Recorder(Vec<String>);
+
+    impl Visitor<'_> for Recorder {
+        fn visit_null(&mut self) {
+            self.0.push("null".to_owned());
+        }
+
+        fn visit_int(&mut self, value: i32) {
+            self.0.push(format!("int {value}"));
+        }
+
+        fn visit_string(&mut self, value: Cow<'_, str>) {
+            self.0.push(format!("string {value}"));
+        }
+
+        fn string_ref(&mut self, index: usize, value: Cow<'_, str>) {
+            self.0.push(format!("string_ref {index} {value}"));
+        }
+
+        fn object_ref(&mut self, index: usize) {
+            self.0.push(format!("object_ref {index}"));
+        }
+
+        fn begin_array(&mut self) {
+            self.0.push("begin_array".to_owned());
+        }
+
+        fn null_run(&mut self, count: usize) {
+            self.0.push(format!("null_run {count}"));
+        }
+
+        fn end_array(&mut self) {
+            self.0.push("end_array".to_owned());
+        }
+
+        fn begin_struct(&mut self) {
+            self.0.push("begin_struct".to_owned());
+        }
+
+        fn field_name(&mut self, name: Cow<'_, str>) {
+            self.0.push(format!("field_name {name}"));
+        }
+
+        fn end_struct(&mut self) {
+            self.0.push("end_struct".to_owned());
+        }
+
+        fn begin_enum(&mut self, name: Cow<'_, str>, constructor: Cow<'_, str>, len: usize) {
+            self.0
+                .push(format!("begin_enum {name}.{constructor} {len}"));
+        }
+
+        fn end_enum(&mut self) {
+            self.0.push("end_enum".to_owned());
+        }
+
+        fn begin_custom(&mut self, name: Cow<'_, str>) {
+            self.0.push(format!("begin_custom {name}"));
+        }
+
+        fn end_custom(&mut self) {
+            self.0.push("end_custom".to_owned());
+        }
+    }
+
+    fn record(input: &str) -> Vec<String> {
+        let mut recorder = Recorder::default();
+        visit(input, &mut recorder).unwrap();
+        recorder.0
+    }
+
+    #[test]
+    fn references_are_not_resolved() {
+        assert_eq!(
+            record("oy1:ay1:bgr0R1wy1:Ey1:B:1nr1"),
+            [
+                "begin_struct",
+                "field_name a",
+                "string b",
+                "end_struct",
+                "object_ref 0",
+                "string_ref 1 b",
+                "begin_enum E.B 1",
+                "null",
+                "end_enum",
+                "object_ref 1",
+            ]
+        );
+    }
+
+    #[test]
+    fn arrays_and_customs() {
+        assert_eq!(
+            record("anu2i1hCy1:Cay1:xy1:yhau2hgr3"),
+            [
+                "begin_array",
+                "null",
+                "null_run 2",
+                "int 1",
+                "end_array",
+                "begin_custom C",
+                "begin_array",
+                "string x",
+                "string y",
+                "end_array",
+                "begin_array",
+                "null_run 2",
+                "end_array",
+                "end_custom",
+                "object_ref 3",
+            ]
+        );
+    }
+
+    #[test]
+    fn default_methods() {
+        struct Nothing;
+        impl Visitor<'_> for Nothing {}
+
+        let input = "ai1y1:ahlzhby1:bnhq:1nhMznhcy1:Cgjy1:E:0:0xzs4:AQIDv2024-01-02 03:04:05r0";
+        visit(input, &mut Nothing).unwrap();
+    }
+
+    #[test]
+    fn errors() {
+        let mut recorder = Recorder::default();
+        assert!(matches!(
+            visit("ai1hr1", &mut recorder),
+            Err(DecodeError::BadObjectCacheRef {
+                index: 1,
+                offset: 4,
+                ..
+            })
+        ));
+        assert!(matches!(
+            visit("Cy1:Cay1:xhz", &mut recorder),
+            Err(DecodeError::Syntax { offset: 12, .. })
+        ));
+    }
+}
@@ -0,0 +1,613 @@
+//! A text notation for values that can be edited by hand and read back
+//!
+//! It looks like the `Debug` output of a value, but every variant is written
+//! so it reads back as itself:
+//!
+//! ```text
+//! // line comments are allowed anywhere there's whitespace
+//! class game.Player {
+//!     name: "Bob",
+//!     gold: 20,
+//!     speed: 1.5,                  // floats always have a `.` or exponent
+//!     limits: [nan, inf, -inf],
+//!     items: list [1, 2],          // `[...]` alone is an array
+//!     seen: date "2024-01-01 10:00:00",
+//!     icon: bytes "aGVsbG8=",
+//!     pet: { name: "Rex", "eye color": enum Color.Rgb(0, 0, 255) },
+//!     quests: intmap { 1: true, -2: false },
+//!     flags: stringmap { seen: true, "not seen": false },
+//!     lookup: objectmap { { x: 1 }: "a" },
+//!     last: enum Mode.2,           // an enum written by index
+//!     error: exception "failed",
+//!     point: custom Point [1, 2],
+//!     named: custom Point { x: 1, y: 2 },
+//!     same_pet: ref 3,
+//! }
+//! ```
+//!
+//! Names that aren't identifiers are quoted: `class "" {}`,
+//! `enum "a b"."C"`. A file is any number of values one after another.
+
+use std::{borrow::Cow, fmt::Write};
+
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use vecmap::VecMap as Map;
+use winnow::{
+    ascii::{digit0, digit1, multispace1, till_line_ending},
+    combinator::{alt, opt, repeat},
+    error::{ErrMode, ErrorKind, FromExternalError, ParserError},
+    stream::{Location, Stateful, Stream},
+    token::{any, one_of, take_till, take_while},
+    Located, Parser,
+};
+
+use super::{
+    value::{date::HaxeDate, float::Float, Value},
+    MAX_DEPTH,
+};
+
+/// Where reading text failed
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+#[error(
+    "{} at line {line}, column {column}",
+    if *.too_deep { "value nested too deep" } else { "invalid text" }
+)]
+pub struct TextError {
+    /// Byte offset into the input
+    pub offset: usize,
+    /// Starting from 1
+    pub line: usize,
+    /// In characters, starting from 1
+    pub column: usize,
+    /// Whether the value there is nested over [`MAX_DEPTH`] deep, rather
+    /// than the text being invalid
+    pub too_deep: bool,
+}
+
+impl TextError {
+    // like `DecodeError::with_snippet`, the position is only known once
+    // parsing has stopped
+    fn with_position(mut self, input: &str) -> Self {
+        let before = &input[..self.offset.min(input.len())];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        self.line = before.matches('\n').count() + 1;
+        self.column = before[line_start..].chars().count() + 1;
+        self
+    }
+}
+
+// the state is how many values are being read inside of each other
+type Input<'a> = Stateful<Located<&'a str>, usize>;
+
+type PResult<T> = winnow::PResult<T, TextError>;
+
+impl ParserError<Input<'_>> for TextError {
+    fn from_error_kind(input: &Input<'_>, _kind: ErrorKind) -> Self {
+        Self {
+            offset: input.location(),
+            line: 0,
+            column: 0,
+            too_deep: false,
+        }
+    }
+
+    fn append(
+        self,
+        _input: &Input<'_>,
+        _token_start: &<Input<'_> as Stream>::Checkpoint,
+        _kind: ErrorKind,
+    ) -> Self {
+        self
+    }
+}
+
+impl<E> FromExternalError<Input<'_>, E> for TextError {
+    fn from_external_error(input: &Input<'_>, kind: ErrorKind, _e: E) -> Self {
+        Self::from_error_kind(input, kind)
+    }
+}
+
+/// Read values written by [`to_text`], borrowing strings without escapes
+/// from `input`
+pub fn from_text(input: &str) -> Result<Vec<Value<'_>>, TextError> {
+    let mut data = Input {
+        input: Located::new(input),
+        state: 0,
+    };
+    values(&mut data).map_err(|err| match err {
+        ErrMode::Backtrack(err) | ErrMode::Cut(err) => err.with_position(input),
+        ErrMode::Incomplete(_) => unreachable!("input is never partial"),
+    })
+}
+
+/// Write values as text, one after another
+pub fn to_text(values: &[Value]) -> String {
+    let mut output = String::new();
+    for value in values {
+        write_value(&mut output, value, 0);
+        output.push('\n');
+    }
+    output
+}
+
+fn values<'a>(data: &mut Input<'a>) -> PResult<Vec<Value<'a>>> {
+    let mut values = Vec::new();
+    loop {
+        ws(data)?;
+        if data.eof_offset() == 0 {
+            return Ok(values);
+        }
+        values.push(value(data)?);
+    }
+}
+
+// whitespace and comments
+fn ws(data: &mut Input<'_>) -> PResult<()> {
+    repeat(
+        0..,
+        alt((multispace1.void(), ("//", till_line_ending).void())),
+    )
+    .parse_next(data)
+}
+
+fn value<'a>(data: &mut Input<'a>) -> PResult<Value<'a>> {
+    ws(data)?;
+    if data.state >= MAX_DEPTH {
+        return Err(ErrMode::Cut(TextError {
+            too_deep: true,
+            ..TextError::from_error_kind(data, ErrorKind::Verify)
+        }));
+    }
+
+    data.state += 1;
+    let value = nested_value(data);
+    data.state -= 1;
+    value
+}
+
+fn nested_value<'a>(data: &mut Input<'a>) -> PResult<Value<'a>> {
+    let Some(next) = data.chars().next() else {
+        return Err(ErrMode::from_error_kind(data, ErrorKind::Eof));
+    };
+    match next {
+        '[' => items(data).map(Value::Array),
+        '{' => fields(data).map(|fields| Value::Struct { fields }),
+        '"' => string(data).map(Value::String),
+        '-' | '0'..='9' => number(data),
+        _ => keyword(data),
+    }
+}
+
+fn keyword<'a>(data: &mut Input<'a>) -> PResult<Value<'a>> {
+    let start = data.checkpoint();
+    let value = match ident(data)? {
+        "null" => Value::Null,
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        "nan" => Value::Float(Float::Nan),
+        "inf" => Value::Float(Float::PositiveInfinity),
+        "list" => Value::List(items(data)?),
+        "stringmap" => Value::StringMap(entries(data, key)?),
+        "intmap" => Value::IntMap(entries(data, |data| {
+            ws(data)?;
+            int(data)
+        })?),
+        "objectmap" => Value::ObjectMap(entries(data, value)?),
+        "class" => {
+            let (name, _) = name(data)?;
+            Value::Class {
+                name,
+                fields: fields(data)?,
+            }
+        }
+        "enum" => enum_value(data)?,
+        "exception" => Value::Exception(Box::new(value(data)?)),
+        "custom" => {
+            let (name, _) = name(data)?;
+            ws(data)?;
+            if data.starts_with('[') {
+                Value::Custom {
+                    name,
+                    raw: items(data)?,
+                }
+            } else {
+                Value::CustomFields {
+                    name,
+                    fields: fields(data)?,
+                }
+            }
+        }
+        "ref" => {
+            ws(data)?;
+            Value::Ref(digit1.try_map(str::parse).parse_next(data)?)
+        }
+        "date" => {
+            ws(data)?;
+            let mut date = string.try_map(|date| date.parse::<HaxeDate>());
+            Value::Date(date.parse_next(data)?)
+        }
+        "bytes" => {
+            ws(data)?;
+            let mut bytes = string.try_map(|bytes| STANDARD.decode(bytes.as_bytes()));
+            Value::Bytes(bytes.parse_next(data)?)
+        }
+        _ => {
+            data.reset(&start);
+            return Err(ErrMode::from_error_kind(data, ErrorKind::Tag));
+        }
+    };
+    Ok(value)
+}
+
+fn number<'a>(data: &mut Input<'a>) -> PResult<Value<'a>> {
+    if opt(("-", "inf")).parse_next(data)?.is_some() {
+        return Ok(Value::Float(Float::NegativeInfinity));
+    }
+
+    let exponent = (one_of(['e', 'E']), opt(one_of(['+', '-'])), digit1);
+    let start = data.checkpoint();
+    let number = (opt('-'), digit1, opt(('.', digit0)), opt(exponent))
+        .recognize()
+        .parse_next(data)?;
+    if number.contains(['.', 'e', 'E']) {
+        // every float `f64::from_str` takes is valid
+        return Ok(Value::Float(Float::new(number.parse().unwrap())));
+    }
+    match number.parse() {
+        Ok(int) => Ok(Value::Int(int)),
+        Err(_) => {
+            data.reset(&start);
+            Err(ErrMode::Cut(TextError::from_error_kind(
+                data,
+                ErrorKind::Verify,
+            )))
+        }
+    }
+}
+
+fn int(data: &mut Input<'_>) -> PResult<i32> {
+    (opt('-'), digit1)
+        .recognize()
+        .try_map(str::parse)
+        .parse_next(data)
+}
+
+fn ident<'a>(data: &mut Input<'a>) -> PResult<&'a str> {
+    (
+        one_of(|c: char| c.is_alphabetic() || c == '_'),
+        take_while(0.., |c: char| c.is_alphanumeric() || c == '_'),
+    )
+        .recognize()
+        .parse_next(data)
+}
+
+fn string<'a>(data: &mut Input<'a>) -> PResult<Cow<'a, str>> {
+    '"'.parse_next(data)?;
+    let plain = take_till(0.., ['"', '\\']).parse_next(data)?;
+    if opt('"').parse_next(data)?.is_some() {
+        return Ok(Cow::Borrowed(plain));
+    }
+
+    let mut string = plain.to_owned();
+    loop {
+        match any.parse_next(data)? {
+            '"' => return Ok(Cow::Owned(string)),
+            _escape => string.push(escape(data)?),
+        }
+        string.push_str(take_till(0.., ['"', '\\']).parse_next(data)?);
+    }
+}
+
+// the character after a `\`
+fn escape(data: &mut Input<'_>) -> PResult<char> {
+    let start = data.checkpoint();
+    let escaped = match any.parse_next(data)? {
+        'n' => Some('\n'),
+        'r' => Some('\r'),
+        't' => Some('\t'),
+        '0' => Some('\0'),
+        c @ ('"' | '\\') => Some(c),
+        'u' => {
+            let hex = take_while(1..=6, |c: char| c.is_ascii_hexdigit());
+            let (_, hex, _) = ('{', hex, '}').parse_next(data)?;
+            u32::from_str_radix(hex, 16).ok().and_then(char::from_u32)
+        }
+        _ => None,
+    };
+    escaped.ok_or_else(|| {
+        data.reset(&start);
+        ErrMode::from_error_kind(data, ErrorKind::Verify)
+    })
+}
+
+// a field name or string map key
+fn key<'a>(data: &mut Input<'a>) -> PResult<Cow<'a, str>> {
+    ws(data)?;
+    alt((string, ident.map(Cow::Borrowed))).parse_next(data)
+}
+
+// a class name, dotted identifiers or a string, and whether it was quoted
+fn name<'a>(data: &mut Input<'a>) -> PResult<(Cow<'a, str>, bool)> {
+    ws(data)?;
+    let path = (ident, repeat::<_, _, (), _, _>(0.., ('.', ident))).recognize();
+    alt((
+        string.map(|name| (name, true)),
+        path.map(|path| (Cow::Borrowed(path), false)),
+    ))
+    .parse_next(data)
+}
+
+// the name and constructor are split at the last `.`, the constructor being
+// an index for enums written by index
+fn enum_value<'a>(data: &mut Input<'a>) -> PResult<Value<'a>> {
+    let (name, quoted) = name(data)?;
+    let (name, constructor) = match name {
+        // quoted names and ones followed by a quoted constructor or index
+        // are whole
+        Cow::Borrowed(path) if !quoted && !data.starts_with('.') => match path.rsplit_once('.') {
+            Some((name, constructor)) => (Cow::Borrowed(name), Some(Cow::Borrowed(constructor))),
+            None => (Cow::Borrowed(path), None),
+        },
+        name => (name, None),
+    };
+    let constructor = match constructor {
+        Some(constructor) => Ok(constructor),
+        None => {
+            '.'.parse_next(data)?;
+            let index = digit1.try_map(str::parse).map(Err);
+            alt((string.map(Ok), ident.map(|c| Ok(Cow::Borrowed(c))), index)).parse_next(data)?
+        }
+    };
+
+    ws(data)?;
+    let fields = if data.starts_with('(') {
+        list(data, '(', ')', value)?
+    } else {
+        Vec::new()
+    };
+    Ok(match constructor {
+        Ok(constructor) => Value::Enum {
+            name,
+            constructor,
+            fields,
+        },
+        Err(index) => Value::EnumIndex {
+            name,
+            index,
+            fields,
+        },
+    })
+}
+
+fn items<'a>(data: &mut Input<'a>) -> PResult<Vec<Value<'a>>> {
+    list(data, '[', ']', value)
+}
+
+fn fields<'a>(data: &mut Input<'a>) -> PResult<Map<Cow<'a, str>, Value<'a>>> {
+    entries(data, key)
+}
+
+// `{ key: value, ... }`
+fn entries<'a, K: Eq>(
+    data: &mut Input<'a>,
+    mut key: impl FnMut(&mut Input<'a>) -> PResult<K>,
+) -> PResult<Map<K, Value<'a>>> {
+    let entry = |data: &mut Input<'a>| {
+        let key = key(data)?;
+        ws(data)?;
+        ':'.parse_next(data)?;
+        Ok((key, value(data)?))
+    };
+    Ok(list(data, '{', '}', entry)?.into_iter().collect())
+}
+
+// comma separated with an optional trailing comma
+fn list<'a, T>(
+    data: &mut Input<'a>,
+    mut open: char,
+    mut close: char,
+    mut item: impl FnMut(&mut Input<'a>) -> PResult<T>,
+) -> PResult<Vec<T>> {
+    ws(data)?;
+    open.parse_next(data)?;
+    let mut items = Vec::new();
+    loop {
+        ws(data)?;
+        if opt(close).parse_next(data)?.is_some() {
+            return Ok(items);
+        }
+        items.push(item(data)?);
+        ws(data)?;
+        if opt(',').parse_next(data)?.is_none() {
+            close.parse_next(data)?;
+            return Ok(items);
+        }
+    }
+}
+
+const INDENT: &str = "    ";
+
+fn write_value(output: &mut String, value: &Value, depth: usize) {
+    match value {
+        Value::Null => output.push_str("null"),
+        Value::Bool(value) => write!(output, "{value}").unwrap(),
+        Value::Int(value) => write!(output, "{value}").unwrap(),
+        Value::Float(Float::Nan) => output.push_str("nan"),
+        Value::Float(Float::PositiveInfinity) => output.push_str("inf"),
+        Value::Float(Float::NegativeInfinity) => output.push_str("-inf"),
+        // debug formatting always has a `.` or exponent
+        Value::Float(Float::Normal(value)) => write!(output, "{:?}", value.as_f64()).unwrap(),
+        Value::String(value) => write_string(output, value),
+        Value::Date(date) => {
+            output.push_str("date ");
+            write_string(output, &date.to_string());
+        }
+        Value::Bytes(bytes) => {
+            output.push_str("bytes ");
+            write_string(output, &STANDARD.encode(bytes));
+        }
+        Value::Array(items) => write_items(output, items, depth),
+        Value::List(items) => {
+            output.push_str("list ");
+            write_items(output, items, depth);
+        }
+        Value::StringMap(map) => {
+            output.push_str("stringmap ");
+            write_fields(output, map, depth);
+        }
+        Value::IntMap(map) => {
+            output.push_str("intmap ");
+            write_entries(output, map, depth, |output, key| {
+                write!(output, "{key}").unwrap()
+            });
+        }
+        Value::ObjectMap(map) => {
+            output.push_str("objectmap ");
+            write_entries(output, map, depth, |output, key| {
+                write_value(output, key, depth + 1)
+            });
+        }
+        Value::Struct { fields } => write_fields(output, fields, depth),
+        Value::Class { name, fields } => {
+            output.push_str("class ");
+            write_name(output, name);
+            output.push(' ');
+            write_fields(output, fields, depth);
+        }
+        Value::Enum {
+            name,
+            constructor,
+            fields,
+        } => {
+            output.push_str("enum ");
+            write_name(output, name);
+            output.push('.');
+            write_key(output, constructor);
+            write_enum_fields(output, fields, depth);
+        }
+        Value::EnumIndex {
+            name,
+            index,
+            fields,
+        } => {
+            output.push_str("enum ");
+            write_name(output, name);
+            write!(output, ".{index}").unwrap();
+            write_enum_fields(output, fields, depth);
+        }
+        Value::Exception(value) => {
+            output.push_str("exception ");
+            write_value(output, value, depth);
+        }
+        Value::Custom { name, raw } => {
+            output.push_str("custom ");
+            write_name(output, name);
+            output.push(' ');
+            write_items(output, raw, depth);
+        }
+        Value::CustomFields { name, fields } => {
+            output.push_str("custom ");
+            write_name(output, name);
+            output.push(' ');
+            write_fields(output, fields, depth);
+        }
+        Value::Ref(index) => write!(output, "ref {index}").unwrap(),
+    }
+}
+
+fn write_string(output: &mut String, value: &str) {
+    output.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            '\n' => output.push_str("\\n"),
+            '\r' => output.push_str("\\r"),
+            '\t' => output.push_str("\\t"),
+            c if c.is_control() => write!(output, "\\u{{{:x}}}", c as u32).unwrap(),
+            c => output.push(c),
+        }
+    }
+    output.push('"');
+}
+
+fn is_ident(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
+fn write_key(output: &mut String, key: &str) {
+    if is_ident(key) {
+        output.push_str(key);
+    } else {
+        write_string(output, key);
+    }
+}
+
+fn is_ident_path(s: &str) -> bool {
+    s.split('.').all(is_ident)
+}
+
+fn write_name(output: &mut String, name: &str) {
+    if is_ident_path(name) {
+        output.push_str(name);
+    } else {
+        write_string(output, name);
+    }
+}
+
+fn write_enum_fields(output: &mut String, fields: &[Value], depth: usize) {
+    if !fields.is_empty() {
+        write_list(output, ('(', ')'), fields, depth, |output, field| {
+            write_value(output, field, depth + 1)
+        });
+    }
+}
+
+fn write_items(output: &mut String, items: &[Value], depth: usize) {
+    write_list(output, ('[', ']'), items, depth, |output, item| {
+        write_value(output, item, depth + 1)
+    });
+}
+
+fn write_fields(output: &mut String, fields: &Map<Cow<str>, Value>, depth: usize) {
+    write_entries(output, fields, depth, |output, key| write_key(output, key));
+}
+
+fn write_entries<K>(
+    output: &mut String,
+    map: &Map<K, Value>,
+    depth: usize,
+    mut write_key: impl FnMut(&mut String, &K),
+) {
+    write_list(output, ('{', '}'), map, depth, |output, (key, value)| {
+        write_key(output, key);
+        output.push_str(": ");
+        write_value(output, value, depth + 1);
+    });
+}
+
+// one item per line, unless there aren't any
+fn write_list<T>(
+    output: &mut String,
+    (open, close): (char, char),
+    items: impl IntoIterator<Item = T>,
+    depth: usize,
+    mut write_item: impl FnMut(&mut String, T),
+) {
+    output.push(open);
+    let mut empty = true;
+    for item in items {
+        empty = false;
+        output.push('\n');
+        output.push_str(&INDENT.repeat(depth + 1));
+        write_item(output, item);
+        output.push(',');
+    }
+    if !empty {
+        output.push('\n');
+        output.push_str(&INDENT.repeat(depth));
+    }
+    output.push(close);
+}
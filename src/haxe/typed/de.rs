@@ -0,0 +1,848 @@
+use std::{borrow::Cow, sync::Arc};
+
+use serde::de::{
+    self,
+    value::{
+        BorrowedStrDeserializer, I32Deserializer, MapDeserializer, SeqAccessDeserializer,
+        SeqDeserializer, StringDeserializer, U32Deserializer,
+    },
+    Deserialize, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess, SeqAccess,
+    VariantAccess, Visitor,
+};
+use winnow::{
+    ascii::{dec_int, dec_uint, float},
+    stream::Location,
+    token::any,
+    Located, Parser, Partial,
+};
+
+use super::Error;
+use crate::haxe::{
+    custom::{self, CustomCodec},
+    de::{self as parser, finish_error, Input, PResult, ParserState},
+    object_cache::Cached,
+    visitor::ValueBuilder,
+    DecodeError, HaxeDate, Value,
+};
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Deserialize the top level values of `input` as a sequence, so they can be
+/// read into a tuple, a `Vec` or a struct with a field per value.
+///
+/// Use [`Deserializer`] directly to read top level values one at a time.
+pub fn from_str_typed<'a, T: Deserialize<'a>>(input: &'a str) -> Result<T> {
+    let mut deserializer = Deserializer::from_str(input);
+    let value = T::deserialize(TopLevel(&mut deserializer))?;
+    deserializer.end()?;
+    Ok(value)
+}
+
+/// Deserializes one haxe value at a time straight from the serialized input
+pub struct Deserializer<'de> {
+    state: ParserState<'de>,
+    input: Partial<Located<&'de str>>,
+    custom_codecs: custom::Codecs,
+}
+
+impl<'de> Deserializer<'de> {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(input: &'de str) -> Self {
+        let state = ParserState::new(input);
+        Self {
+            input: state.input_at(0, false),
+            state,
+            custom_codecs: custom::Codecs::default(),
+        }
+    }
+
+    /// Read custom classes named `name` with `codec` instead of
+    /// [`FieldArrays`](crate::haxe::FieldArrays)
+    #[must_use]
+    pub fn custom_codec(
+        mut self,
+        name: impl Into<String>,
+        codec: impl CustomCodec + 'static,
+    ) -> Self {
+        self.custom_codecs.insert(name.into(), Arc::new(codec));
+        self
+    }
+
+    /// Whether every top level value has been read
+    pub fn is_empty(&self) -> bool {
+        self.input.is_empty()
+    }
+
+    /// Errors if there are any top level values left
+    pub fn end(&self) -> Result<()> {
+        if self.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Message {
+                message: "trailing values".to_owned(),
+                offset: Some(self.offset()),
+            })
+        }
+    }
+
+    fn offset(&self) -> usize {
+        self.input.location()
+    }
+
+    fn error(&self, err: DecodeError) -> Error {
+        Error::Decode(err.with_snippet(self.state.source))
+    }
+
+    fn syntax_error(&self) -> Error {
+        self.error(DecodeError::Syntax {
+            offset: self.offset(),
+            snippet: String::new(),
+        })
+    }
+
+    fn peek(&self) -> Result<char> {
+        self.input.chars().next().ok_or_else(|| self.syntax_error())
+    }
+
+    // runs one of the value parser's parsers on the input
+    fn parse<O>(&mut self, parser: impl FnOnce(&mut Input<'de, '_>) -> PResult<O>) -> Result<O> {
+        let mut data = Input {
+            input: self.input,
+            state: &mut self.state,
+        };
+        let result = parser(&mut data);
+        let input = data.input;
+        match result {
+            Ok(output) => {
+                self.input = input;
+                Ok(output)
+            }
+            Err(err) => Err(Error::Decode(finish_error(err, self.state.source))),
+        }
+    }
+
+    fn advance(&mut self) -> Result<char> {
+        self.parse(|data| any.parse_next(data))
+    }
+
+    fn expect(&mut self, mut expected: char) -> Result<()> {
+        self.parse(|data| expected.parse_next(data)).map(drop)
+    }
+
+    fn cache_start(&mut self, start: usize) -> Option<usize> {
+        self.state.object_cache.reserve(start)
+    }
+
+    fn cache_finish(&mut self, index: Option<usize>) {
+        let end = self.offset();
+        self.state.object_cache.fill(index, end);
+    }
+
+    fn cache_done(&mut self, start: usize) {
+        let end = self.offset();
+        self.state.object_cache.push(start, end);
+    }
+
+    fn parse_string(&mut self) -> Result<Cow<'de, str>> {
+        self.parse(parser::parse_string)
+    }
+
+    // reads a value inside of the ones being read, see `MAX_DEPTH`
+    fn nested<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        let offset = self.offset();
+        if let Err(err) = self.state.enter(offset) {
+            return Err(self.error(err));
+        }
+        let result = f(self);
+        self.state.leave();
+        result
+    }
+
+    fn replay<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        let offset = self.offset();
+        let start = match self.parse(parser::parse_object_cache_entry)? {
+            (_, Cached::Object { start }) => start,
+            (index, Cached::Unfinished) => {
+                return Err(Error::Message {
+                    message: format!("object reference r{index} points at an object containing it"),
+                    offset: Some(offset),
+                })
+            }
+            (_, Cached::Dropped) => unreachable!("all of the input is kept"),
+        };
+
+        let resume = std::mem::replace(&mut self.input, self.state.input_at(start, false));
+        self.state.object_cache.begin_replay(offset);
+        let result = f(self);
+        self.state.object_cache.end_replay();
+        self.input = resume;
+        result
+    }
+
+    fn deserialize_value<V: Visitor<'de>>(&mut self, visitor: V) -> Result<V::Value> {
+        let start = self.offset();
+        match self.peek()? {
+            'n' => {
+                self.advance()?;
+                visitor.visit_unit()
+            }
+            't' => {
+                self.advance()?;
+                visitor.visit_bool(true)
+            }
+            'f' => {
+                self.advance()?;
+                visitor.visit_bool(false)
+            }
+            'z' => {
+                self.advance()?;
+                visitor.visit_i32(0)
+            }
+            'i' => {
+                self.advance()?;
+                let n: i32 = self.parse(|data| dec_int.parse_next(data))?;
+                visitor.visit_i32(n)
+            }
+            'd' => {
+                self.advance()?;
+                let n: f64 = self.parse(|data| float.parse_next(data))?;
+                visitor.visit_f64(n)
+            }
+            'k' => {
+                self.advance()?;
+                visitor.visit_f64(f64::NAN)
+            }
+            'm' => {
+                self.advance()?;
+                visitor.visit_f64(f64::NEG_INFINITY)
+            }
+            'p' => {
+                self.advance()?;
+                visitor.visit_f64(f64::INFINITY)
+            }
+            'y' | 'R' => match self.parse_string()? {
+                Cow::Borrowed(s) => visitor.visit_borrowed_str(s),
+                Cow::Owned(s) => visitor.visit_string(s),
+            },
+            'v' => {
+                self.advance()?;
+                let date_start = self.offset();
+                let date = self.parse(parser::parse_date_body)?;
+                self.cache_done(start);
+                // local dates are read as their text, timestamps as their
+                // milliseconds
+                match date {
+                    HaxeDate::Local(_) => {
+                        let source = self.state.source;
+                        visitor.visit_borrowed_str(&source[date_start..self.offset()])
+                    }
+                    HaxeDate::Timestamp(millis) => visitor.visit_f64(millis.as_f64()),
+                }
+            }
+            's' => visitor.visit_byte_buf(self.parse(parser::parse_bytes)?),
+            tag @ ('a' | 'l') => {
+                self.advance()?;
+                let index = self.cache_start(start);
+                let mut seq = Seq {
+                    de: self,
+                    null_runs: tag == 'a',
+                    pending_nulls: 0,
+                };
+                let value = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+                self.cache_finish(index);
+                Ok(value)
+            }
+            tag @ ('o' | 'c' | 'b' | 'q' | 'M') => {
+                self.advance()?;
+                let index = self.cache_start(start);
+                if tag == 'c' {
+                    // the class name has no place in serde's data model
+                    self.parse_string()?;
+                }
+                let (keys, end) = match tag {
+                    'o' | 'c' => (MapKeys::String, 'g'),
+                    'b' => (MapKeys::String, 'h'),
+                    'q' => (MapKeys::Int, 'h'),
+                    _ => (MapKeys::Value, 'h'),
+                };
+                let mut map = Map {
+                    de: self,
+                    keys,
+                    end,
+                };
+                let value = visitor.visit_map(&mut map)?;
+                map.end()?;
+                self.cache_finish(index);
+                Ok(value)
+            }
+            // what `hxSerialize` wrote only has names once a codec gives them,
+            // so custom classes are decoded as values first
+            'C' => {
+                let custom_codecs = std::mem::take(&mut self.custom_codecs);
+                let mut builder = ValueBuilder::new(&custom_codecs);
+                let result = self.parse(|data| parser::parse_tagged(data, &mut builder));
+                let value = builder.finished();
+                self.custom_codecs = custom_codecs;
+                result?;
+                let value = value.expect("a custom class was parsed");
+                de::Deserializer::deserialize_any(ValueDeserializer(value), visitor)
+            }
+            'w' | 'j' => visitor.visit_enum(self.enum_access()?),
+            'x' => {
+                self.advance()?;
+                self.nested(|de| de.deserialize_value(visitor))
+            }
+            'r' => self.replay(|de| de.nested(|de| de.deserialize_value(visitor))),
+            tag => Err(self.error(DecodeError::UnknownTag {
+                tag,
+                offset: start,
+                snippet: String::new(),
+            })),
+        }
+    }
+
+    fn enum_access<'a>(&'a mut self) -> Result<Enum<'a, 'de>> {
+        let start = self.offset();
+        let variant = match self.advance()? {
+            'w' => {
+                self.parse_string()?;
+                Variant::Name(self.parse_string()?)
+            }
+            'j' => {
+                self.parse_string()?;
+                self.expect(':')?;
+                Variant::Index(self.parse(|data| dec_uint.parse_next(data))?)
+            }
+            _ => {
+                return Err(self.error(DecodeError::Syntax {
+                    offset: start,
+                    snippet: String::new(),
+                }))
+            }
+        };
+        self.expect(':')?;
+        let count: usize = self.parse(|data| dec_uint.parse_next(data))?;
+
+        Ok(Enum {
+            de: self,
+            start,
+            variant,
+            count,
+        })
+    }
+}
+
+impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let offset = self.offset();
+        self.nested(|de| de.deserialize_value(visitor))
+            .map_err(|err| err.at(offset))
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        if self.peek()? == 'n' {
+            self.advance()?;
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        let offset = self.offset();
+        let result = self.nested(|de| match de.peek()? {
+            'w' | 'j' => de
+                .enum_access()
+                .and_then(|access| visitor.visit_enum(access)),
+            // plain strings work for enums without fields
+            'y' | 'R' => match de.parse_string()? {
+                Cow::Borrowed(s) => visitor.visit_enum(s.into_deserializer()),
+                Cow::Owned(s) => visitor.visit_enum(s.into_deserializer()),
+            },
+            'r' => de.replay(|de| de.deserialize_enum(_name, _variants, visitor)),
+            'x' => {
+                de.advance()?;
+                de.deserialize_enum(_name, _variants, visitor)
+            }
+            _ => de.deserialize_value(visitor),
+        });
+        result.map_err(|err| err.at(offset))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+struct TopLevel<'a, 'de>(&'a mut Deserializer<'de>);
+
+impl<'de> de::Deserializer<'de> for TopLevel<'_, 'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_seq(self)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+impl<'de> SeqAccess<'de> for TopLevel<'_, 'de> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>> {
+        if self.0.is_empty() {
+            return Ok(None);
+        }
+
+        seed.deserialize(&mut *self.0).map(Some)
+    }
+}
+
+struct Seq<'a, 'de> {
+    de: &'a mut Deserializer<'de>,
+    // only arrays compress runs of nulls into `u<count>`
+    null_runs: bool,
+    pending_nulls: usize,
+}
+
+impl<'de> Seq<'_, 'de> {
+    fn has_next(&mut self) -> Result<bool> {
+        if self.pending_nulls > 0 {
+            return Ok(true);
+        }
+
+        match self.de.peek()? {
+            'h' => Ok(false),
+            'u' if self.null_runs => {
+                self.pending_nulls = self.de.parse(parser::parse_null_run)?;
+                Ok(true)
+            }
+            _ => Ok(true),
+        }
+    }
+
+    fn next<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<T::Value> {
+        if self.pending_nulls > 0 {
+            self.pending_nulls -= 1;
+            return seed.deserialize(Null);
+        }
+
+        seed.deserialize(&mut *self.de)
+    }
+
+    fn end(&mut self) -> Result<()> {
+        if self.pending_nulls > 0 || self.de.peek()? != 'h' {
+            return Err(Error::Message {
+                message: "trailing elements".to_owned(),
+                offset: Some(self.de.offset()),
+            });
+        }
+
+        self.de.expect('h')
+    }
+}
+
+impl<'de> SeqAccess<'de> for Seq<'_, 'de> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>> {
+        if !self.has_next()? {
+            return Ok(None);
+        }
+
+        self.next(seed).map(Some)
+    }
+}
+
+enum MapKeys {
+    String,
+    Int,
+    Value,
+}
+
+struct Map<'a, 'de> {
+    de: &'a mut Deserializer<'de>,
+    keys: MapKeys,
+    end: char,
+}
+
+impl Map<'_, '_> {
+    fn end(&mut self) -> Result<()> {
+        if self.de.peek()? != self.end {
+            return Err(Error::Message {
+                message: "trailing entries".to_owned(),
+                offset: Some(self.de.offset()),
+            });
+        }
+
+        self.de.expect(self.end)
+    }
+}
+
+impl<'de> MapAccess<'de> for Map<'_, 'de> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>> {
+        if self.de.peek()? == self.end {
+            return Ok(None);
+        }
+
+        match self.keys {
+            MapKeys::String => deserialize_str_key(seed, self.de.parse_string()?),
+            MapKeys::Int => {
+                self.de.expect(':')?;
+                let key: i32 = self.de.parse(|data| dec_int.parse_next(data))?;
+                seed.deserialize(I32Deserializer::new(key))
+            }
+            MapKeys::Value => seed.deserialize(&mut *self.de),
+        }
+        .map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value> {
+        seed.deserialize(&mut *self.de)
+    }
+}
+
+fn deserialize_str_key<'de, K: DeserializeSeed<'de>>(
+    seed: K,
+    key: Cow<'de, str>,
+) -> Result<K::Value> {
+    match key {
+        Cow::Borrowed(key) => seed.deserialize(BorrowedStrDeserializer::new(key)),
+        Cow::Owned(key) => seed.deserialize(StringDeserializer::new(key)),
+    }
+}
+
+enum Variant<'de> {
+    Name(Cow<'de, str>),
+    Index(u32),
+}
+
+impl<'de> Variant<'de> {
+    fn deserialize<V: DeserializeSeed<'de>>(&self, seed: V) -> Result<V::Value> {
+        match self {
+            Variant::Name(name) => deserialize_str_key(seed, name.clone()),
+            Variant::Index(index) => seed.deserialize(U32Deserializer::<Error>::new(*index)),
+        }
+    }
+}
+
+struct Enum<'a, 'de> {
+    de: &'a mut Deserializer<'de>,
+    start: usize,
+    variant: Variant<'de>,
+    count: usize,
+}
+
+impl<'de> Enum<'_, 'de> {
+    fn fields(&mut self) -> EnumFields<'_, 'de> {
+        EnumFields {
+            de: &mut *self.de,
+            remaining: self.count,
+        }
+    }
+
+    // haxe caches enums only after their fields
+    fn end(self, remaining: usize) -> Result<()> {
+        if remaining > 0 {
+            return Err(Error::Message {
+                message: format!("enum has {remaining} fields left over"),
+                offset: Some(self.de.offset()),
+            });
+        }
+
+        self.de.cache_done(self.start);
+        Ok(())
+    }
+}
+
+impl<'de> EnumAccess<'de> for Enum<'_, 'de> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self)> {
+        let value = self.variant.deserialize(seed)?;
+        Ok((value, self))
+    }
+}
+
+impl<'de> VariantAccess<'de> for Enum<'_, 'de> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<()> {
+        let count = self.count;
+        self.end(count)
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(mut self, seed: T) -> Result<T::Value> {
+        if self.count == 1 {
+            let value = seed.deserialize(&mut *self.de)?;
+            self.end(0)?;
+            return Ok(value);
+        }
+
+        // lets ignored values and sequences take enums with any number of fields
+        let mut fields = self.fields();
+        let value = seed.deserialize(SeqAccessDeserializer::new(&mut fields))?;
+        let remaining = fields.remaining;
+        self.end(remaining)?;
+        Ok(value)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(mut self, _len: usize, visitor: V) -> Result<V::Value> {
+        let mut fields = self.fields();
+        let value = visitor.visit_seq(&mut fields)?;
+        let remaining = fields.remaining;
+        self.end(remaining)?;
+        Ok(value)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        self.tuple_variant(0, visitor)
+    }
+}
+
+struct EnumFields<'a, 'de> {
+    de: &'a mut Deserializer<'de>,
+    remaining: usize,
+}
+
+impl<'de> SeqAccess<'de> for EnumFields<'_, 'de> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+
+        self.remaining -= 1;
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.remaining)
+    }
+}
+
+// stands in for the nulls of an array's `u<count>`
+struct Null;
+
+impl<'de> de::Deserializer<'de> for Null {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_none()
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct newtype_struct seq tuple tuple_struct
+        map struct enum identifier ignored_any
+    }
+}
+
+// a value already decoded, which is how custom classes are read
+struct ValueDeserializer<'de>(Value<'de>);
+
+impl<'de> IntoDeserializer<'de, Error> for ValueDeserializer<'de> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+fn visit_values<'de, V: Visitor<'de>>(values: Vec<Value<'de>>, visitor: V) -> Result<V::Value> {
+    let mut seq = SeqDeserializer::new(values.into_iter().map(ValueDeserializer));
+    let value = visitor.visit_seq(&mut seq)?;
+    seq.end()?;
+    Ok(value)
+}
+
+fn visit_entries<'de, V: Visitor<'de>>(
+    entries: impl Iterator<Item = (Value<'de>, Value<'de>)>,
+    visitor: V,
+) -> Result<V::Value> {
+    let entries = entries.map(|(key, value)| (ValueDeserializer(key), ValueDeserializer(value)));
+    let mut map = MapDeserializer::new(entries);
+    let value = visitor.visit_map(&mut map)?;
+    map.end()?;
+    Ok(value)
+}
+
+impl<'de> de::Deserializer<'de> for ValueDeserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.0 {
+            Value::Null => visitor.visit_unit(),
+            Value::Bool(value) => visitor.visit_bool(value),
+            Value::Int(value) => visitor.visit_i32(value),
+            Value::Float(value) => visitor.visit_f64(value.as_f64()),
+            Value::String(Cow::Borrowed(s)) => visitor.visit_borrowed_str(s),
+            Value::String(Cow::Owned(s)) => visitor.visit_string(s),
+            // read like dates anywhere else
+            Value::Date(date @ HaxeDate::Local(_)) => visitor.visit_string(date.to_string()),
+            Value::Date(HaxeDate::Timestamp(millis)) => visitor.visit_f64(millis.as_f64()),
+            Value::Bytes(bytes) => visitor.visit_byte_buf(bytes),
+            Value::Array(values) | Value::List(values) | Value::Custom { raw: values, .. } => {
+                visit_values(values, visitor)
+            }
+            Value::StringMap(fields)
+            | Value::Struct { fields }
+            | Value::Class { fields, .. }
+            | Value::CustomFields { fields, .. } => visit_entries(
+                fields
+                    .into_iter()
+                    .map(|(key, value)| (Value::String(key), value)),
+                visitor,
+            ),
+            Value::IntMap(entries) => visit_entries(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (Value::Int(key), value)),
+                visitor,
+            ),
+            Value::ObjectMap(entries) => visit_entries(entries.into_iter(), visitor),
+            Value::Enum {
+                constructor,
+                fields,
+                ..
+            } => visitor.visit_enum(ValueEnum {
+                variant: Variant::Name(constructor),
+                fields,
+            }),
+            Value::EnumIndex { index, fields, .. } => {
+                let Ok(index) = index.try_into() else {
+                    return Err(de::Error::custom(format!("enum index {index} is too big")));
+                };
+                visitor.visit_enum(ValueEnum {
+                    variant: Variant::Index(index),
+                    fields,
+                })
+            }
+            Value::Exception(value) => ValueDeserializer(*value).deserialize_any(visitor),
+            Value::Ref(_) => unreachable!("references are copied"),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.0 {
+            Value::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        match self.0 {
+            // plain strings work for enums without fields
+            Value::String(Cow::Borrowed(s)) => visitor.visit_enum(s.into_deserializer()),
+            Value::String(Cow::Owned(s)) => visitor.visit_enum(s.into_deserializer()),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+struct ValueEnum<'de> {
+    variant: Variant<'de>,
+    fields: Vec<Value<'de>>,
+}
+
+impl<'de> EnumAccess<'de> for ValueEnum<'de> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self)> {
+        let value = self.variant.deserialize(seed)?;
+        Ok((value, self))
+    }
+}
+
+impl<'de> VariantAccess<'de> for ValueEnum<'de> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<()> {
+        match self.fields.len() {
+            0 => Ok(()),
+            remaining => Err(de::Error::custom(format!(
+                "enum has {remaining} fields left over"
+            ))),
+        }
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(mut self, seed: T) -> Result<T::Value> {
+        match self.fields.pop() {
+            Some(field) if self.fields.is_empty() => seed.deserialize(ValueDeserializer(field)),
+            field => {
+                self.fields.extend(field);
+                let mut fields =
+                    SeqDeserializer::new(self.fields.into_iter().map(ValueDeserializer));
+                let value = seed.deserialize(SeqAccessDeserializer::new(&mut fields))?;
+                fields.end()?;
+                Ok(value)
+            }
+        }
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value> {
+        visit_values(self.fields, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        visit_values(self.fields, visitor)
+    }
+}
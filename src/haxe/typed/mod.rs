@@ -0,0 +1,89 @@
+//! Serde support that works on the haxe format directly, without going
+//! through [`Value`](super::Value)
+
+mod de;
+mod ser;
+
+use std::fmt::{self, Display};
+
+use super::DecodeError;
+
+pub use de::{from_str_typed, Deserializer};
+pub use ser::{to_string_typed, Compound, Serializer};
+
+/// Prefix for the name of a struct written as a class, as in
+/// `#[serde(rename = "class:Player")]`
+pub const CLASS_PREFIX: &str = "class:";
+
+/// Prefix for the name of a struct written as a custom class, as in
+/// `#[serde(rename = "custom:Inventory")]`
+pub const CUSTOM_PREFIX: &str = "custom:";
+
+const LIST: &str = "$haxe::List";
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    Decode(#[from] DecodeError),
+
+    #[error("failed to write output")]
+    Write(#[from] fmt::Error),
+
+    #[error("{message}{}", .offset.map(|offset| format!(" at byte {offset}")).unwrap_or_default())]
+    Message {
+        message: String,
+        offset: Option<usize>,
+    },
+}
+
+impl Error {
+    fn at(self, at: usize) -> Self {
+        match self {
+            Self::Message {
+                message,
+                offset: None,
+            } => Self::Message {
+                message,
+                offset: Some(at),
+            },
+            err => err,
+        }
+    }
+}
+
+impl serde::de::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Self::Message {
+            message: msg.to_string(),
+            offset: None,
+        }
+    }
+}
+
+impl serde::ser::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Self::Message {
+            message: msg.to_string(),
+            offset: None,
+        }
+    }
+}
+
+/// Write a sequence as a haxe `List` instead of an `Array`, with
+/// `#[serde(with = "haxe::typed::list")]`
+pub mod list {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<T: Serialize + ?Sized, S: Serializer>(
+        value: &T,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(super::LIST, value)
+    }
+
+    pub fn deserialize<'de, T: Deserialize<'de>, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<T, D::Error> {
+        T::deserialize(deserializer)
+    }
+}
@@ -0,0 +1,958 @@
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fmt::{self, Display, Write},
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use percent_encoding::percent_encode;
+use serde::ser::{self, Error as _, Impossible, Serialize};
+
+use super::{Error, CLASS_PREFIX, CUSTOM_PREFIX, LIST};
+use crate::haxe::{ser::write_float, Serializer as Options};
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Serialize a tuple, sequence or struct as top level values, the
+/// counterpart of [`from_str_typed`](super::from_str_typed)
+pub fn to_string_typed<T: Serialize + ?Sized>(values: &T) -> Result<String> {
+    Options::new().encode_typed(values)
+}
+
+impl Options {
+    /// Like [`Options::encode`], but for any [`Serialize`] tuple, sequence or
+    /// struct of top level values
+    ///
+    /// [`Options::use_cache`] has no effect, as there are no values to
+    /// compare. Enums are written by index if [`Options::use_enum_index`] is
+    /// set, using the variant's declaration order.
+    pub fn encode_typed<T: Serialize + ?Sized>(&self, values: &T) -> Result<String> {
+        let mut serializer = Serializer::new(self, String::new());
+        values.serialize(TopLevel(&mut serializer))?;
+        Ok(serializer.into_inner())
+    }
+}
+
+/// Writes one haxe value per `serialize` call
+pub struct Serializer<'o, W> {
+    options: &'o Options,
+    output: W,
+    string_cache: HashMap<String, usize>,
+
+    // set right before serializing a value they apply to
+    custom_fields: Option<Vec<&'static str>>,
+    list: bool,
+}
+
+impl<'o, W: Write> Serializer<'o, W> {
+    pub fn new(options: &'o Options, output: W) -> Self {
+        Self {
+            options,
+            output,
+            string_cache: HashMap::new(),
+            custom_fields: None,
+            list: false,
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.output
+    }
+
+    fn write_string(&mut self, value: &str) -> Result<()> {
+        if self.options.use_string_cache {
+            if let Some(index) = self.string_cache.get(value) {
+                write!(self.output, "R{index}")?;
+                return Ok(());
+            }
+            let next_index = self.string_cache.len();
+            self.string_cache.insert(value.to_owned(), next_index);
+        }
+
+        let encoded: Cow<'_, str> =
+            percent_encode(value.as_bytes(), self.options.encode_set).into();
+        write!(self.output, "y{len}:{encoded}", len = encoded.len())?;
+        Ok(())
+    }
+
+    fn write_int<N: TryInto<i32> + Display + Copy>(&mut self, value: N) -> Result<()> {
+        match value.try_into() {
+            Ok(0) => self.output.write_char('z')?,
+            Ok(n) => write!(self.output, "i{n}")?,
+            Err(_) => return Err(Error::custom(format!("{value} doesn't fit in a haxe Int"))),
+        }
+        Ok(())
+    }
+
+    fn write_float(&mut self, value: f64) -> Result<()> {
+        if value.is_nan() {
+            self.output.write_char('k')?;
+        } else if value == f64::INFINITY {
+            self.output.write_char('p')?;
+        } else if value == f64::NEG_INFINITY {
+            self.output.write_char('m')?;
+        } else {
+            write_float(&mut self.output, self.options.float_format, value)?;
+        }
+        Ok(())
+    }
+
+    fn write_nulls(&mut self, count: usize) -> Result<()> {
+        match count {
+            0 => {}
+            1 => self.output.write_char('n')?,
+            count => write!(self.output, "u{count}")?,
+        }
+        Ok(())
+    }
+
+    fn write_enum(
+        &mut self,
+        name: &str,
+        index: u32,
+        constructor: &str,
+        field_count: usize,
+    ) -> Result<()> {
+        if self.options.use_enum_index == Some(true) {
+            self.output.write_char('j')?;
+            self.write_string(name)?;
+            write!(self.output, ":{index}:{field_count}")?;
+        } else {
+            self.output.write_char('w')?;
+            self.write_string(name)?;
+            self.write_string(constructor)?;
+            write!(self.output, ":{field_count}")?;
+        }
+        Ok(())
+    }
+
+    /// Write `value` after any written so far
+    ///
+    /// Custom classes can only be written this way, as their field names have
+    /// to be found before anything is written.
+    pub fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        let shape = value.serialize(Probe).ok();
+        self.serialize_probed(value, shape)
+    }
+
+    fn serialize_probed<T: Serialize + ?Sized>(
+        &mut self,
+        value: &T,
+        shape: Option<Shape>,
+    ) -> Result<()> {
+        // customs write all their field names before any of the values
+        self.custom_fields = match shape {
+            Some(Shape::Custom(fields)) => Some(fields),
+            _ => None,
+        };
+        // a list that wasn't a sequence doesn't make the ones inside it lists
+        self.list = false;
+        value.serialize(&mut *self)
+    }
+}
+
+impl<'a, 'o, W: Write> ser::Serializer for &'a mut Serializer<'o, W> {
+    type Ok = ();
+    type Error = Error;
+
+    type SerializeSeq = Compound<'a, 'o, W>;
+    type SerializeTuple = Compound<'a, 'o, W>;
+    type SerializeTupleStruct = Compound<'a, 'o, W>;
+    type SerializeTupleVariant = Compound<'a, 'o, W>;
+    type SerializeMap = Compound<'a, 'o, W>;
+    type SerializeStruct = Compound<'a, 'o, W>;
+    type SerializeStructVariant = Compound<'a, 'o, W>;
+
+    fn serialize_bool(self, v: bool) -> Result<()> {
+        self.output.write_char(if v { 't' } else { 'f' })?;
+        Ok(())
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<()> {
+        self.write_int(v)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<()> {
+        self.write_int(v)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<()> {
+        self.write_int(v)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<()> {
+        self.write_int(v)
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<()> {
+        self.write_int(v)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<()> {
+        self.write_int(v)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<()> {
+        self.write_int(v)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<()> {
+        self.write_int(v)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<()> {
+        self.write_int(v)
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<()> {
+        self.write_int(v)
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<()> {
+        self.write_float(v.into())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<()> {
+        self.write_float(v)
+    }
+
+    fn serialize_char(self, v: char) -> Result<()> {
+        self.write_string(v.encode_utf8(&mut [0; 4]))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<()> {
+        self.write_string(v)
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<()> {
+        let encoded = STANDARD.encode(v);
+        write!(self.output, "s{len}:{encoded}", len = encoded.len())?;
+        Ok(())
+    }
+
+    fn serialize_none(self) -> Result<()> {
+        self.output.write_char('n')?;
+        Ok(())
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<()> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<()> {
+        self.serialize_none()
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<()> {
+        self.serialize_none()
+    }
+
+    fn serialize_unit_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+    ) -> Result<()> {
+        self.write_enum(name, variant_index, variant, 0)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        self.list = name == LIST;
+        value.serialize(&mut *self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        self.write_enum(name, variant_index, variant, 1)?;
+        self.serialize_value(value)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
+        if std::mem::take(&mut self.list) {
+            self.output.write_char('l')?;
+            Ok(Compound::new(self, CompoundKind::List))
+        } else {
+            self.output.write_char('a')?;
+            Ok(Compound::new(self, CompoundKind::Array { nulls: 0 }))
+        }
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
+        self.write_enum(name, variant_index, variant, len)?;
+        Ok(Compound::new(self, CompoundKind::EnumFields))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+        // the kind of map depends on the keys
+        Ok(Compound::new(self, CompoundKind::Map { keys: None }))
+    }
+
+    fn serialize_struct(self, name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
+        if let Some(name) = name.strip_prefix(CUSTOM_PREFIX) {
+            let Some(fields) = self.custom_fields.take() else {
+                return Err(Error::custom(format!(
+                    "custom class {name} must be written with Serializer::serialize_value"
+                )));
+            };
+
+            self.output.write_char('C')?;
+            self.write_string(name)?;
+            self.output.write_char('a')?;
+            for field in fields {
+                self.write_string(field)?;
+            }
+            self.output.write_str("ha")?;
+            return Ok(Compound::new(self, CompoundKind::Custom { nulls: 0 }));
+        }
+
+        if let Some(name) = name.strip_prefix(CLASS_PREFIX) {
+            self.output.write_char('c')?;
+            self.write_string(name)?;
+        } else {
+            self.output.write_char('o')?;
+        }
+        Ok(Compound::new(self, CompoundKind::Object))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+        self.write_enum(name, variant_index, variant, len)?;
+        Ok(Compound::new(self, CompoundKind::EnumFields))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum MapKeys {
+    String,
+    Int,
+    Value,
+}
+
+enum CompoundKind {
+    Array { nulls: usize },
+    List,
+    Object,
+    Custom { nulls: usize },
+    Map { keys: Option<MapKeys> },
+    EnumFields,
+}
+
+pub struct Compound<'a, 'o, W> {
+    serializer: &'a mut Serializer<'o, W>,
+    kind: CompoundKind,
+}
+
+impl<'a, 'o, W: Write> Compound<'a, 'o, W> {
+    fn new(serializer: &'a mut Serializer<'o, W>, kind: CompoundKind) -> Self {
+        Self { serializer, kind }
+    }
+
+    fn element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        let (CompoundKind::Array { nulls } | CompoundKind::Custom { nulls }) = &mut self.kind
+        else {
+            return self.serializer.serialize_value(value);
+        };
+
+        // arrays write runs of nulls as `u<count>`
+        let shape = value.serialize(Probe).ok();
+        if matches!(shape, Some(Shape::Null)) {
+            *nulls += 1;
+            return Ok(());
+        }
+
+        let nulls = std::mem::take(nulls);
+        self.serializer.write_nulls(nulls)?;
+        self.serializer.serialize_probed(value, shape)
+    }
+
+    fn key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<()> {
+        let CompoundKind::Map { keys } = &mut self.kind else {
+            unreachable!("only maps have keys");
+        };
+
+        let key_value = key.serialize(KeyProbe).unwrap_or(Key::Value);
+        let kind = match key_value {
+            Key::String(_) => MapKeys::String,
+            Key::Int(_) => MapKeys::Int,
+            Key::Value => MapKeys::Value,
+        };
+        match *keys {
+            None => {
+                let tag = match kind {
+                    MapKeys::String => 'b',
+                    MapKeys::Int => 'q',
+                    MapKeys::Value => 'M',
+                };
+                self.serializer.output.write_char(tag)?;
+                *keys = Some(kind);
+            }
+            Some(keys) if keys != kind => {
+                return Err(Error::custom(
+                    "map keys must all be strings, all be integers or all be neither",
+                ));
+            }
+            Some(_) => {}
+        }
+
+        match key_value {
+            Key::String(key) => self.serializer.write_string(&key),
+            Key::Int(key) => {
+                write!(self.serializer.output, ":{key}")?;
+                Ok(())
+            }
+            Key::Value => self.serializer.serialize_value(key),
+        }
+    }
+
+    fn field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<()> {
+        if let CompoundKind::Object = self.kind {
+            self.serializer.write_string(key)?;
+        }
+        self.element(value)
+    }
+
+    fn end(self) -> Result<()> {
+        let output = &mut self.serializer.output;
+        match self.kind {
+            CompoundKind::Array { nulls } => {
+                self.serializer.write_nulls(nulls)?;
+                self.serializer.output.write_char('h')?;
+            }
+            CompoundKind::List => output.write_char('h')?,
+            CompoundKind::Object => output.write_char('g')?,
+            CompoundKind::Custom { nulls } => {
+                self.serializer.write_nulls(nulls)?;
+                self.serializer.output.write_str("hg")?;
+            }
+            // an empty map could be any kind of map
+            CompoundKind::Map { keys: None } => output.write_str("bh")?,
+            CompoundKind::Map { keys: Some(_) } => output.write_char('h')?,
+            CompoundKind::EnumFields => {}
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> ser::SerializeSeq for Compound<'_, '_, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.end()
+    }
+}
+
+impl<W: Write> ser::SerializeTuple for Compound<'_, '_, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.end()
+    }
+}
+
+impl<W: Write> ser::SerializeTupleStruct for Compound<'_, '_, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.end()
+    }
+}
+
+impl<W: Write> ser::SerializeTupleVariant for Compound<'_, '_, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.end()
+    }
+}
+
+impl<W: Write> ser::SerializeMap for Compound<'_, '_, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<()> {
+        self.key(key)
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.end()
+    }
+}
+
+impl<W: Write> ser::SerializeStruct for Compound<'_, '_, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        self.field(key, value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.end()
+    }
+}
+
+impl<W: Write> ser::SerializeStructVariant for Compound<'_, '_, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        self.field(key, value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.end()
+    }
+}
+
+// The methods of a serializer that only cares about a few kinds of values
+macro_rules! reject {
+    ($error:expr; $($method:ident($($ty:ty),*) -> $ok:ty;)*) => {
+        $(
+            fn $method(self, $(_: $ty),*) -> std::result::Result<$ok, Self::Error> {
+                Err($error)
+            }
+        )*
+    };
+}
+
+// Anything a probe doesn't care about
+#[derive(Debug)]
+struct Skip;
+
+impl Display for Skip {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("skipped")
+    }
+}
+
+impl std::error::Error for Skip {}
+
+impl ser::Error for Skip {
+    fn custom<T: Display>(_msg: T) -> Self {
+        Self
+    }
+}
+
+enum Shape {
+    Null,
+    Custom(Vec<&'static str>),
+}
+
+/// Finds out whether a value is null or a custom class before serializing it
+struct Probe;
+
+impl ser::Serializer for Probe {
+    type Ok = Shape;
+    type Error = Skip;
+
+    type SerializeSeq = Impossible<Shape, Skip>;
+    type SerializeTuple = Impossible<Shape, Skip>;
+    type SerializeTupleStruct = Impossible<Shape, Skip>;
+    type SerializeTupleVariant = Impossible<Shape, Skip>;
+    type SerializeMap = Impossible<Shape, Skip>;
+    type SerializeStruct = CustomFields;
+    type SerializeStructVariant = Impossible<Shape, Skip>;
+
+    fn serialize_none(self) -> std::result::Result<Shape, Skip> {
+        Ok(Shape::Null)
+    }
+
+    fn serialize_unit(self) -> std::result::Result<Shape, Skip> {
+        Ok(Shape::Null)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> std::result::Result<Shape, Skip> {
+        Ok(Shape::Null)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> std::result::Result<Shape, Skip> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> std::result::Result<Shape, Skip> {
+        value.serialize(self)
+    }
+
+    fn serialize_struct(
+        self,
+        name: &'static str,
+        _len: usize,
+    ) -> std::result::Result<CustomFields, Skip> {
+        if name.starts_with(CUSTOM_PREFIX) {
+            Ok(CustomFields(Vec::new()))
+        } else {
+            Err(Skip)
+        }
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> std::result::Result<Shape, Skip> {
+        Err(Skip)
+    }
+
+    reject! {
+        Skip;
+        serialize_bool(bool) -> Shape;
+        serialize_i8(i8) -> Shape;
+        serialize_i16(i16) -> Shape;
+        serialize_i32(i32) -> Shape;
+        serialize_i64(i64) -> Shape;
+        serialize_u8(u8) -> Shape;
+        serialize_u16(u16) -> Shape;
+        serialize_u32(u32) -> Shape;
+        serialize_u64(u64) -> Shape;
+        serialize_f32(f32) -> Shape;
+        serialize_f64(f64) -> Shape;
+        serialize_char(char) -> Shape;
+        serialize_str(&str) -> Shape;
+        serialize_bytes(&[u8]) -> Shape;
+        serialize_unit_variant(&'static str, u32, &'static str) -> Shape;
+        serialize_seq(Option<usize>) -> Self::SerializeSeq;
+        serialize_tuple(usize) -> Self::SerializeTuple;
+        serialize_tuple_struct(&'static str, usize) -> Self::SerializeTupleStruct;
+        serialize_tuple_variant(&'static str, u32, &'static str, usize) -> Self::SerializeTupleVariant;
+        serialize_map(Option<usize>) -> Self::SerializeMap;
+        serialize_struct_variant(&'static str, u32, &'static str, usize) -> Self::SerializeStructVariant;
+    }
+}
+
+struct CustomFields(Vec<&'static str>);
+
+impl ser::SerializeStruct for CustomFields {
+    type Ok = Shape;
+    type Error = Skip;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        _value: &T,
+    ) -> std::result::Result<(), Skip> {
+        self.0.push(key);
+        Ok(())
+    }
+
+    fn end(self) -> std::result::Result<Shape, Skip> {
+        Ok(Shape::Custom(self.0))
+    }
+}
+
+enum Key {
+    String(String),
+    Int(i32),
+    Value,
+}
+
+/// Finds out which kind of map a key belongs in
+struct KeyProbe;
+
+impl KeyProbe {
+    fn int<N: TryInto<i32>>(value: N) -> std::result::Result<Key, Skip> {
+        value.try_into().map(Key::Int).map_err(|_| Skip)
+    }
+}
+
+impl ser::Serializer for KeyProbe {
+    type Ok = Key;
+    type Error = Skip;
+
+    type SerializeSeq = Impossible<Key, Skip>;
+    type SerializeTuple = Impossible<Key, Skip>;
+    type SerializeTupleStruct = Impossible<Key, Skip>;
+    type SerializeTupleVariant = Impossible<Key, Skip>;
+    type SerializeMap = Impossible<Key, Skip>;
+    type SerializeStruct = Impossible<Key, Skip>;
+    type SerializeStructVariant = Impossible<Key, Skip>;
+
+    fn serialize_i8(self, v: i8) -> std::result::Result<Key, Skip> {
+        Self::int(v)
+    }
+
+    fn serialize_i16(self, v: i16) -> std::result::Result<Key, Skip> {
+        Self::int(v)
+    }
+
+    fn serialize_i32(self, v: i32) -> std::result::Result<Key, Skip> {
+        Self::int(v)
+    }
+
+    fn serialize_i64(self, v: i64) -> std::result::Result<Key, Skip> {
+        Self::int(v)
+    }
+
+    fn serialize_u8(self, v: u8) -> std::result::Result<Key, Skip> {
+        Self::int(v)
+    }
+
+    fn serialize_u16(self, v: u16) -> std::result::Result<Key, Skip> {
+        Self::int(v)
+    }
+
+    fn serialize_u32(self, v: u32) -> std::result::Result<Key, Skip> {
+        Self::int(v)
+    }
+
+    fn serialize_u64(self, v: u64) -> std::result::Result<Key, Skip> {
+        Self::int(v)
+    }
+
+    fn serialize_char(self, v: char) -> std::result::Result<Key, Skip> {
+        Ok(Key::String(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> std::result::Result<Key, Skip> {
+        Ok(Key::String(v.to_owned()))
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, _value: &T) -> std::result::Result<Key, Skip> {
+        Err(Skip)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> std::result::Result<Key, Skip> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> std::result::Result<Key, Skip> {
+        Err(Skip)
+    }
+
+    reject! {
+        Skip;
+        serialize_bool(bool) -> Key;
+        serialize_f32(f32) -> Key;
+        serialize_f64(f64) -> Key;
+        serialize_bytes(&[u8]) -> Key;
+        serialize_none() -> Key;
+        serialize_unit() -> Key;
+        serialize_unit_struct(&'static str) -> Key;
+        serialize_unit_variant(&'static str, u32, &'static str) -> Key;
+        serialize_seq(Option<usize>) -> Self::SerializeSeq;
+        serialize_tuple(usize) -> Self::SerializeTuple;
+        serialize_tuple_struct(&'static str, usize) -> Self::SerializeTupleStruct;
+        serialize_tuple_variant(&'static str, u32, &'static str, usize) -> Self::SerializeTupleVariant;
+        serialize_map(Option<usize>) -> Self::SerializeMap;
+        serialize_struct(&'static str, usize) -> Self::SerializeStruct;
+        serialize_struct_variant(&'static str, u32, &'static str, usize) -> Self::SerializeStructVariant;
+    }
+}
+
+/// Writes the elements of a sequence as top level values
+struct TopLevel<'a, 'o, W>(&'a mut Serializer<'o, W>);
+
+fn not_a_sequence() -> Error {
+    Error::custom("top level values must be a tuple, sequence or struct")
+}
+
+impl<'a, 'o, W: Write> ser::Serializer for TopLevel<'a, 'o, W> {
+    type Ok = ();
+    type Error = Error;
+
+    type SerializeSeq = Self;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = Self;
+    type SerializeTupleVariant = Impossible<(), Error>;
+    type SerializeMap = Impossible<(), Error>;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Impossible<(), Error>;
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self> {
+        Ok(self)
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Self> {
+        Ok(self)
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self> {
+        Ok(self)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, _value: &T) -> Result<()> {
+        Err(not_a_sequence())
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<()> {
+        Err(not_a_sequence())
+    }
+
+    reject! {
+        not_a_sequence();
+        serialize_bool(bool) -> ();
+        serialize_i8(i8) -> ();
+        serialize_i16(i16) -> ();
+        serialize_i32(i32) -> ();
+        serialize_i64(i64) -> ();
+        serialize_u8(u8) -> ();
+        serialize_u16(u16) -> ();
+        serialize_u32(u32) -> ();
+        serialize_u64(u64) -> ();
+        serialize_f32(f32) -> ();
+        serialize_f64(f64) -> ();
+        serialize_char(char) -> ();
+        serialize_str(&str) -> ();
+        serialize_bytes(&[u8]) -> ();
+        serialize_none() -> ();
+        serialize_unit() -> ();
+        serialize_unit_struct(&'static str) -> ();
+        serialize_unit_variant(&'static str, u32, &'static str) -> ();
+        serialize_tuple_variant(&'static str, u32, &'static str, usize) -> Self::SerializeTupleVariant;
+        serialize_map(Option<usize>) -> Self::SerializeMap;
+        serialize_struct_variant(&'static str, u32, &'static str, usize) -> Self::SerializeStructVariant;
+    }
+}
+
+impl<W: Write> ser::SerializeSeq for TopLevel<'_, '_, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.0.serialize_value(value)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<W: Write> ser::SerializeTuple for TopLevel<'_, '_, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.0.serialize_value(value)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<W: Write> ser::SerializeTupleStruct for TopLevel<'_, '_, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.0.serialize_value(value)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<W: Write> ser::SerializeStruct for TopLevel<'_, '_, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        _key: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        self.0.serialize_value(value)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
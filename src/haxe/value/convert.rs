@@ -0,0 +1,377 @@
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
+    ops,
+};
+
+use vecmap::VecMap as Map;
+
+use super::{
+    float::Float,
+    path::{at, Path, Segment, Segments},
+    Value,
+};
+
+/// A value wasn't the kind a conversion expected, like `expected int at
+/// [2], found string`
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+#[error("expected {expected}{}, found {found}", at(path))]
+pub struct TypeError {
+    pub expected: &'static str,
+    pub found: &'static str,
+    /// Where the mismatched value is inside the one being converted
+    pub path: Path,
+}
+
+impl TypeError {
+    fn new(expected: &'static str, found: &Value) -> Self {
+        Self {
+            expected,
+            found: found.kind(),
+            path: Path::default(),
+        }
+    }
+
+    // the same error, found inside a container at `key`
+    fn within(mut self, key: Segment) -> Self {
+        self.path.prepend(key);
+        self
+    }
+}
+
+/// Something [`Value::get`] and indexing can look up, an index or int key
+/// for `usize` and `i32`, or a field or string key for strings
+///
+/// Object maps are looked up by int or string keys the same way.
+pub trait ValueIndex: private::Sealed {
+    #[doc(hidden)]
+    fn segment(&self) -> Segment;
+}
+
+mod private {
+    pub trait Sealed {}
+    impl Sealed for usize {}
+    impl Sealed for i32 {}
+    impl Sealed for str {}
+    impl Sealed for String {}
+    impl<T: Sealed + ?Sized> Sealed for &T {}
+}
+
+impl ValueIndex for usize {
+    fn segment(&self) -> Segment {
+        Segment::Int(*self as i64)
+    }
+}
+
+impl ValueIndex for i32 {
+    fn segment(&self) -> Segment {
+        Segment::Int((*self).into())
+    }
+}
+
+impl ValueIndex for str {
+    fn segment(&self) -> Segment {
+        Segment::Str(self.to_owned())
+    }
+}
+
+impl ValueIndex for String {
+    fn segment(&self) -> Segment {
+        Segment::Str(self.clone())
+    }
+}
+
+impl<T: ValueIndex + ?Sized> ValueIndex for &T {
+    fn segment(&self) -> Segment {
+        (**self).segment()
+    }
+}
+
+impl<'a> Value<'a> {
+    /// What kind of value this is, as written in a [`TypeError`]
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Value::Null => "null",
+            Value::Bool(_) => "bool",
+            Value::Int(_) => "int",
+            Value::Float(_) => "float",
+            Value::String(_) => "string",
+            Value::Date(_) => "date",
+            Value::Bytes(_) => "bytes",
+            Value::Array(_) => "array",
+            Value::List(_) => "list",
+            Value::StringMap(_) => "string map",
+            Value::IntMap(_) => "int map",
+            Value::ObjectMap(_) => "object map",
+            Value::Struct { .. } => "struct",
+            Value::Class { .. } => "class",
+            Value::Enum { .. } | Value::EnumIndex { .. } => "enum",
+            Value::Exception(_) => "exception",
+            Value::Custom { .. } | Value::CustomFields { .. } => "custom class",
+            Value::Ref(_) => "reference",
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, Value::Null)
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_int(&self) -> Option<i32> {
+        match self {
+            Value::Int(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// A float, or an int since haxe ints are floats too
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Int(value) => Some((*value).into()),
+            Value::Float(value) => Some(value.as_f64()),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Value::Bytes(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    /// The items of an array or list
+    pub fn as_array(&self) -> Option<&Vec<Value<'a>>> {
+        match self {
+            Value::Array(items) | Value::List(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    /// The items of an array or list
+    pub fn as_array_mut(&mut self) -> Option<&mut Vec<Value<'a>>> {
+        match self {
+            Value::Array(items) | Value::List(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    /// The fields of a struct, class or custom class
+    pub fn as_fields(&self) -> Option<&Map<Cow<'a, str>, Value<'a>>> {
+        match self {
+            Value::Struct { fields }
+            | Value::Class { fields, .. }
+            | Value::CustomFields { fields, .. } => Some(fields),
+            _ => None,
+        }
+    }
+
+    /// The fields of a struct, class or custom class
+    pub fn as_fields_mut(&mut self) -> Option<&mut Map<Cow<'a, str>, Value<'a>>> {
+        match self {
+            Value::Struct { fields }
+            | Value::Class { fields, .. }
+            | Value::CustomFields { fields, .. } => Some(fields),
+            _ => None,
+        }
+    }
+
+    /// The item, field or map value at `key`, picked the same way as a one
+    /// segment [`Path`]
+    pub fn get(&self, key: impl ValueIndex) -> Option<&Value<'a>> {
+        self.get_path_from(&[key.segment()])
+    }
+
+    /// The item, field or map value at `key`, picked the same way as a one
+    /// segment [`Path`]
+    pub fn get_mut(&mut self, key: impl ValueIndex) -> Option<&mut Value<'a>> {
+        self.get_path_mut_from(&[key.segment()])
+    }
+}
+
+static NULL: Value<'static> = Value::Null;
+
+/// [`Value::get`], or null if there's nothing at `key`
+impl<'a, I: ValueIndex> ops::Index<I> for Value<'a> {
+    type Output = Value<'a>;
+
+    fn index(&self, key: I) -> &Value<'a> {
+        self.get(key).unwrap_or(&NULL)
+    }
+}
+
+/// [`Value::get_mut`], panicking if there's nothing at `key`
+impl<I: ValueIndex> ops::IndexMut<I> for Value<'_> {
+    fn index_mut(&mut self, key: I) -> &mut Self {
+        let segment = key.segment();
+        let kind = self.kind();
+        match self.get_path_mut_from(std::slice::from_ref(&segment)) {
+            Some(value) => value,
+            None => panic!("no {} in {kind}", Segments(&[segment])),
+        }
+    }
+}
+
+impl From<bool> for Value<'_> {
+    fn from(value: bool) -> Self {
+        Value::Bool(value)
+    }
+}
+
+impl From<i32> for Value<'_> {
+    fn from(value: i32) -> Self {
+        Value::Int(value)
+    }
+}
+
+impl From<f64> for Value<'_> {
+    fn from(value: f64) -> Self {
+        Value::Float(Float::new(value))
+    }
+}
+
+impl<'a> From<&'a str> for Value<'a> {
+    fn from(value: &'a str) -> Self {
+        Value::String(Cow::Borrowed(value))
+    }
+}
+
+impl From<String> for Value<'_> {
+    fn from(value: String) -> Self {
+        Value::String(Cow::Owned(value))
+    }
+}
+
+impl<'a> From<Cow<'a, str>> for Value<'a> {
+    fn from(value: Cow<'a, str>) -> Self {
+        Value::String(value)
+    }
+}
+
+impl<'a, T: Into<Value<'a>>> From<Vec<T>> for Value<'a> {
+    fn from(items: Vec<T>) -> Self {
+        Value::Array(items.into_iter().map(Into::into).collect())
+    }
+}
+
+/// A string map, sorted by key so it always encodes the same way
+impl<'a, T: Into<Value<'a>>> From<HashMap<String, T>> for Value<'a> {
+    fn from(map: HashMap<String, T>) -> Self {
+        let mut entries: Vec<_> = map.into_iter().collect();
+        entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Value::StringMap(
+            entries
+                .into_iter()
+                .map(|(key, value)| (Cow::Owned(key), value.into()))
+                .collect(),
+        )
+    }
+}
+
+impl<'a, T: Into<Value<'a>>> From<BTreeMap<i32, T>> for Value<'a> {
+    fn from(map: BTreeMap<i32, T>) -> Self {
+        Value::IntMap(
+            map.into_iter()
+                .map(|(key, value)| (key, value.into()))
+                .collect(),
+        )
+    }
+}
+
+// scalars convert from both borrowed and owned values
+macro_rules! try_from_scalar {
+    ($($ty:ty: $expected:literal => $convert:expr,)*) => {$(
+        impl TryFrom<&Value<'_>> for $ty {
+            type Error = TypeError;
+
+            fn try_from(value: &Value<'_>) -> Result<Self, TypeError> {
+                let convert: fn(&Value<'_>) -> Option<$ty> = $convert;
+                convert(value).ok_or_else(|| TypeError::new($expected, value))
+            }
+        }
+
+        impl TryFrom<Value<'_>> for $ty {
+            type Error = TypeError;
+
+            fn try_from(value: Value<'_>) -> Result<Self, TypeError> {
+                Self::try_from(&value)
+            }
+        }
+    )*};
+}
+
+try_from_scalar! {
+    bool: "bool" => |value| value.as_bool(),
+    i32: "int" => |value| value.as_int(),
+    i64: "int" => |value| value.as_int().map(Into::into),
+    f64: "float" => |value| value.as_f64(),
+    String: "string" => |value| value.as_str().map(str::to_owned),
+}
+
+/// The items of an array or list
+impl<'a, T: TryFrom<Value<'a>, Error = TypeError>> TryFrom<Value<'a>> for Vec<T> {
+    type Error = TypeError;
+
+    fn try_from(value: Value<'a>) -> Result<Self, TypeError> {
+        let (Value::Array(items) | Value::List(items)) = value else {
+            return Err(TypeError::new("array", &value));
+        };
+        items
+            .into_iter()
+            .enumerate()
+            .map(|(index, item)| T::try_from(item).map_err(|err| err.within(index.segment())))
+            .collect()
+    }
+}
+
+/// The entries of a string map, or the fields of a struct, class or custom
+/// class
+impl<'a, T: TryFrom<Value<'a>, Error = TypeError>> TryFrom<Value<'a>> for HashMap<String, T> {
+    type Error = TypeError;
+
+    fn try_from(value: Value<'a>) -> Result<Self, TypeError> {
+        let (Value::StringMap(fields)
+        | Value::Struct { fields }
+        | Value::Class { fields, .. }
+        | Value::CustomFields { fields, .. }) = value
+        else {
+            return Err(TypeError::new("string map", &value));
+        };
+        fields
+            .into_iter()
+            .map(|(key, value)| match T::try_from(value) {
+                Ok(value) => Ok((key.into_owned(), value)),
+                Err(err) => Err(err.within(Segment::Name(key.into_owned()))),
+            })
+            .collect()
+    }
+}
+
+impl<'a, T: TryFrom<Value<'a>, Error = TypeError>> TryFrom<Value<'a>> for BTreeMap<i32, T> {
+    type Error = TypeError;
+
+    fn try_from(value: Value<'a>) -> Result<Self, TypeError> {
+        let Value::IntMap(map) = value else {
+            return Err(TypeError::new("int map", &value));
+        };
+        map.into_iter()
+            .map(|(key, value)| match T::try_from(value) {
+                Ok(value) => Ok((key, value)),
+                Err(err) => Err(err.within(key.segment())),
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,314 @@
+use std::{fmt, str::FromStr};
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use super::float::Float;
+
+/// A serialized `Date`, in whichever of the two forms haxe writes them so it's
+/// written back the same way
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HaxeDate {
+    /// `YYYY-MM-DD hh:mm:ss`, written by haxe 3 in the local time of whoever
+    /// saved it
+    Local(LocalDate),
+
+    /// Milliseconds since the unix epoch, written by haxe 4
+    Timestamp(Float),
+}
+
+/// The calendar date and time of a [`HaxeDate::Local`], which doesn't say
+/// what time zone it's in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct LocalDate {
+    year: u16,
+    month: u8,
+    day: u8,
+    hour: u8,
+    minute: u8,
+    second: u8,
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum DateError {
+    #[error("expected `YYYY-MM-DD hh:mm:ss` or milliseconds since the unix epoch")]
+    Syntax,
+
+    #[error("{field} {value} is out of range")]
+    OutOfRange { field: &'static str, value: i64 },
+
+    #[error("timestamp isn't a finite number")]
+    NotFinite,
+}
+
+const MILLIS_PER_SECOND: f64 = 1000.0;
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+impl HaxeDate {
+    /// A date in the haxe 4 form
+    pub fn from_unix_millis(millis: f64) -> Result<Self, DateError> {
+        if !millis.is_finite() {
+            return Err(DateError::NotFinite);
+        }
+        Ok(Self::Timestamp(Float::new(millis)))
+    }
+
+    /// Milliseconds since the unix epoch, local dates are taken to be in UTC
+    pub fn unix_millis(&self) -> f64 {
+        match self {
+            Self::Local(date) => date.unix() as f64 * MILLIS_PER_SECOND,
+            Self::Timestamp(millis) => millis.as_f64(),
+        }
+    }
+
+    /// Another date in the same form as this one, local dates drop any
+    /// fraction of a second
+    pub fn with_unix_millis(&self, millis: f64) -> Result<Self, DateError> {
+        match self {
+            Self::Local(_) => {
+                if !millis.is_finite() {
+                    return Err(DateError::NotFinite);
+                }
+                let seconds = (millis / MILLIS_PER_SECOND).floor() as i64;
+                LocalDate::from_unix(seconds).map(Self::Local)
+            }
+            Self::Timestamp(_) => Self::from_unix_millis(millis),
+        }
+    }
+}
+
+impl fmt::Display for HaxeDate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Local(date) => date.fmt(f),
+            Self::Timestamp(millis) => write!(f, "{}", millis.as_f64()),
+        }
+    }
+}
+
+impl FromStr for HaxeDate {
+    type Err = DateError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if is_local_form(s.as_bytes()) {
+            s.parse().map(Self::Local)
+        } else if !s.is_empty() && s.bytes().all(is_timestamp_byte) {
+            let millis = s.parse().map_err(|_| DateError::Syntax)?;
+            Self::from_unix_millis(millis)
+        } else {
+            Err(DateError::Syntax)
+        }
+    }
+}
+
+impl Serialize for HaxeDate {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Local(date) => serializer.collect_str(date),
+            Self::Timestamp(millis) => serializer.serialize_f64(millis.as_f64()),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for HaxeDate {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl de::Visitor<'_> for Visitor {
+            type Value = HaxeDate;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a `YYYY-MM-DD hh:mm:ss` string or a unix timestamp in milliseconds")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                v.parse().map_err(E::custom)
+            }
+
+            fn visit_f64<E: de::Error>(self, v: f64) -> Result<Self::Value, E> {
+                HaxeDate::from_unix_millis(v).map_err(E::custom)
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+                self.visit_f64(v as f64)
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+                self.visit_f64(v as f64)
+            }
+        }
+
+        deserializer.deserialize_any(Visitor)
+    }
+}
+
+impl LocalDate {
+    pub fn new(
+        year: u16,
+        month: u8,
+        day: u8,
+        hour: u8,
+        minute: u8,
+        second: u8,
+    ) -> Result<Self, DateError> {
+        let check = |field, value: u16, range: std::ops::RangeInclusive<u16>| {
+            if range.contains(&value) {
+                Ok(())
+            } else {
+                Err(DateError::OutOfRange {
+                    field,
+                    value: value.into(),
+                })
+            }
+        };
+        check("year", year, 0..=9999)?;
+        check("month", month.into(), 1..=12)?;
+        check("day", day.into(), 1..=days_in_month(year, month).into())?;
+        check("hour", hour.into(), 0..=23)?;
+        check("minute", minute.into(), 0..=59)?;
+        check("second", second.into(), 0..=59)?;
+
+        Ok(Self {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+        })
+    }
+
+    /// The date `seconds` after the unix epoch in UTC
+    pub fn from_unix(seconds: i64) -> Result<Self, DateError> {
+        let out_of_range = || DateError::OutOfRange {
+            field: "timestamp",
+            value: seconds,
+        };
+
+        let (year, month, day) = civil_from_days(seconds.div_euclid(SECONDS_PER_DAY));
+        let time = seconds.rem_euclid(SECONDS_PER_DAY);
+        let year = u16::try_from(year).map_err(|_| out_of_range())?;
+        // both always fit, they're at most 12 and 31
+        let (month, day) = (month as u8, day as u8);
+        let (hour, minute, second) = (time / 3600, time / 60 % 60, time % 60);
+
+        Self::new(year, month, day, hour as u8, minute as u8, second as u8)
+            .map_err(|_| out_of_range())
+    }
+
+    /// Seconds since the unix epoch, taking this date to be in UTC
+    pub fn unix(&self) -> i64 {
+        let days = days_from_civil(self.year.into(), self.month.into(), self.day.into());
+        let time = i64::from(self.hour) * 3600 + i64::from(self.minute) * 60;
+        days * SECONDS_PER_DAY + time + i64::from(self.second)
+    }
+
+    pub fn year(&self) -> u16 {
+        self.year
+    }
+
+    pub fn month(&self) -> u8 {
+        self.month
+    }
+
+    pub fn day(&self) -> u8 {
+        self.day
+    }
+
+    pub fn hour(&self) -> u8 {
+        self.hour
+    }
+
+    pub fn minute(&self) -> u8 {
+        self.minute
+    }
+
+    pub fn second(&self) -> u8 {
+        self.second
+    }
+}
+
+impl fmt::Display for LocalDate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+impl FromStr for LocalDate {
+    type Err = DateError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = s.as_bytes();
+        let separators = [(4, b'-'), (7, b'-'), (10, b' '), (13, b':'), (16, b':')];
+        if bytes.len() != 19 || separators.iter().any(|&(i, c)| bytes[i] != c) {
+            return Err(DateError::Syntax);
+        }
+
+        let number = |start: usize, len: usize| {
+            let digits = &s[start..start + len];
+            if !digits.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(DateError::Syntax);
+            }
+            digits.parse::<u16>().map_err(|_| DateError::Syntax)
+        };
+        // two digit numbers always fit in a u8
+        let small = |start| number(start, 2).map(|n| n as u8);
+
+        Self::new(
+            number(0, 4)?,
+            small(5)?,
+            small(8)?,
+            small(11)?,
+            small(14)?,
+            small(17)?,
+        )
+    }
+}
+
+// Haxe's `Unserializer` tells the forms apart the same way
+pub(crate) fn is_local_form(bytes: &[u8]) -> bool {
+    bytes.len() >= 5 && bytes[0].is_ascii_digit() && bytes[4] == b'-'
+}
+
+pub(crate) fn is_timestamp_byte(byte: u8) -> bool {
+    byte.is_ascii_digit() || matches!(byte, b'-' | b'+' | b'.' | b'e' | b'E')
+}
+
+fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        2 if year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400)) => {
+            29
+        }
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// https://howardhinnant.github.io/date_algorithms.html#days_from_civil
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+// https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400;
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
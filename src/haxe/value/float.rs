@@ -1,7 +1,9 @@
+use std::hash::{Hash, Hasher};
+
 use ordered_float::OrderedFloat;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Eq, PartialEq, Hash)]
 pub enum Float {
     Nan,
     PositiveInfinity,
@@ -34,9 +36,25 @@ impl Float {
 }
 
 // new type is required for privacy
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct Normal(OrderedFloat<f64>);
 
+// compared by bits so -0.0 and 0.0 stay different values, `Normal` is never
+// NaN so that's otherwise the same as comparing the floats
+impl PartialEq for Normal {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_f64().to_bits() == other.as_f64().to_bits()
+    }
+}
+
+impl Eq for Normal {}
+
+impl Hash for Normal {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_f64().to_bits().hash(state);
+    }
+}
+
 impl Normal {
     pub fn new(value: f64) -> Self {
         Self(OrderedFloat(value))
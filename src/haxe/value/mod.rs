@@ -1,6 +1,13 @@
+pub mod convert;
+pub mod date;
 pub mod float;
+pub mod path;
 
-use std::{borrow::Cow, fmt::Debug};
+use std::{
+    borrow::Cow,
+    fmt::Debug,
+    hash::{DefaultHasher, Hash, Hasher},
+};
 
 use serde::{Deserialize, Serialize};
 use vecmap::VecMap as Map;
@@ -14,14 +21,18 @@ pub enum Value<'a> {
     Float(float::Float),
 
     String(Cow<'a, str>),
-    Date(Cow<'a, str>),
+    Date(date::HaxeDate),
     Bytes(Vec<u8>),
 
     Array(Vec<Value<'a>>),
     List(Vec<Value<'a>>),
 
     StringMap(Map<Cow<'a, str>, Value<'a>>),
+    // toml only allows string keys, so write the keys as strings like json does
+    #[serde(with = "int_map_keys")]
     IntMap(Map<i32, Value<'a>>),
+    // json only allows string keys, so serialize the entries as a list of pairs
+    #[serde(with = "object_map_entries")]
     ObjectMap(Map<Value<'a>, Value<'a>>),
 
     Struct {
@@ -39,11 +50,233 @@ pub enum Value<'a> {
         fields: Vec<Value<'a>>,
     },
 
+    /// An enum serialized with `useEnumIndex`, so only the constructor's index is known
+    EnumIndex {
+        name: Cow<'a, str>,
+        index: usize,
+        fields: Vec<Value<'a>>,
+    },
+
     Exception(Box<Value<'a>>),
+
+    /// A custom class (`C`) with the values its `hxSerialize` wrote, when no
+    /// `CustomCodec` could name them
+    #[serde(rename = "CustomRaw")]
     Custom {
+        name: Cow<'a, str>,
+        raw: Vec<Value<'a>>,
+    },
+
+    /// A custom class (`C`) whose values were named by its `CustomCodec`
+    // exported as `Custom` like before there were codecs, so older exports
+    // still load
+    #[serde(rename = "Custom", alias = "CustomFields")]
+    CustomFields {
         name: Cow<'a, str>,
         fields: Map<Cow<'a, str>, Value<'a>>,
     },
+
+    /// A reference to an earlier object by its index in haxe's object cache,
+    /// only produced when decoding with `Unserializer::keep_references`
+    Ref(usize),
+}
+
+impl Value<'_> {
+    /// This value with every string moved out of the input it was decoded
+    /// from, so it can outlive it
+    pub fn into_owned(self) -> Value<'static> {
+        match self {
+            Value::Null => Value::Null,
+            Value::Bool(value) => Value::Bool(value),
+            Value::Int(value) => Value::Int(value),
+            Value::Float(value) => Value::Float(value),
+            Value::String(value) => Value::String(owned_str(value)),
+            Value::Date(value) => Value::Date(value),
+            Value::Bytes(bytes) => Value::Bytes(bytes),
+            Value::Array(value) => Value::Array(owned_values(value)),
+            Value::List(value) => Value::List(owned_values(value)),
+            Value::StringMap(value) => Value::StringMap(owned_fields(value)),
+            Value::IntMap(value) => Value::IntMap(
+                value
+                    .into_iter()
+                    .map(|(key, value)| (key, value.into_owned()))
+                    .collect(),
+            ),
+            Value::ObjectMap(value) => Value::ObjectMap(
+                value
+                    .into_iter()
+                    .map(|(key, value)| (key.into_owned(), value.into_owned()))
+                    .collect(),
+            ),
+            Value::Struct { fields } => Value::Struct {
+                fields: owned_fields(fields),
+            },
+            Value::Class { name, fields } => Value::Class {
+                name: owned_str(name),
+                fields: owned_fields(fields),
+            },
+            Value::Enum {
+                name,
+                constructor,
+                fields,
+            } => Value::Enum {
+                name: owned_str(name),
+                constructor: owned_str(constructor),
+                fields: owned_values(fields),
+            },
+            Value::EnumIndex {
+                name,
+                index,
+                fields,
+            } => Value::EnumIndex {
+                name: owned_str(name),
+                index,
+                fields: owned_values(fields),
+            },
+            Value::Exception(value) => Value::Exception(Box::new(value.into_owned())),
+            Value::Custom { name, raw } => Value::Custom {
+                name: owned_str(name),
+                raw: owned_values(raw),
+            },
+            Value::CustomFields { name, fields } => Value::CustomFields {
+                name: owned_str(name),
+                fields: owned_fields(fields),
+            },
+            Value::Ref(index) => Value::Ref(index),
+        }
+    }
+
+    /// A copy of this value that doesn't borrow from the input it was decoded
+    /// from
+    pub fn to_owned(&self) -> Value<'static> {
+        self.clone().into_owned()
+    }
+}
+
+fn owned_str(s: Cow<'_, str>) -> Cow<'static, str> {
+    Cow::Owned(s.into_owned())
+}
+
+fn owned_values(values: Vec<Value<'_>>) -> Vec<Value<'static>> {
+    values.into_iter().map(Value::into_owned).collect()
+}
+
+fn owned_fields(fields: Map<Cow<'_, str>, Value<'_>>) -> Map<Cow<'static, str>, Value<'static>> {
+    fields
+        .into_iter()
+        .map(|(key, value)| (owned_str(key), value.into_owned()))
+        .collect()
+}
+
+mod object_map_entries {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use super::{Map, Value};
+
+    pub fn serialize<S: Serializer>(
+        map: &Map<Value<'_>, Value<'_>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(map.iter())
+    }
+
+    pub fn deserialize<'de, 'a, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Map<Value<'a>, Value<'a>>, D::Error> {
+        Vec::<(Value<'a>, Value<'a>)>::deserialize(deserializer).map(Map::from_iter)
+    }
+}
+
+mod int_map_keys {
+    use serde::{
+        de::{Error, Unexpected},
+        Deserialize, Deserializer, Serializer,
+    };
+
+    use super::{Map, Value};
+
+    pub fn serialize<S: Serializer>(
+        map: &Map<i32, Value<'_>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(map.iter().map(|(key, value)| (key.to_string(), value)))
+    }
+
+    pub fn deserialize<'de, 'a, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Map<i32, Value<'a>>, D::Error> {
+        Map::<String, Value<'a>>::deserialize(deserializer)?
+            .into_iter()
+            .map(|(key, value)| match key.parse() {
+                Ok(key) => Ok((key, value)),
+                Err(_) => Err(D::Error::invalid_value(
+                    Unexpected::Str(&key),
+                    &"an int key",
+                )),
+            })
+            .collect()
+    }
+}
+
+impl Hash for Value<'_> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Value::Null => {}
+            Value::Bool(value) => value.hash(state),
+            Value::Int(value) => value.hash(state),
+            Value::Float(value) => value.hash(state),
+            Value::String(value) => value.hash(state),
+            Value::Date(value) => value.hash(state),
+            Value::Bytes(bytes) => bytes.hash(state),
+            Value::Array(value) | Value::List(value) => value.hash(state),
+            Value::StringMap(value) => hash_map(value, state),
+            Value::IntMap(value) => hash_map(value, state),
+            Value::ObjectMap(value) => hash_map(value, state),
+            Value::Struct { fields } => hash_map(fields, state),
+            Value::Class { name, fields } | Value::CustomFields { name, fields } => {
+                name.hash(state);
+                hash_map(fields, state);
+            }
+            Value::Custom { name, raw } => {
+                name.hash(state);
+                raw.hash(state);
+            }
+            Value::Enum {
+                name,
+                constructor,
+                fields,
+            } => {
+                name.hash(state);
+                constructor.hash(state);
+                fields.hash(state);
+            }
+            Value::EnumIndex {
+                name,
+                index,
+                fields,
+            } => {
+                name.hash(state);
+                index.hash(state);
+                fields.hash(state);
+            }
+            Value::Exception(value) => value.hash(state),
+            Value::Ref(index) => index.hash(state),
+        }
+    }
+}
+
+// maps compare equal regardless of entry order, so their hash can't depend on it
+fn hash_map<K: Hash, V: Hash, H: Hasher>(map: &Map<K, V>, state: &mut H) {
+    map.len().hash(state);
+    map.iter()
+        .map(|entry| {
+            let mut hasher = DefaultHasher::new();
+            entry.hash(&mut hasher);
+            hasher.finish()
+        })
+        .fold(0_u64, u64::wrapping_add)
+        .hash(state);
 }
 
 impl Debug for Value<'_> {
@@ -53,7 +286,8 @@ impl Debug for Value<'_> {
             Value::Bool(value) => write!(f, "{value:?}"),
             Value::Int(value) => write!(f, "{value:?}"),
             Value::Float(value) => write!(f, "{:?}", value.as_f64()),
-            Value::String(value) | Value::Date(value) => write!(f, "{value:?}"),
+            Value::String(value) => write!(f, "{value:?}"),
+            Value::Date(value) => write!(f, "Date({value})"),
             Value::Bytes(bytes) => write!(f, "{bytes:?}"),
             Value::Array(value) | Value::List(value) => {
                 f.debug_list().entries(value.iter()).finish()
@@ -69,7 +303,11 @@ impl Debug for Value<'_> {
                 }
                 f.finish()
             }
-            Value::Class { name, fields } | Value::Custom { name, fields } => {
+            Value::Custom { name, raw } => {
+                write!(f, "custom {name} ")?;
+                f.debug_list().entries(raw.iter()).finish()
+            }
+            Value::Class { name, fields } | Value::CustomFields { name, fields } => {
                 f.write_str("class ")?;
                 let mut f = f.debug_struct(name);
                 for (field, value) in fields {
@@ -88,7 +326,19 @@ impl Debug for Value<'_> {
                 }
                 f.finish()
             }
+            Value::EnumIndex {
+                name,
+                index,
+                fields,
+            } => {
+                let mut f = f.debug_tuple(format!("{name}.{index}").as_str());
+                for field in fields {
+                    f.field(field);
+                }
+                f.finish()
+            }
             Value::Exception(value) => write!(f, "{value:?}"),
+            Value::Ref(index) => f.debug_tuple("Ref").field(index).finish(),
         }
     }
 }
@@ -0,0 +1,364 @@
+use std::{borrow::Cow, fmt, str::FromStr};
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use super::Value;
+use crate::haxe::{from_str_owned, to_string};
+
+/// Where to find values nested inside another, like
+/// `player.inventory[3].count`
+///
+/// A path is a list of segments, each written as `.name` (the first needn't
+/// have the dot) or in brackets:
+///
+/// - `name` picks a field or string key, or an index or int key if it's a
+///   number
+/// - `[3]` picks an array, list, enum or custom class item, or an int key
+/// - `["name"]` picks a field or string key, with `\"` and `\\` escapes
+/// - `[=oy1:xzg]` picks an object map key, written serialized
+/// - `*` or `[*]` picks every item, field or map value
+///
+/// Object map keys that are ints or strings can also be picked with `[3]`
+/// and `["name"]`. The empty path picks the value itself.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Path {
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    /// `.name`
+    Name(String),
+    /// `[3]`
+    Int(i64),
+    /// `["name"]`
+    Str(String),
+    /// `[=oy1:xzg]`
+    Object(Value<'static>),
+    /// `*` or `[*]`
+    Wildcard,
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum PathError {
+    #[error("expected a name or `[` at byte {offset}")]
+    Syntax { offset: usize },
+
+    #[error("`[` at byte {offset} is never closed")]
+    Unclosed { offset: usize },
+
+    #[error("invalid key at byte {offset}")]
+    InvalidKey { offset: usize },
+}
+
+impl Path {
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    pub(crate) fn prepend(&mut self, segment: Segment) {
+        self.segments.insert(0, segment);
+    }
+}
+
+impl From<Vec<Segment>> for Path {
+    fn from(segments: Vec<Segment>) -> Self {
+        Self { segments }
+    }
+}
+
+impl FromStr for Path {
+    type Err = PathError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut segments = Vec::new();
+        let mut rest = s;
+        while !rest.is_empty() {
+            let offset = s.len() - rest.len();
+            if let Some(key) = rest.strip_prefix('[') {
+                let (segment, len) = parse_key(key, offset)?;
+                segments.push(segment);
+                rest = &key[len..];
+                continue;
+            }
+
+            let name = match rest.strip_prefix('.') {
+                Some(name) => name,
+                None if segments.is_empty() => rest,
+                None => return Err(PathError::Syntax { offset }),
+            };
+            let len = name.find(['.', '[', ']']).unwrap_or(name.len());
+            let segment = match &name[..len] {
+                "" => {
+                    return Err(PathError::Syntax {
+                        offset: s.len() - name.len(),
+                    })
+                }
+                "*" => Segment::Wildcard,
+                name => Segment::Name(name.to_owned()),
+            };
+            segments.push(segment);
+            rest = &name[len..];
+        }
+
+        Ok(Self { segments })
+    }
+}
+
+// parses a bracketed key after its `[`, returning it and how much of `s` it
+// took up including the `]`
+fn parse_key(s: &str, offset: usize) -> Result<(Segment, usize), PathError> {
+    let unclosed = PathError::Unclosed { offset };
+    let invalid = PathError::InvalidKey { offset: offset + 1 };
+
+    if let Some(quoted) = s.strip_prefix('"') {
+        let mut key = String::new();
+        let mut chars = quoted.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    let end = 1 + i + 1;
+                    if !s[end..].starts_with(']') {
+                        return Err(unclosed);
+                    }
+                    return Ok((Segment::Str(key), end + 1));
+                }
+                '\\' => match chars.next() {
+                    Some((_, c @ ('"' | '\\'))) => key.push(c),
+                    _ => return Err(invalid),
+                },
+                c => key.push(c),
+            }
+        }
+        return Err(unclosed);
+    }
+
+    // serialized strings percent encode `]`, so it can only close the key
+    let len = s.find(']').ok_or(unclosed)?;
+    let segment = match &s[..len] {
+        "*" => Segment::Wildcard,
+        key => match key.strip_prefix('=') {
+            Some(key) => {
+                let mut values = from_str_owned(key).map_err(|_| invalid.clone())?;
+                match (values.pop(), values.is_empty()) {
+                    (Some(key), true) => Segment::Object(key),
+                    _ => return Err(invalid),
+                }
+            }
+            None => Segment::Int(key.parse().map_err(|_| invalid)?),
+        },
+    };
+    Ok((segment, len + 1))
+}
+
+impl Serialize for Path {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Path {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let path = Cow::<str>::deserialize(deserializer)?;
+        path.parse().map_err(de::Error::custom)
+    }
+}
+
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Segments(&self.segments).fmt(f)
+    }
+}
+
+// displays segments like a `Path` without needing to own them
+pub(crate) struct Segments<'s>(pub(crate) &'s [Segment]);
+
+impl fmt::Display for Segments<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, segment) in self.0.iter().enumerate() {
+            match segment {
+                // names that wouldn't read back as one are quoted
+                Segment::Name(name)
+                    if name.is_empty() || name == "*" || name.contains(['.', '[', ']']) =>
+                {
+                    write_quoted(f, name)?;
+                }
+                Segment::Name(name) if i == 0 => f.write_str(name)?,
+                Segment::Name(name) => write!(f, ".{name}")?,
+                Segment::Int(index) => write!(f, "[{index}]")?,
+                Segment::Str(key) => write_quoted(f, key)?,
+                Segment::Object(key) => write!(f, "[={}]", to_string(std::slice::from_ref(key)))?,
+                Segment::Wildcard => f.write_str("[*]")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+// ` at path` for errors about a value nested in another, or nothing for the
+// value itself
+pub(crate) fn at(path: &Path) -> String {
+    match path.segments() {
+        [] => String::new(),
+        segments => format!(" at {}", Segments(segments)),
+    }
+}
+
+fn write_quoted(f: &mut fmt::Formatter<'_>, key: &str) -> fmt::Result {
+    let key = key.replace('\\', "\\\\").replace('"', "\\\"");
+    write!(f, "[\"{key}\"]")
+}
+
+impl<'a> Value<'a> {
+    /// The first value at `path`
+    pub fn get_path(&self, path: &Path) -> Option<&Value<'a>> {
+        self.get_path_from(&path.segments)
+    }
+
+    /// The first value at `path`
+    pub fn get_path_mut(&mut self, path: &Path) -> Option<&mut Value<'a>> {
+        self.get_path_mut_from(&path.segments)
+    }
+
+    /// Every value at `path`, in the order they're stored
+    pub fn select_all(&self, path: &Path) -> Vec<&Value<'a>> {
+        let mut selected = vec![self];
+        for segment in &path.segments {
+            selected = selected
+                .into_iter()
+                .flat_map(|value| children(value, segment))
+                .collect();
+        }
+        selected
+    }
+
+    pub(crate) fn get_path_from(&self, segments: &[Segment]) -> Option<&Value<'a>> {
+        let Some((segment, rest)) = segments.split_first() else {
+            return Some(self);
+        };
+        children(self, segment)
+            .into_iter()
+            .find_map(|child| child.get_path_from(rest))
+    }
+
+    pub(crate) fn get_path_mut_from(&mut self, segments: &[Segment]) -> Option<&mut Value<'a>> {
+        let Some((segment, rest)) = segments.split_first() else {
+            return Some(self);
+        };
+        children_mut(self, segment)
+            .into_iter()
+            .find_map(|child| child.get_path_mut_from(rest))
+    }
+}
+
+// the keys and indices a segment can stand for
+pub(crate) struct Keys<'s> {
+    pub(crate) index: Option<usize>,
+    pub(crate) int: Option<i32>,
+    pub(crate) string: Option<&'s str>,
+    object: Option<&'s Value<'static>>,
+}
+
+impl<'s> Keys<'s> {
+    pub(crate) fn new(segment: &'s Segment) -> Self {
+        let (int, string) = match segment {
+            Segment::Name(name) => (name.parse().ok(), Some(name.as_str())),
+            Segment::Int(int) => (Some(*int), None),
+            Segment::Str(key) => (None, Some(key.as_str())),
+            Segment::Object(_) | Segment::Wildcard => (None, None),
+        };
+
+        Self {
+            index: int.and_then(|int: i64| usize::try_from(int).ok()),
+            int: int.and_then(|int| i32::try_from(int).ok()),
+            string,
+            object: match segment {
+                Segment::Object(key) => Some(key),
+                _ => None,
+            },
+        }
+    }
+
+    // object map keys are matched by value, ints and strings included
+    pub(crate) fn objects(&self) -> impl Iterator<Item = Value<'static>> {
+        let by_string = self.string.map(|key| Value::String(key.to_owned().into()));
+        [self.object.cloned(), by_string, self.int.map(Value::Int)]
+            .into_iter()
+            .flatten()
+    }
+}
+
+fn children<'v, 'a>(value: &'v Value<'a>, segment: &Segment) -> Vec<&'v Value<'a>> {
+    if let Segment::Wildcard = segment {
+        return match value {
+            Value::Array(items)
+            | Value::List(items)
+            | Value::Enum { fields: items, .. }
+            | Value::EnumIndex { fields: items, .. }
+            | Value::Custom { raw: items, .. } => items.iter().collect(),
+            Value::StringMap(fields)
+            | Value::Struct { fields }
+            | Value::Class { fields, .. }
+            | Value::CustomFields { fields, .. } => fields.values().collect(),
+            Value::IntMap(map) => map.values().collect(),
+            Value::ObjectMap(map) => map.values().collect(),
+            _ => Vec::new(),
+        };
+    }
+
+    let keys = Keys::new(segment);
+    let child = match value {
+        Value::Array(items)
+        | Value::List(items)
+        | Value::Enum { fields: items, .. }
+        | Value::EnumIndex { fields: items, .. }
+        | Value::Custom { raw: items, .. } => keys.index.and_then(|index| items.get(index)),
+        Value::StringMap(fields)
+        | Value::Struct { fields }
+        | Value::Class { fields, .. }
+        | Value::CustomFields { fields, .. } => keys.string.and_then(|key| fields.get(key)),
+        Value::IntMap(map) => keys.int.and_then(|key| map.get(&key)),
+        Value::ObjectMap(map) => keys.objects().find_map(|key| map.get(&key)),
+        _ => None,
+    };
+    child.into_iter().collect()
+}
+
+fn children_mut<'v, 'a>(value: &'v mut Value<'a>, segment: &Segment) -> Vec<&'v mut Value<'a>> {
+    if let Segment::Wildcard = segment {
+        return match value {
+            Value::Array(items)
+            | Value::List(items)
+            | Value::Enum { fields: items, .. }
+            | Value::EnumIndex { fields: items, .. }
+            | Value::Custom { raw: items, .. } => items.iter_mut().collect(),
+            Value::StringMap(fields)
+            | Value::Struct { fields }
+            | Value::Class { fields, .. }
+            | Value::CustomFields { fields, .. } => fields.values_mut().collect(),
+            Value::IntMap(map) => map.values_mut().collect(),
+            Value::ObjectMap(map) => map.values_mut().collect(),
+            _ => Vec::new(),
+        };
+    }
+
+    let keys = Keys::new(segment);
+    let child = match value {
+        Value::Array(items)
+        | Value::List(items)
+        | Value::Enum { fields: items, .. }
+        | Value::EnumIndex { fields: items, .. }
+        | Value::Custom { raw: items, .. } => keys.index.and_then(|index| items.get_mut(index)),
+        Value::StringMap(fields)
+        | Value::Struct { fields }
+        | Value::Class { fields, .. }
+        | Value::CustomFields { fields, .. } => keys.string.and_then(|key| fields.get_mut(key)),
+        Value::IntMap(map) => keys.int.and_then(|key| map.get_mut(&key)),
+        Value::ObjectMap(map) => {
+            let key = keys.objects().find(|key| map.contains_key(key));
+            key.and_then(|key| map.get_mut(&key))
+        }
+        _ => None,
+    };
+    child.into_iter().collect()
+}
@@ -0,0 +1,369 @@
+use std::borrow::Cow;
+
+use vecmap::VecMap as Map;
+
+use super::{
+    custom,
+    value::{date::HaxeDate, float::Float, Value},
+};
+
+/// Callbacks for each part of haxe serialized data as it's parsed, see
+/// [`visit`](super::visit)
+///
+/// Nothing is resolved for string values and objects, so cache references
+/// show up as [`Visitor::string_ref`] and [`Visitor::object_ref`]. Field
+/// names, keys and the names of classes and enums are always resolved.
+/// Strings borrow from the input unless they had to be percent decoded.
+///
+/// Every method does nothing by default.
+#[allow(unused_variables)]
+pub trait Visitor<'a> {
+    fn visit_null(&mut self) {}
+
+    fn visit_bool(&mut self, value: bool) {}
+
+    fn visit_int(&mut self, value: i32) {}
+
+    fn visit_float(&mut self, value: Float) {}
+
+    /// A string literal, which is added to the string cache
+    fn visit_string(&mut self, value: Cow<'a, str>) {}
+
+    /// `R<index>`, a reference to a string literal read earlier
+    fn string_ref(&mut self, index: usize, value: Cow<'a, str>) {}
+
+    fn visit_date(&mut self, value: HaxeDate) {}
+
+    fn visit_bytes(&mut self, value: Vec<u8>) {}
+
+    /// `r<index>`, a reference to an object read earlier
+    fn object_ref(&mut self, index: usize) {}
+
+    fn begin_array(&mut self) {}
+
+    /// `u<count>`, a run of nulls in an array
+    fn null_run(&mut self, count: usize) {}
+
+    fn end_array(&mut self) {}
+
+    fn begin_list(&mut self) {}
+
+    fn end_list(&mut self) {}
+
+    fn begin_string_map(&mut self) {}
+
+    fn end_string_map(&mut self) {}
+
+    fn begin_int_map(&mut self) {}
+
+    fn int_key(&mut self, key: i32) {}
+
+    fn end_int_map(&mut self) {}
+
+    /// Followed by alternating keys and values
+    fn begin_object_map(&mut self) {}
+
+    fn end_object_map(&mut self) {}
+
+    fn begin_struct(&mut self) {}
+
+    /// Key of the next value in a struct, class or string map
+    fn field_name(&mut self, name: Cow<'a, str>) {}
+
+    fn end_struct(&mut self) {}
+
+    fn begin_class(&mut self, name: Cow<'a, str>) {}
+
+    fn end_class(&mut self) {}
+
+    fn begin_enum(&mut self, name: Cow<'a, str>, constructor: Cow<'a, str>, len: usize) {}
+
+    fn begin_enum_index(&mut self, name: Cow<'a, str>, index: usize, len: usize) {}
+
+    /// Ends both kinds of enums
+    fn end_enum(&mut self) {}
+
+    fn begin_exception(&mut self) {}
+
+    fn end_exception(&mut self) {}
+
+    /// Followed by the values its `hxSerialize` wrote
+    fn begin_custom(&mut self, name: Cow<'a, str>) {}
+
+    fn end_custom(&mut self) {}
+}
+
+/// Builds values from the callbacks, which is how every decoder makes them
+///
+/// Object references become [`Value::Ref`], decoders that copy objects
+/// instead [`push`](ValueBuilder::push) the copy.
+pub(super) struct ValueBuilder<'a, 'c> {
+    custom_codecs: &'c custom::Codecs,
+    stack: Vec<Partial<'a>>,
+    // the last value finished outside of any container
+    finished: Option<Value<'a>>,
+}
+
+impl<'a, 'c> ValueBuilder<'a, 'c> {
+    pub(super) fn new(custom_codecs: &'c custom::Codecs) -> Self {
+        Self {
+            custom_codecs,
+            stack: Vec::new(),
+            finished: None,
+        }
+    }
+
+    /// The value finished since this was last called, if it isn't inside of
+    /// a container
+    pub(super) fn finished(&mut self) -> Option<Value<'a>> {
+        self.finished.take()
+    }
+
+    pub(super) fn push(&mut self, value: Value<'a>) {
+        match self.stack.last_mut() {
+            Some(parent) => parent.push(value),
+            None => self.finished = Some(value),
+        }
+    }
+
+    fn begin(&mut self, kind: PartialKind<'a>) {
+        self.stack.push(Partial { key: None, kind });
+    }
+
+    fn key(&mut self, key: Key<'a>) {
+        self.stack
+            .last_mut()
+            .expect("keys are inside a container")
+            .key = Some(key);
+    }
+
+    /// Ends whatever container was started last
+    pub(super) fn end(&mut self) {
+        let partial = self
+            .stack
+            .pop()
+            .expect("containers are started before they end");
+        let value = partial.kind.finish(self.custom_codecs);
+        self.push(value);
+    }
+}
+
+impl<'a> Visitor<'a> for ValueBuilder<'a, '_> {
+    fn visit_null(&mut self) {
+        self.push(Value::Null);
+    }
+
+    fn visit_bool(&mut self, value: bool) {
+        self.push(Value::Bool(value));
+    }
+
+    fn visit_int(&mut self, value: i32) {
+        self.push(Value::Int(value));
+    }
+
+    fn visit_float(&mut self, value: Float) {
+        self.push(Value::Float(value));
+    }
+
+    fn visit_string(&mut self, value: Cow<'a, str>) {
+        self.push(Value::String(value));
+    }
+
+    fn string_ref(&mut self, _index: usize, value: Cow<'a, str>) {
+        self.push(Value::String(value));
+    }
+
+    fn visit_date(&mut self, value: HaxeDate) {
+        self.push(Value::Date(value));
+    }
+
+    fn visit_bytes(&mut self, value: Vec<u8>) {
+        self.push(Value::Bytes(value));
+    }
+
+    fn object_ref(&mut self, index: usize) {
+        self.push(Value::Ref(index));
+    }
+
+    fn begin_array(&mut self) {
+        self.begin(PartialKind::Array(Vec::new()));
+    }
+
+    fn null_run(&mut self, count: usize) {
+        for _ in 0..count {
+            self.push(Value::Null);
+        }
+    }
+
+    fn end_array(&mut self) {
+        self.end();
+    }
+
+    fn begin_list(&mut self) {
+        self.begin(PartialKind::List(Vec::new()));
+    }
+
+    fn end_list(&mut self) {
+        self.end();
+    }
+
+    fn begin_string_map(&mut self) {
+        self.begin(PartialKind::StringMap(Map::new()));
+    }
+
+    fn end_string_map(&mut self) {
+        self.end();
+    }
+
+    fn begin_int_map(&mut self) {
+        self.begin(PartialKind::IntMap(Map::new()));
+    }
+
+    fn int_key(&mut self, key: i32) {
+        self.key(Key::Int(key));
+    }
+
+    fn end_int_map(&mut self) {
+        self.end();
+    }
+
+    fn begin_object_map(&mut self) {
+        self.begin(PartialKind::ObjectMap(Map::new()));
+    }
+
+    fn end_object_map(&mut self) {
+        self.end();
+    }
+
+    fn begin_struct(&mut self) {
+        self.begin(PartialKind::Struct(Map::new()));
+    }
+
+    fn field_name(&mut self, name: Cow<'a, str>) {
+        self.key(Key::String(name));
+    }
+
+    fn end_struct(&mut self) {
+        self.end();
+    }
+
+    fn begin_class(&mut self, name: Cow<'a, str>) {
+        self.begin(PartialKind::Class(name, Map::new()));
+    }
+
+    fn end_class(&mut self) {
+        self.end();
+    }
+
+    // the length is only trusted as far as the fields that are there
+    fn begin_enum(&mut self, name: Cow<'a, str>, constructor: Cow<'a, str>, _len: usize) {
+        self.begin(PartialKind::Enum(name, constructor, Vec::new()));
+    }
+
+    fn begin_enum_index(&mut self, name: Cow<'a, str>, index: usize, _len: usize) {
+        self.begin(PartialKind::EnumIndex(name, index, Vec::new()));
+    }
+
+    fn end_enum(&mut self) {
+        self.end();
+    }
+
+    fn begin_exception(&mut self) {
+        self.begin(PartialKind::Exception(None));
+    }
+
+    fn end_exception(&mut self) {
+        self.end();
+    }
+
+    fn begin_custom(&mut self, name: Cow<'a, str>) {
+        self.begin(PartialKind::Custom(name, Vec::new()));
+    }
+
+    fn end_custom(&mut self) {
+        self.end();
+    }
+}
+
+enum Key<'a> {
+    String(Cow<'a, str>),
+    Int(i32),
+    Value(Value<'a>),
+}
+
+struct Partial<'a> {
+    key: Option<Key<'a>>,
+    kind: PartialKind<'a>,
+}
+
+enum PartialKind<'a> {
+    Array(Vec<Value<'a>>),
+    List(Vec<Value<'a>>),
+    StringMap(Map<Cow<'a, str>, Value<'a>>),
+    IntMap(Map<i32, Value<'a>>),
+    ObjectMap(Map<Value<'a>, Value<'a>>),
+    Struct(Map<Cow<'a, str>, Value<'a>>),
+    Class(Cow<'a, str>, Map<Cow<'a, str>, Value<'a>>),
+    Enum(Cow<'a, str>, Cow<'a, str>, Vec<Value<'a>>),
+    EnumIndex(Cow<'a, str>, usize, Vec<Value<'a>>),
+    Exception(Option<Value<'a>>),
+    Custom(Cow<'a, str>, Vec<Value<'a>>),
+}
+
+impl<'a> Partial<'a> {
+    fn push(&mut self, value: Value<'a>) {
+        match (&mut self.kind, self.key.take()) {
+            (PartialKind::ObjectMap(_), None) => self.key = Some(Key::Value(value)),
+            (PartialKind::ObjectMap(map), Some(Key::Value(key))) => {
+                map.insert(key, value);
+            }
+            (PartialKind::IntMap(map), Some(Key::Int(key))) => {
+                map.insert(key, value);
+            }
+            (
+                PartialKind::StringMap(map) | PartialKind::Struct(map) | PartialKind::Class(_, map),
+                Some(Key::String(key)),
+            ) => {
+                map.insert(key, value);
+            }
+            (
+                PartialKind::Array(values)
+                | PartialKind::List(values)
+                | PartialKind::Enum(_, _, values)
+                | PartialKind::EnumIndex(_, _, values)
+                | PartialKind::Custom(_, values),
+                None,
+            ) => values.push(value),
+            (PartialKind::Exception(inner), None) => *inner = Some(value),
+            _ => unreachable!("callbacks always come in the right order"),
+        }
+    }
+}
+
+impl<'a> PartialKind<'a> {
+    fn finish(self, custom_codecs: &custom::Codecs) -> Value<'a> {
+        match self {
+            Self::Array(values) => Value::Array(values),
+            Self::List(values) => Value::List(values),
+            Self::StringMap(map) => Value::StringMap(map),
+            Self::IntMap(map) => Value::IntMap(map),
+            Self::ObjectMap(map) => Value::ObjectMap(map),
+            Self::Struct(fields) => Value::Struct { fields },
+            Self::Class(name, fields) => Value::Class { name, fields },
+            Self::Enum(name, constructor, fields) => Value::Enum {
+                name,
+                constructor,
+                fields,
+            },
+            Self::EnumIndex(name, index, fields) => Value::EnumIndex {
+                name,
+                index,
+                fields,
+            },
+            Self::Exception(value) => {
+                Value::Exception(Box::new(value.expect("exceptions contain a value")))
+            }
+            Self::Custom(name, raw) => custom::decode(custom_codecs, name, raw),
+        }
+    }
+}
@@ -4,6 +4,7 @@ use std::{
 };
 
 use path_slash::PathExt;
+use serde::Serialize;
 
 use crate::haxe;
 
@@ -29,6 +30,14 @@ pub enum Command {
     },
 }
 
+#[derive(Serialize)]
+struct ManifestEntry {
+    path: String,
+    #[serde(rename = "type")]
+    file_type: &'static str,
+    id: String,
+}
+
 pub fn run(Cli::Manifest { command }: Cli) {
     let Command::Generate { path, output } = command;
 
@@ -47,17 +56,11 @@ pub fn run(Cli::Manifest { command }: Cli) {
                 }
             };
 
-            let slash_file_path = Cow::Owned(file_path.to_slash_lossy().into_owned());
-            let file = haxe::Value::String(slash_file_path);
-            let file_type = haxe::Value::String(file_type.into());
-
-            manifest_values.push(haxe::Value::Struct {
-                fields: [
-                    ("path".into(), file.clone()),
-                    ("type".into(), file_type),
-                    ("id".into(), file),
-                ]
-                .into(),
+            let slash_file_path = file_path.to_slash_lossy().into_owned();
+            manifest_values.push(ManifestEntry {
+                path: slash_file_path.clone(),
+                file_type,
+                id: slash_file_path,
             });
 
             Ok(())
@@ -65,7 +68,7 @@ pub fn run(Cli::Manifest { command }: Cli) {
         .unwrap();
     }
 
-    let data = haxe::to_string(&[haxe::Value::Array(manifest_values)]);
+    let data = haxe::to_string_typed(&(manifest_values,)).unwrap();
     std::env::set_current_dir(initial_working_dir).unwrap();
     std::fs::write(output, data).unwrap();
 }
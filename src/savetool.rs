@@ -33,6 +33,10 @@ pub enum Command {
         #[arg(short, long, value_enum, default_value_t = haxe::cli::FileFormat::Auto)]
         format: haxe::cli::FileFormat,
 
+        /// Write references for objects equal to ones already written
+        #[arg(long)]
+        use_cache: bool,
+
         file: PathBuf,
     },
 
@@ -43,7 +47,25 @@ pub enum Command {
         #[arg(short, long, value_enum, default_value_t = haxe::cli::FileFormat::Auto)]
         format: haxe::cli::FileFormat,
 
+        /// Keep object references instead of copying what they point to
+        #[arg(long)]
+        keep_references: bool,
+
+        file: PathBuf,
+    },
+
+    /// Print what changed between two saves
+    Diff { old: PathBuf, new: PathBuf },
+
+    /// Apply a json patch to a save, see `haxe patch`
+    #[cfg(feature = "export-json")]
+    Patch {
+        #[arg(short, long)]
+        output: PathBuf,
+
         file: PathBuf,
+
+        patch: PathBuf,
     },
 }
 
@@ -57,11 +79,12 @@ pub fn run(Cli::Savetool { command }: Cli) {
             file,
             output,
             format,
+            use_cache,
         } => {
             let format = haxe::FileFormat::guess(format, &file);
             if let haxe::FileFormat::Debug = format {
                 eprintln!("Error: a format is required when serializing");
-                return;
+                std::process::exit(1);
             }
 
             let data = std::fs::read(file).unwrap();
@@ -69,16 +92,59 @@ pub fn run(Cli::Savetool { command }: Cli) {
             let save_file: SaveFile = match format {
                 haxe::FileFormat::Debug => unreachable!(),
 
+                haxe::FileFormat::Text => match SaveFile::from_text(&data) {
+                    Ok(save_file) => save_file,
+                    Err(err) => {
+                        eprintln!("Error: {err}");
+                        std::process::exit(1);
+                    }
+                },
+
                 #[cfg(feature = "export-json")]
                 haxe::FileFormat::Json => serde_json::from_slice(&data).unwrap(),
-            };
 
-            let data = SaveFile::encode(&save_file);
+                #[cfg(feature = "export-json")]
+                haxe::FileFormat::JsonPlain => {
+                    let json = serde_json::from_slice(&data).unwrap();
+                    match SaveFile::from_plain_json(&json) {
+                        Ok(save_file) => save_file,
+                        Err(err) => {
+                            eprintln!("Error: {err}");
+                            std::process::exit(1);
+                        }
+                    }
+                }
+
+                #[cfg(feature = "export-yaml")]
+                haxe::FileFormat::Yaml => match haxe::from_yaml(&data) {
+                    Ok(save_file) => save_file,
+                    Err(err) => {
+                        eprintln!("Error: {err}");
+                        std::process::exit(1);
+                    }
+                },
 
-            let key = MM2_SAVE_KEY.try_into().unwrap();
-            let data = xxtea::encrypt_with_padding(data.into_bytes(), key)
-                .unwrap_or_else(|err| panic!("{err:?}"));
+                #[cfg(feature = "export-toml")]
+                haxe::FileFormat::Toml => match toml::from_str(std::str::from_utf8(&data).unwrap())
+                {
+                    Ok(save_file) => save_file,
+                    Err(err) => {
+                        eprintln!("Error: {err}");
+                        std::process::exit(1);
+                    }
+                },
+            };
+
+            let serializer = haxe::Serializer::new().use_cache(use_cache);
+            let data = SaveFile::encode(&save_file, &serializer);
 
+            let data = match encrypt(data) {
+                Ok(data) => data,
+                Err(err) => {
+                    eprintln!("Error: {err}");
+                    std::process::exit(1);
+                }
+            };
             std::fs::write(output, data).unwrap();
         }
 
@@ -86,14 +152,24 @@ pub fn run(Cli::Savetool { command }: Cli) {
             file,
             output: output_path,
             format,
+            keep_references,
         } => {
-            let data = std::fs::read(file).unwrap();
-
-            let key = MM2_SAVE_KEY.try_into().unwrap();
-            let data =
-                xxtea::decrypt_with_padding(data, key).unwrap_or_else(|err| panic!("{err:?}"));
+            let data = match decrypt(&file) {
+                Ok(data) => data,
+                Err(err) => {
+                    eprintln!("Error: {err}");
+                    std::process::exit(1);
+                }
+            };
 
-            let save_file = SaveFile::decode(&data);
+            let unserializer = haxe::Unserializer::new().keep_references(keep_references);
+            let save_file = match SaveFile::decode(&data, &unserializer) {
+                Ok(save_file) => save_file,
+                Err(err) => {
+                    eprintln!("Error: {err}");
+                    std::process::exit(1);
+                }
+            };
 
             let mut output = std::fs::File::create(&output_path).unwrap();
             match haxe::FileFormat::guess(format, &output_path) {
@@ -101,17 +177,131 @@ pub fn run(Cli::Savetool { command }: Cli) {
                     write!(output, "{save_file:#?}").unwrap();
                 }
 
+                haxe::FileFormat::Text => {
+                    output.write_all(save_file.to_text().as_bytes()).unwrap();
+                }
+
                 #[cfg(feature = "export-json")]
                 haxe::FileFormat::Json => {
                     output
                         .write_all(&serde_json::to_vec_pretty(&save_file).unwrap())
                         .unwrap();
                 }
+
+                #[cfg(feature = "export-json")]
+                haxe::FileFormat::JsonPlain => {
+                    let json = save_file.to_plain_json();
+                    output
+                        .write_all(&serde_json::to_vec_pretty(&json).unwrap())
+                        .unwrap();
+                }
+
+                #[cfg(feature = "export-yaml")]
+                haxe::FileFormat::Yaml => {
+                    let yaml = haxe::to_yaml(&save_file).unwrap();
+                    output.write_all(yaml.as_bytes()).unwrap();
+                }
+
+                #[cfg(feature = "export-toml")]
+                haxe::FileFormat::Toml => {
+                    let toml = haxe::to_toml_table(&save_file).unwrap();
+                    output.write_all(toml.as_bytes()).unwrap();
+                }
+            };
+        }
+
+        Command::Diff { old, new } => {
+            let (old_data, new_data) = match decrypt(&old).and_then(|old| Ok((old, decrypt(&new)?)))
+            {
+                Ok(data) => data,
+                Err(err) => {
+                    eprintln!("Error: {err}");
+                    std::process::exit(1);
+                }
+            };
+            let unserializer = haxe::Unserializer::new();
+            let saves = SaveFile::decode(&old_data, &unserializer)
+                .and_then(|old| Ok((old, SaveFile::decode(&new_data, &unserializer)?)));
+            let (old, new) = match saves {
+                Ok(saves) => saves,
+                Err(err) => {
+                    eprintln!("Error: {err}");
+                    std::process::exit(1);
+                }
             };
+
+            if old.version != new.version {
+                println!("~ version: {} -> {}", old.version, new.version);
+            }
+            // top level values are indexed like an array, as in queries
+            let (old_values, new_values) = (
+                haxe::Value::Array(old.values),
+                haxe::Value::Array(new.values),
+            );
+            let diff = haxe::diff(&old_values, &new_values);
+            print!("{diff}");
+            if diff.is_same() && old.version == new.version {
+                println!("No changes");
+            }
+        }
+
+        #[cfg(feature = "export-json")]
+        Command::Patch {
+            file,
+            patch,
+            output,
+        } => {
+            // a patch that didn't apply fails the command, so scripts notice
+            let Some(patch) = haxe::read_patch(&patch) else {
+                std::process::exit(1);
+            };
+            let data = match decrypt(&file) {
+                Ok(data) => data,
+                Err(err) => {
+                    eprintln!("Error: {err}");
+                    std::process::exit(1);
+                }
+            };
+            let decoded = SaveFile::split_version(&data).and_then(|(version, input)| {
+                let decoded = haxe::Unserializer::new().decode_lossless(input)?;
+                Ok((version, decoded))
+            });
+            let (version, (mut values, spelling)) = match decoded {
+                Ok(decoded) => decoded,
+                Err(err) => {
+                    eprintln!("Error: {err}");
+                    std::process::exit(1);
+                }
+            };
+            if let Err(err) = patch.apply_to_all(&mut values) {
+                eprintln!("Error: {err}");
+                std::process::exit(1);
+            }
+
+            let hxon = haxe::Serializer::new().encode_lossless(&values, &spelling);
+            let data = match encrypt(format!("[{version}]{hxon}")) {
+                Ok(data) => data,
+                Err(err) => {
+                    eprintln!("Error: {err}");
+                    std::process::exit(1);
+                }
+            };
+            std::fs::write(output, data).unwrap();
         }
     }
 }
 
+fn encrypt(data: String) -> Result<Vec<u8>, xxtea::CryptPadError> {
+    let key = MM2_SAVE_KEY.try_into().unwrap();
+    xxtea::encrypt_with_padding(data.into_bytes(), key)
+}
+
+fn decrypt(file: &std::path::Path) -> Result<Vec<u8>, xxtea::CryptPadError> {
+    let data = std::fs::read(file).unwrap();
+    let key = MM2_SAVE_KEY.try_into().unwrap();
+    xxtea::decrypt_with_padding(data, key)
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 struct SaveFile<'a> {
     version: Cow<'a, str>,
@@ -119,23 +309,93 @@ struct SaveFile<'a> {
 }
 
 impl<'a> SaveFile<'a> {
-    fn encode(save_file: &Self) -> String {
+    fn encode(save_file: &Self, serializer: &haxe::Serializer) -> String {
         format!(
             "[{version}]{hxon}",
             version = save_file.version,
-            hxon = haxe::to_string(&save_file.values),
+            hxon = serializer.encode(&save_file.values),
         )
     }
 
-    fn decode(data: &'a [u8]) -> Self {
-        let input = &mut std::str::from_utf8(data).unwrap();
+    fn decode(
+        data: &'a [u8],
+        unserializer: &haxe::Unserializer,
+    ) -> Result<Self, haxe::DecodeError> {
+        let (version, input) = Self::split_version(data)?;
+        Ok(Self {
+            version: version.into(),
+            values: unserializer.decode(input)?,
+        })
+    }
+
+    // the version tag and the serialized values after it
+    fn split_version(data: &[u8]) -> Result<(String, &str), haxe::DecodeError> {
+        let input = match std::str::from_utf8(data) {
+            Ok(input) => input,
+            Err(err) => {
+                let valid = std::str::from_utf8(&data[..err.valid_up_to()]).unwrap();
+                let err = haxe::DecodeError::InvalidUtf8 {
+                    offset: err.valid_up_to(),
+                    snippet: String::new(),
+                };
+                return Err(err.with_snippet(valid));
+            }
+        };
 
-        Self {
-            version: Self::parse_version_tag(input).unwrap().into(),
-            values: haxe::from_str(input).unwrap(),
+        let mut rest = input;
+        match Self::parse_version_tag(&mut rest) {
+            Ok(version) => Ok((version, rest)),
+            Err(_) => {
+                let err = haxe::DecodeError::Syntax {
+                    offset: 0,
+                    snippet: String::new(),
+                };
+                Err(err.with_snippet(input))
+            }
         }
     }
 
+    // the version tag on its own line, then the values
+    fn to_text(&self) -> String {
+        format!("[{}]\n{}", self.version, haxe::to_text(&self.values))
+    }
+
+    fn from_text(data: &'a [u8]) -> Result<Self, Cow<'static, str>> {
+        let Ok(input) = std::str::from_utf8(data) else {
+            return Err("text isn't valid utf-8".into());
+        };
+        let input = &mut &*input;
+        let Ok(version) = Self::parse_version_tag(input) else {
+            return Err("expected a `[version]` tag at the start".into());
+        };
+
+        Ok(Self {
+            version: version.into(),
+            values: haxe::from_text(input).map_err(|err| err.to_string())?,
+        })
+    }
+
+    // shaped like the tagged json, with the values as plain json
+    #[cfg(feature = "export-json")]
+    fn to_plain_json(&self) -> serde_json::Value {
+        let values: Vec<_> = self.values.iter().map(haxe::to_plain_json).collect();
+        serde_json::json!({ "version": self.version, "values": values })
+    }
+
+    #[cfg(feature = "export-json")]
+    fn from_plain_json(json: &serde_json::Value) -> Result<Self, Cow<'static, str>> {
+        let Some(version) = json.get("version").and_then(|version| version.as_str()) else {
+            return Err("expected a version string".into());
+        };
+        let values = json.get("values").unwrap_or(&serde_json::Value::Null);
+        let values = haxe::values_from_plain_json(values).map_err(|err| err.to_string())?;
+
+        Ok(Self {
+            version: version.to_owned().into(),
+            values,
+        })
+    }
+
     fn parse_version_tag(input: &mut &str) -> PResult<String> {
         let version = repeat(1.., alt((one_of('0'..='9'), '.')));
         delimited('[', version, ']').parse_next(input)
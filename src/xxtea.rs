@@ -6,10 +6,12 @@ pub enum TeaMode {
     Decrypt,
 }
 
+#[allow(clippy::result_unit_err)]
 pub fn decrypt(data: &mut [u32], key: &[u8; 16]) -> Result<(), ()> {
     crypt(TeaMode::Decrypt, data, key)
 }
 
+#[allow(clippy::result_unit_err)]
 pub fn encrypt(data: &mut [u32], key: &[u8; 16]) -> Result<(), ()> {
     crypt(TeaMode::Encrypt, data, key)
 }
@@ -17,7 +19,7 @@ pub fn encrypt(data: &mut [u32], key: &[u8; 16]) -> Result<(), ()> {
 const TEA_DELTA: Wrapping<u32> = Wrapping(0x9e_37_79_b9);
 
 // From the improved version of the reference code in https://w.wiki/AU4y
-#[allow(clippy::many_single_char_names)]
+#[allow(clippy::many_single_char_names, clippy::result_unit_err)]
 pub fn crypt(mode: TeaMode, data: &mut [u32], key: &[u8; 16]) -> Result<(), ()> {
     if data.len() < 2 {
         return Err(());
@@ -81,12 +83,15 @@ pub enum CryptPadError {
 
     #[error(transparent)]
     InvalidUtf8Data(#[from] std::str::Utf8Error),
+
+    #[error("data is shorter than the 8 bytes xxtea works on")]
+    TooShort,
 }
 
 pub fn decrypt_with_padding(mut data: Vec<u8>, key: &[u8; 16]) -> Result<Vec<u8>, CryptPadError> {
     {
         let data = bytemuck::try_cast_slice_mut(&mut data)?;
-        decrypt(data, key).unwrap();
+        decrypt(data, key).map_err(|()| CryptPadError::TooShort)?;
     }
 
     // pop at most 4 nul padding bytes from the end
@@ -106,7 +111,7 @@ pub fn encrypt_with_padding(mut data: Vec<u8>, key: &[u8; 16]) -> Result<Vec<u8>
     {
         data.resize(data.len().next_multiple_of(4), 0);
         let data = bytemuck::try_cast_slice_mut(&mut data)?;
-        encrypt(data, key).unwrap();
+        encrypt(data, key).map_err(|()| CryptPadError::TooShort)?;
     }
 
     Ok(data)